@@ -0,0 +1,58 @@
+//! Benchmarks of parsing responses and WebSocket messages.
+//!
+//! Compare the JSON backends by saving a baseline without the `simd-json` feature and comparing against it:
+//! ```sh
+//! cargo bench --bench json -- --save-baseline serde_json
+//! cargo bench --bench json --features simd-json -- --baseline serde_json
+//! ```
+//! The `serde_json` benchmarks parse without the handlers, so they don't depend on the feature.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use crypto_botters::{
+    binance::BinanceOption,
+    bybit::BybitOption,
+    generic_api_client::{
+        http::{Bytes, HeaderMap, RequestHandler, StatusCode},
+        websocket::{WebSocketHandler, WebSocketMessage},
+    },
+    traits::*,
+};
+
+/// A diff depth message of a combined Binance stream, with 100 levels on each side.
+const BINANCE_DEPTH: &str = include_str!("data/binance_depth.json");
+/// A Bybit `orderbook.50` snapshot.
+const BYBIT_ORDERBOOK_50: &str = include_str!("data/bybit_orderbook_50.json");
+
+fn parse<O>(c: &mut Criterion, name: &str, message: &'static str)
+where
+    O: WebSocketOption<fn(serde_json::Value)> + HttpOption<'static, serde_json::Value, ()>,
+{
+    let mut group = c.benchmark_group(name);
+    group.bench_function("serde_json", |b| b.iter(|| serde_json::from_str::<serde_json::Value>(black_box(message)).unwrap()));
+
+    let message_handler: fn(serde_json::Value) = |message| { black_box(message); };
+    let mut handler = O::websocket_handler(message_handler, O::Options::default());
+    group.bench_function("handle_message", |b| b.iter_batched(
+        || WebSocketMessage::Text(message.to_owned()),
+        |message| handler.handle_message(message),
+        BatchSize::SmallInput,
+    ));
+
+    let request_handler = <O as HttpOption<serde_json::Value, ()>>::request_handler(O::Options::default());
+    let body = Bytes::from_static(message.as_bytes());
+    let handle_response = || RequestHandler::<()>::handle_response(&request_handler, StatusCode::OK, HeaderMap::new(), body.clone());
+    assert!(handle_response().is_ok());
+    group.bench_function("handle_response", |b| b.iter(handle_response));
+    group.finish();
+}
+
+fn binance(c: &mut Criterion) {
+    parse::<BinanceOption>(c, "binance_depth", BINANCE_DEPTH);
+}
+
+fn bybit(c: &mut Criterion) {
+    parse::<BybitOption>(c, "bybit_orderbook_50", BYBIT_ORDERBOOK_50);
+}
+
+criterion_group!(benches, binance, bybit);
+criterion_main!(benches);
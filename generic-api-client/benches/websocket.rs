@@ -0,0 +1,140 @@
+//! Benchmarks of the end-to-end latency of [WebSocketConnection], from a local server sending messages
+//! until the [WebSocketHandler] receives them.
+//!
+//! ```sh
+//! cargo bench -p generic-api-client --bench websocket
+//! ```
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use futures_util::{SinkExt, StreamExt};
+use generic_api_client::websocket::*;
+use tokio::{
+    net::TcpListener,
+    runtime::Runtime,
+    sync::{mpsc, Notify},
+};
+use tokio_tungstenite::tungstenite::Message;
+
+/// A trade message of a typical size.
+const TRADE: &str = r#"{"e":"trade","E":1672515782136,"s":"BNBBTC","t":12345,"p":"0.001","q":"100","T":1672515782136,"m":true,"M":true}"#;
+
+struct CountingHandler {
+    received: Arc<AtomicUsize>,
+    target: Arc<AtomicUsize>,
+    notify: Arc<Notify>,
+    url_prefix: String,
+    reply: bool,
+}
+
+impl WebSocketHandler for CountingHandler {
+    fn websocket_config(&self) -> WebSocketConfig {
+        let mut config = WebSocketConfig::new();
+        config.url_prefix = self.url_prefix.clone();
+        config
+    }
+
+    fn handle_message(&mut self, _: WebSocketMessage) -> Vec<WebSocketMessage> {
+        let received = self.received.fetch_add(1, Ordering::Relaxed) + 1;
+        if received == self.target.load(Ordering::Relaxed) {
+            self.notify.notify_one();
+        }
+        if self.reply {
+            vec![WebSocketMessage::Pong(vec![])]
+        } else {
+            vec![]
+        }
+    }
+}
+
+/// A connection to a local server which sends [TRADE] the requested number of times.
+struct Feed {
+    connection: WebSocketConnection<CountingHandler>,
+    command_tx: mpsc::UnboundedSender<usize>,
+    received: Arc<AtomicUsize>,
+    target: Arc<AtomicUsize>,
+    notify: Arc<Notify>,
+}
+
+impl Feed {
+    async fn start(reply: bool) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url_prefix = format!("ws://{}", listener.local_addr().unwrap());
+        let (command_tx, mut command_rx) = mpsc::unbounded_channel::<usize>();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (mut sink, mut stream) = tokio_tungstenite::accept_async(stream).await.unwrap().split();
+            // replies are discarded, but they need to be read so that the client isn't blocked
+            tokio::spawn(async move { while let Some(Ok(_)) = stream.next().await {} });
+            while let Some(count) = command_rx.recv().await {
+                for _ in 0..count {
+                    sink.feed(Message::Text(TRADE.to_owned())).await.unwrap();
+                }
+                sink.flush().await.unwrap();
+            }
+        });
+
+        let received = Arc::new(AtomicUsize::new(0));
+        let target = Arc::new(AtomicUsize::new(0));
+        let notify = Arc::new(Notify::new());
+        let handler = CountingHandler {
+            received: Arc::clone(&received),
+            target: Arc::clone(&target),
+            notify: Arc::clone(&notify),
+            url_prefix,
+            reply,
+        };
+        let connection = WebSocketConnection::new("", handler).await.unwrap();
+        Self { connection, command_tx, received, target, notify }
+    }
+
+    /// Makes the server send `count` messages at once, and waits until all of them are handled.
+    async fn receive(&self, count: usize) -> Duration {
+        let start = Instant::now();
+        self.target.store(self.received.load(Ordering::Relaxed) + count, Ordering::Relaxed);
+        self.command_tx.send(count).unwrap();
+        self.notify.notified().await;
+        start.elapsed()
+    }
+}
+
+fn websocket(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("websocket");
+
+    for reply in [false, true] {
+        let feed = runtime.block_on(Feed::start(reply));
+        let name = if reply { "reply" } else { "no_reply" };
+
+        group.throughput(Throughput::Elements(1));
+        group.bench_function(BenchmarkId::new("latency", name), |b| b.iter_custom(|iters| runtime.block_on(async {
+            let mut elapsed = Duration::ZERO;
+            for _ in 0..iters {
+                elapsed += feed.receive(1).await;
+            }
+            elapsed
+        })));
+
+        const BURST: usize = 1000;
+        group.throughput(Throughput::Elements(BURST as u64));
+        group.bench_function(BenchmarkId::new("burst", name), |b| b.iter_custom(|iters| runtime.block_on(async {
+            let mut elapsed = Duration::ZERO;
+            for _ in 0..iters {
+                elapsed += feed.receive(BURST).await;
+            }
+            elapsed
+        })));
+
+        runtime.block_on(async { drop(feed.connection) });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, websocket);
+criterion_main!(benches);
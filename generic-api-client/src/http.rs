@@ -1,8 +1,14 @@
-use std::time::Duration;
+use std::{
+    collections::hash_map::RandomState,
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    time::{Duration, Instant, SystemTime},
+};
 use serde::Serialize;
 use thiserror::Error;
-pub use reqwest::{Request, RequestBuilder, StatusCode, Method, header::{self, HeaderMap}};
+pub use reqwest::{Request, RequestBuilder, StatusCode, Method, Url, header::{self, HeaderMap}};
 pub use bytes::Bytes;
+use crate::rate_limit::{RateLimit, RateLimiter};
 
 /// The User Agent string
 pub static USER_AGENT: &str = concat!("generic-api-client/", env!("CARGO_PKG_VERSION"));
@@ -11,9 +17,12 @@ pub static USER_AGENT: &str = concat!("generic-api-client/", env!("CARGO_PKG_VER
 ///
 /// When making a HTTP request or starting a websocket connection with this client,
 /// a handler that implements [RequestHandler] is required.
+///
+/// Clones of a `Client` share the same connection pool and [RateLimiter].
 #[derive(Debug, Clone)]
 pub struct Client {
     client: reqwest::Client,
+    rate_limiter: RateLimiter,
 }
 
 impl Client {
@@ -23,6 +32,14 @@ impl Client {
         Self::default()
     }
 
+    /// Returns the [RateLimiter] used by this `Client`.
+    ///
+    /// See also [RequestHandler::rate_limits()].
+    #[inline(always)]
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
+    }
+
     /// Makes an HTTP request with the given [RequestHandler] and returns the response.
     ///
     /// It is recommended to use methods like [get()][Self::get()] because this method takes many type parameters and parameters.
@@ -32,41 +49,133 @@ impl Client {
     pub async fn request<Q, B, H>(
         &self, method: Method, url: &str, query: Option<&Q>, body: Option<B>, handler: &H,
     ) -> Result<H::Successful, RequestError<H::BuildError, H::Unsuccessful>>
+    where
+        Q: Serialize + ?Sized,
+        H: RequestHandler<B>,
+    {
+        let response = self.send(method, url, query, body, handler).await?;
+        handler.handle_response(response.status, response.headers, response.body).map_err(RequestError::ResponseHandleError)
+    }
+
+    /// Same as [request()][Self::request()], but returns the value returned by [RequestHandler::handle_response()]
+    /// together with the [ResponseMetadata] of the response.
+    pub async fn request_with_metadata<Q, B, H>(
+        &self, method: Method, url: &str, query: Option<&Q>, body: Option<B>, handler: &H,
+    ) -> Result<Response<H::Successful>, RequestError<H::BuildError, H::Unsuccessful>>
+    where
+        Q: Serialize + ?Sized,
+        H: RequestHandler<B>,
+    {
+        let response = self.send(method, url, query, body, handler).await?;
+        let metadata = ResponseMetadata {
+            status: response.status,
+            headers: response.headers.clone(),
+            sent_at: response.sent_at,
+            received_at: response.sent_at + response.latency,
+            latency: response.latency,
+            attempt_count: response.attempt_count,
+        };
+        let body = handler.handle_response(response.status, response.headers, response.body).map_err(RequestError::ResponseHandleError)?;
+        Ok(Response { body, metadata })
+    }
+
+    /// Sends the request, retrying it as decided by `handler`, and returns the response which should be passed to
+    /// [RequestHandler::handle_response()].
+    async fn send<Q, B, H>(
+        &self, method: Method, url: &str, query: Option<&Q>, body: Option<B>, handler: &H,
+    ) -> Result<ReceivedResponse, RequestError<H::BuildError, H::Unsuccessful>>
     where
         Q: Serialize + ?Sized,
         H: RequestHandler<B>,
     {
         let config = handler.request_config();
         config.verify();
-        let url = config.url_prefix + url;
+        let url = config.url_prefix.clone() + url;
+        let started = Instant::now();
         let mut count = 1;
         loop {
             // create RequestBuilder
-            let mut request_builder = self.client.request(method.clone(), url.clone())
-                .timeout(config.timeout);
-            if let Some(query) = query {
-                request_builder = request_builder.query(query);
+            let request_builder = || {
+                let mut request_builder = self.client.request(method.clone(), url.clone())
+                    .timeout(config.timeout);
+                if let Some(query) = query {
+                    request_builder = request_builder.query(query);
+                }
+                request_builder
+            };
+            let mut request = handler.build_request_async(request_builder(), &body, count).await.map_err(RequestError::BuildRequestError)?;
+            let limits = handler.rate_limits(&request);
+            if !limits.is_empty() {
+                let waited = self.rate_limiter.acquire(&limits, config.max_rate_limit_wait).await
+                    .map_err(|wait| RequestError::RateLimitExceeded { wait })?;
+                if waited {
+                    // the request might contain a timestamp which is now outdated, so we build it again
+                    request = handler.build_request_async(request_builder(), &body, count).await.map_err(RequestError::BuildRequestError)?;
+                }
             }
-            let request = handler.build_request(request_builder, &body, count).map_err(RequestError::BuildRequestError)?;
             // send the request
-            match self.client.execute(request).await {
+            let request_url = request.url().clone();
+            let sent_at = SystemTime::now();
+            let sent = Instant::now();
+            let cooldown = match self.client.execute(request).await {
                 Ok(mut response) => {
                     let status = response.status();
                     let headers = std::mem::take(response.headers_mut());
-                    let body = response.bytes().await.map_err(RequestError::ReceiveResponse)?;
-                    return handler.handle_response(status, headers, body).map_err(RequestError::ResponseHandleError);
+                    for (key, used) in handler.reported_rate_limit_usage(&request_url, status, &headers) {
+                        self.rate_limiter.set_used(&key, used);
+                    }
+                    match response.bytes().await {
+                        Ok(body) => {
+                            let cooldown = match handler.retry_decision(&method, status, &headers, &body) {
+                                RetryDecision::DoNotRetry => None,
+                                RetryDecision::Retry => config.retry_cooldown(count, started.elapsed(), None),
+                                RetryDecision::RetryAfter(duration) => config.retry_cooldown(count, started.elapsed(), Some(duration)),
+                            };
+                            match cooldown {
+                                Some(cooldown) => {
+                                    log::debug!("Retrying request because of the response, status: {}, count: {}", status, count);
+                                    cooldown
+                                },
+                                None => return Ok(ReceivedResponse {
+                                    status,
+                                    headers,
+                                    body,
+                                    sent_at,
+                                    latency: sent.elapsed(),
+                                    attempt_count: count,
+                                }),
+                            }
+                        },
+                        Err(error) => {
+                            // the server might have processed the request, so we only retry idempotent requests
+                            let cooldown = if error.is_timeout() && is_idempotent(&method) {
+                                config.retry_cooldown(count, started.elapsed(), None)
+                            } else {
+                                None
+                            };
+                            match cooldown {
+                                Some(cooldown) => {
+                                    log::warn!("Retrying request because of a timeout while receiving the response, count: {}", count);
+                                    cooldown
+                                },
+                                None => return Err(RequestError::ReceiveResponse(error)),
+                            }
+                        },
+                    }
                 },
                 Err(error) => {
-                    if count >= config.max_try {
-                        // max retry count
-                        return Err(RequestError::SendRequest(error));
+                    match config.retry_cooldown(count, started.elapsed(), None) {
+                        Some(cooldown) => {
+                            log::warn!("Retrying sending reqeust, count: {}", count);
+                            cooldown
+                        },
+                        // max retry count or max elapsed time
+                        None => return Err(RequestError::SendRequest(error)),
                     }
-                    log::warn!("Retrying sending reqeust, count: {}", count);
-                    // else, continue
-                    count += 1;
-                    tokio::time::sleep(config.retry_cooldown).await;
                 },
-            }
+            };
+            count += 1;
+            tokio::time::sleep(cooldown).await;
         }
     }
 
@@ -191,10 +300,57 @@ impl Default for Client {
             .unwrap(); // user agent should be valid
         Self {
             client,
+            rate_limiter: RateLimiter::new(),
         }
     }
 }
 
+/// The response which was received for the last attempt of a request.
+struct ReceivedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    sent_at: SystemTime,
+    latency: Duration,
+    attempt_count: u8,
+}
+
+/// A value returned by [Client::request_with_metadata()].
+#[derive(Debug, Clone)]
+pub struct Response<T> {
+    /// The value returned by [RequestHandler::handle_response()].
+    pub body: T,
+    /// Information about the response.
+    pub metadata: ResponseMetadata,
+}
+
+/// Information about a response returned by [Client::request_with_metadata()].
+///
+/// When the request was retried, this describes the last attempt.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ResponseMetadata {
+    /// The status of the response.
+    pub status: StatusCode,
+    /// The headers of the response, such as `Date`, rate limit usages and request ids.
+    pub headers: HeaderMap,
+    /// When the request was sent.
+    pub sent_at: SystemTime,
+    /// When the body of the response was received.
+    pub received_at: SystemTime,
+    /// The time between sending the request and receiving the body of the response.
+    pub latency: Duration,
+    /// The number of times the request was sent. This is `1` if the request was not retried.
+    pub attempt_count: u8,
+}
+
+impl ResponseMetadata {
+    /// Returns the value of the header `name`, if it exists and only contains visible ASCII characters.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)?.to_str().ok()
+    }
+}
+
 /// A `trait` which is used to process requests and responses for the [Client].
 pub trait RequestHandler<B> {
     /// The type which is returned to the caller of [Client::request()] when the response was successful.
@@ -209,12 +365,57 @@ pub trait RequestHandler<B> {
         RequestConfig::default()
     }
 
+    /// Decides whether the request should be sent again after a response was received.
+    ///
+    /// This is called for every response before [handle_response()][Self::handle_response()].
+    /// If this returns [RetryDecision::DoNotRetry], or if retrying is not allowed by [RequestConfig] (see
+    /// [RequestConfig::max_try] and [RequestConfig::retry_max_elapsed]), the response is passed to
+    /// [handle_response()][Self::handle_response()]. Otherwise, the response is discarded and the request is
+    /// built (with an incremented `attempt_count`) and sent again.
+    ///
+    /// The default implementation is [default_retry_decision()].
+    #[allow(unused_variables)]
+    fn retry_decision(&self, method: &Method, status: StatusCode, headers: &HeaderMap, response_body: &Bytes) -> RetryDecision {
+        default_retry_decision(method, status, headers)
+    }
+
+    /// Returns the rate limits which apply to `request`.
+    ///
+    /// [Client] waits until the [RateLimiter] allows the request before sending it. If it had to wait,
+    /// the request is built again with [build_request()][Self::build_request()] so that timestamps are not outdated.
+    ///
+    /// The default implementation returns no rate limits.
+    #[allow(unused_variables)]
+    fn rate_limits(&self, request: &Request) -> Vec<RateLimit> {
+        vec![]
+    }
+
+    /// Returns the usage of rate limits reported by the server in the response headers,
+    /// as pairs of a [RateLimit::key] and the number of consumed tokens. `url` is the url the request was sent to.
+    ///
+    /// [Client] overwrites the usage of the corresponding buckets in its [RateLimiter] (see [RateLimiter::set_used()]).
+    /// The default implementation returns nothing.
+    #[allow(unused_variables)]
+    fn reported_rate_limit_usage(&self, url: &Url, status: StatusCode, headers: &HeaderMap) -> Vec<(String, u32)> {
+        vec![]
+    }
+
     /// Build a HTTP request to be sent.
     ///
     /// Implementors have to decide how to include the `request_body` into the `builder`. Implementors can
     /// also perform other operations (such as authorization) on the request.
     fn build_request(&self, builder: RequestBuilder, request_body: &Option<B>, attempt_count: u8) -> Result<Request, Self::BuildError>;
 
+    /// Build a HTTP request to be sent, waiting for asynchronous work such as signing in another process.
+    ///
+    /// [Client] calls this instead of [build_request()][Self::build_request()], so that handlers can build requests
+    /// without blocking the runtime. The default implementation calls [build_request()][Self::build_request()].
+    fn build_request_async(&self, builder: RequestBuilder, request_body: &Option<B>, attempt_count: u8)
+        -> impl Future<Output = Result<Request, Self::BuildError>>
+    {
+        std::future::ready(self.build_request(builder, request_body, attempt_count))
+    }
+
     /// Handle a HTTP response before it is returned to the caller of [Client::request()].
     ///
     /// You can verify, parse, etc... the response here before it is returned to the caller.
@@ -243,19 +444,39 @@ pub trait RequestHandler<B> {
 #[derive(Debug, Clone)]
 #[non_exhaustive]
 pub struct RequestConfig {
-    /// [Client] will retry sending a request if it failed to send. `max_try` can be used limit the number of attempts.
+    /// [Client] will retry sending a request if it failed to send, if a timeout occurred while receiving the response
+    /// of an idempotent request, or if [RequestHandler::retry_decision()] decided to retry. `max_try` can be used limit the number of attempts.
     ///
     /// Do not set this to `0` or [Client::request()] will **panic**. [Default]s to `1` (which means no retry).
     pub max_try: u8,
-    /// Duration that should elapse after retrying sending a request.
+    /// Duration that should elapse before the first retry.
     ///
-    /// [Default]s to 500ms. See also: `max_try`.
+    /// [Default]s to 500ms. See also: `max_try`, `retry_backoff`.
     pub retry_cooldown: Duration,
+    /// The cooldown is multiplied by this value on each retry (exponential backoff).
+    ///
+    /// Must be greater than or equal to `1.0`. [Default]s to `2.0`.
+    pub retry_backoff: f64,
+    /// The maximum cooldown between retries. [Default]s to 10s.
+    ///
+    /// If the server requests a longer cooldown with [RetryDecision::RetryAfter], the request is not retried and
+    /// the response is passed to [RequestHandler::handle_response()], so that a long ban doesn't block the caller.
+    pub retry_max_cooldown: Duration,
+    /// The cooldown is randomly reduced by up to this ratio, so that multiple clients don't retry at the same time.
+    ///
+    /// Must be between `0.0` and `1.0`. [Default]s to `0.5`.
+    pub retry_jitter: f64,
+    /// [Client] will not retry if the next attempt would start after this amount of time has elapsed since the first attempt.
+    /// [Default]s to [Duration::ZERO], which means no limit.
+    pub retry_max_elapsed: Duration,
     /// The timeout set when sending a request. [Default]s to 3s.
     ///
     /// It is possible for the [RequestHandler] to override this in [RequestHandler::build_request()].
     /// See also: [RequestBuilder::timeout()].
     pub timeout: Duration,
+    /// If the [RateLimiter] requires waiting longer than this before sending a request, [Client::request()] returns
+    /// [RequestError::RateLimitExceeded] instead of waiting. [Default]s to `None`, which means no limit.
+    pub max_rate_limit_wait: Option<Duration>,
     /// The prefix which will be used for requests sent using this configuration. [Default]s to `""`.
     ///
     /// Example usage: `"https://example.com"`
@@ -272,6 +493,29 @@ impl RequestConfig {
     #[inline(always)]
     fn verify(&self) {
         assert_ne!(self.max_try, 0, "RequestConfig.max_try must not be equal to 0");
+        assert!(self.retry_backoff >= 1.0, "RequestConfig.retry_backoff must be greater than or equal to 1.0");
+        assert!((0.0..=1.0).contains(&self.retry_jitter), "RequestConfig.retry_jitter must be between 0.0 and 1.0");
+    }
+
+    /// Returns the cooldown before the next attempt, or `None` if the request should not be retried.
+    fn retry_cooldown(&self, count: u8, elapsed: Duration, retry_after: Option<Duration>) -> Option<Duration> {
+        if count >= self.max_try {
+            return None;
+        }
+        if retry_after.is_some_and(|retry_after| retry_after > self.retry_max_cooldown) {
+            return None;
+        }
+        let cooldown = retry_after.unwrap_or_else(|| {
+            let backoff = self.retry_backoff.powi(i32::from(count) - 1);
+            let cooldown = (self.retry_cooldown.as_secs_f64() * backoff).min(self.retry_max_cooldown.as_secs_f64());
+            // a random number in [0, 1)
+            let random = (RandomState::new().build_hasher().finish() >> 11) as f64 / (1u64 << 53) as f64;
+            Duration::from_secs_f64(cooldown * (1.0 - self.retry_jitter * random))
+        });
+        if !self.retry_max_elapsed.is_zero() && elapsed.checked_add(cooldown).is_none_or(|end| end > self.retry_max_elapsed) {
+            return None;
+        }
+        Some(cooldown)
     }
 }
 
@@ -280,12 +524,67 @@ impl Default for RequestConfig {
         Self {
             max_try: 1,
             retry_cooldown: Duration::from_millis(500),
+            retry_backoff: 2.0,
+            retry_max_cooldown: Duration::from_secs(10),
+            retry_jitter: 0.5,
+            retry_max_elapsed: Duration::ZERO,
             timeout: Duration::from_secs(3),
+            max_rate_limit_wait: None,
             url_prefix: String::new(),
         }
     }
 }
 
+/// The decision returned by [RequestHandler::retry_decision()].
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum RetryDecision {
+    /// Pass the response to [RequestHandler::handle_response()].
+    DoNotRetry,
+    /// Retry after a cooldown calculated from the [RequestConfig].
+    Retry,
+    /// Retry after the given amount of time, for example the one specified in the `Retry-After` header.
+    RetryAfter(Duration),
+}
+
+/// The default implementation of [RequestHandler::retry_decision()].
+///
+/// - `429 Too Many Requests` is retried, respecting the `Retry-After` header if it exists.
+/// - `503 Service Unavailable` with a `Retry-After` header is retried after the specified time.
+/// - Other `5xx` responses are retried only if the method is idempotent, because the server might have processed the request.
+pub fn default_retry_decision(method: &Method, status: StatusCode, headers: &HeaderMap) -> RetryDecision {
+    let retry_after = parse_retry_after(headers);
+    if status == StatusCode::TOO_MANY_REQUESTS || (status == StatusCode::SERVICE_UNAVAILABLE && retry_after.is_some()) {
+        retry_after.map_or(RetryDecision::Retry, RetryDecision::RetryAfter)
+    } else if status.is_server_error() && is_idempotent(method) {
+        RetryDecision::Retry
+    } else {
+        RetryDecision::DoNotRetry
+    }
+}
+
+/// Parses the `Retry-After` header, if it exists and is in seconds or an HTTP-date.
+///
+/// An HTTP-date in the past is returned as [Duration::ZERO].
+pub fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(header::RETRY_AFTER)?;
+    let string = value.to_str().ok().map(str::trim);
+    if let Some(seconds) = string.and_then(|string| string.parse().ok()) {
+        return Some(Duration::from_secs(seconds));
+    }
+    match string.and_then(|string| httpdate::parse_http_date(string).ok()) {
+        Some(date) => Some(date.duration_since(SystemTime::now()).unwrap_or(Duration::ZERO)),
+        None => {
+            log::debug!("Unsupported value in Retry-After header: {:?}", value);
+            None
+        },
+    }
+}
+
+#[inline]
+fn is_idempotent(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS | Method::TRACE)
+}
+
 /// An `enum` that represents errors that could be returned by [Client::request()]
 ///
 /// Type parameter `R` is [RequestHandler::Unsuccessful].
@@ -297,6 +596,12 @@ pub enum RequestError<E, R> {
     /// An error which occurred while receiving a HTTP response.
     #[error("failed to receive response")]
     ReceiveResponse(#[source] reqwest::Error),
+    /// The request was not sent because the [RateLimiter] required waiting longer than [RequestConfig::max_rate_limit_wait].
+    #[error("rate limit exceeded, the request could be sent after {wait:?}")]
+    RateLimitExceeded {
+        /// The time the request had to wait.
+        wait: Duration,
+    },
     /// Error occurred in [RequestHandler::build_request()].
     #[error("the handler failed to build a request")]
     BuildRequestError(E),
@@ -20,6 +20,7 @@
 //! - [WebSocketConnection][websocket::WebSocketConnection] A `struct` to manage WebSocket connections
 //! - [RequestHandler][http::RequestHandler] A `trait` for implementing features like authentication on your requests
 //! - [WebSocketHandler][websocket::WebSocketHandler] A `trait` that is used to handle messages etc.. for a WebSocket Connection.
+//! - [RateLimiter][rate_limit::RateLimiter] A `struct` that throttles requests sent by [Client][http::Client]
 //!
 //! For a more detailed documentation, see the links above.
 
@@ -27,3 +28,5 @@
 pub mod http;
 /// Module for interacting with WebSocket APIs.
 pub mod websocket;
+/// Module for client-side rate limiting.
+pub mod rate_limit;
@@ -0,0 +1,179 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use parking_lot::Mutex as SyncMutex;
+
+/// A `struct` that represents a token bucket and the number of tokens a request consumes from it.
+///
+/// Returned by [RequestHandler::rate_limits()][crate::http::RequestHandler::rate_limits()].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RateLimit {
+    /// The name of the bucket. Requests that return the same `key` share the bucket.
+    ///
+    /// Example usage: `"example.com:ip"`, `"example.com:account:1234:/orders"`
+    pub key: String,
+    /// The maximum number of tokens in the bucket. `0` means that the bucket is ignored.
+    pub capacity: u32,
+    /// The time it takes for an empty bucket to be completely refilled.
+    pub interval: Duration,
+    /// The number of tokens the request consumes.
+    ///
+    /// A request that costs more than `capacity` waits until the bucket is full, and then empties it.
+    pub cost: u32,
+}
+
+impl RateLimit {
+    /// Constructs a new `RateLimit`.
+    #[inline(always)]
+    pub fn new(key: impl Into<String>, capacity: u32, interval: Duration, cost: u32) -> Self {
+        Self {
+            key: key.into(),
+            capacity,
+            interval,
+            cost,
+        }
+    }
+}
+
+/// A `struct` that throttles requests using token buckets.
+///
+/// [Client][crate::http::Client] waits on its `RateLimiter` before sending each request. This `struct` uses an [Arc] internally,
+/// so clones of a `RateLimiter` (and clones of a [Client][crate::http::Client]) share the same buckets.
+///
+/// Buckets are created when they are first used, and the capacity and interval of a bucket are updated
+/// to the ones in the latest [RateLimit] with the same key.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimiter {
+    buckets: Arc<SyncMutex<HashMap<String, Bucket>>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    capacity: u32,
+    interval: Duration,
+    tokens: f64,
+    updated: Instant,
+}
+
+/// The usage of a bucket in a [RateLimiter]. Returned by [RateLimiter::usage()].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimitUsage {
+    /// The number of tokens consumed, including the ones that have not been refilled yet.
+    pub used: f64,
+    /// The maximum number of tokens in the bucket.
+    pub capacity: u32,
+    /// The time it takes for an empty bucket to be completely refilled.
+    pub interval: Duration,
+}
+
+impl RateLimiter {
+    /// Constructs a new `RateLimiter` without any buckets.
+    #[inline(always)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Waits until all the buckets in `limits` have enough tokens, then consumes them.
+    ///
+    /// Returns `Ok(true)` if it had to wait. If it has to wait longer than `max_wait`, it returns the time it has to wait
+    /// without waiting or consuming tokens.
+    pub async fn acquire(&self, limits: &[RateLimit], max_wait: Option<Duration>) -> Result<bool, Duration> {
+        let mut waited = false;
+        loop {
+            match self.try_acquire(limits) {
+                Ok(()) => return Ok(waited),
+                Err(wait) => {
+                    if max_wait.is_some_and(|max_wait| wait > max_wait) {
+                        return Err(wait);
+                    }
+                    log::debug!("Waiting {:?} for rate limit", wait);
+                    waited = true;
+                    tokio::time::sleep(wait).await;
+                },
+            }
+        }
+    }
+
+    /// Consumes tokens from all the buckets in `limits` if they have enough tokens.
+    ///
+    /// Otherwise, no tokens are consumed and the time to wait before trying again is returned.
+    pub fn try_acquire(&self, limits: &[RateLimit]) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock();
+        let mut wait = Duration::ZERO;
+        for limit in limits.iter().filter(|limit| limit.capacity != 0) {
+            let bucket = buckets.entry(limit.key.clone()).or_insert_with(|| Bucket {
+                capacity: limit.capacity,
+                interval: limit.interval,
+                tokens: f64::from(limit.capacity),
+                updated: now,
+            });
+            bucket.capacity = limit.capacity;
+            bucket.interval = limit.interval;
+            bucket.refill(now);
+            // a request that costs more than the capacity is allowed when the bucket is full
+            let required = Self::cost(limit);
+            if bucket.tokens < required {
+                wait = wait.max(bucket.time_until(required));
+            }
+        }
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+        for limit in limits.iter().filter(|limit| limit.capacity != 0) {
+            if let Some(bucket) = buckets.get_mut(&limit.key) {
+                bucket.tokens -= Self::cost(limit);
+            }
+        }
+        Ok(())
+    }
+
+    /// The number of tokens `limit` consumes, which is at most its capacity so that the bucket never goes into debt.
+    #[inline(always)]
+    fn cost(limit: &RateLimit) -> f64 {
+        f64::from(limit.cost.min(limit.capacity))
+    }
+
+    /// Overwrites the number of consumed tokens of the bucket with the given key,
+    /// for example with a value reported by the server. Does nothing if the bucket does not exist.
+    ///
+    /// If `used` is greater than the capacity, requests wait until the excess has been refilled as well.
+    pub fn set_used(&self, key: &str, used: u32) {
+        if let Some(bucket) = self.buckets.lock().get_mut(key) {
+            bucket.refill(Instant::now());
+            bucket.tokens = f64::from(bucket.capacity) - f64::from(used);
+        }
+    }
+
+    /// Returns the current usage of the bucket with the given key, or `None` if the bucket does not exist.
+    pub fn usage(&self, key: &str) -> Option<RateLimitUsage> {
+        let mut buckets = self.buckets.lock();
+        let bucket = buckets.get_mut(key)?;
+        bucket.refill(Instant::now());
+        Some(RateLimitUsage {
+            used: f64::from(bucket.capacity) - bucket.tokens,
+            capacity: bucket.capacity,
+            interval: bucket.interval,
+        })
+    }
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated);
+        self.updated = now;
+        if self.interval.is_zero() {
+            self.tokens = f64::from(self.capacity);
+            return;
+        }
+        let refilled = elapsed.as_secs_f64() / self.interval.as_secs_f64() * f64::from(self.capacity);
+        self.tokens = (self.tokens + refilled).min(f64::from(self.capacity));
+    }
+
+    fn time_until(&self, tokens: f64) -> Duration {
+        let missing = tokens - self.tokens;
+        Duration::from_secs_f64(missing / f64::from(self.capacity) * self.interval.as_secs_f64())
+    }
+}
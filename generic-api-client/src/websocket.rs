@@ -1,6 +1,8 @@
 use std::{
+    future::Future,
     sync::{Arc, atomic::{AtomicBool, Ordering}},
-    collections::hash_map::{HashMap, Entry},
+    collections::{hash_map::{DefaultHasher, HashMap}, VecDeque},
+    hash::{Hash, Hasher},
     time::Duration,
     mem,
 };
@@ -99,7 +101,7 @@ impl<H: WebSocketHandler> WebSocketConnection<H> {
             config: WebSocketConfig,
             sink: Arc<AsyncMutex<WebSocketSplitSink>>,
         ) {
-            let mut messages: HashMap<WebSocketMessage, isize> = HashMap::new();
+            let mut messages = DedupWindow::new(config.dedup_window);
 
             let timeout_duration = if config.message_timeout.is_zero() {
                 Duration::MAX
@@ -113,47 +115,57 @@ impl<H: WebSocketHandler> WebSocketConnection<H> {
                     Ok(Some((id, FeederMessage::Message(Ok(message))))) => {
                         // message successfully received
                         if let Some(message) = WebSocketMessage::from_message(message) {
-                            if reconnect_manager.is_reconnecting() {
-                                // reconnecting
-                                let id_sign: isize = if id {
-                                    1
-                                } else {
-                                    -1
-                                };
-                                let entry = messages.entry(message.clone());
-                                match entry {
-                                    Entry::Occupied(mut occupied) => {
-                                        if config.ignore_duplicate_during_reconnection {
-                                            log::debug!("Skipping duplicate message.");
-                                            continue;
+                            // the handler is locked once per message, for deduplicating and handling it
+                            let (outbound, ready) = {
+                                let mut handler = connection.handler.lock();
+                                if reconnect_manager.is_reconnecting() {
+                                    // reconnecting
+                                    let id_sign: isize = if id {
+                                        1
+                                    } else {
+                                        -1
+                                    };
+                                    let key = handler.dedup_key(&message).unwrap_or_else(|| DedupKey::new(&message));
+                                    match messages.touch(key) {
+                                        Some(count) => {
+                                            if config.ignore_duplicate_during_reconnection {
+                                                log::debug!("Skipping duplicate message.");
+                                                continue;
+                                            }
+
+                                            *count += id_sign;
+                                            if id_sign != count.signum() {
+                                                // same message which comes from different connections, so we assume it's a duplicate.
+                                                log::debug!("Skipping duplicate message.");
+                                                continue;
+                                            }
+                                            // comes from the same connection, which means the message was sent twice.
+                                        },
+                                        None => {
+                                            // new message
+                                            messages.insert(key, id_sign);
                                         }
-
-                                        *occupied.get_mut() += id_sign;
-                                        if id_sign != occupied.get().signum() {
-                                            // same message which comes from different connections, so we assume it's a duplicate.
-                                            log::debug!("Skipping duplicate message.");
-                                            continue;
-                                        }
-                                        // comes from the same connection, which means the message was sent twice.
-                                    },
-                                    Entry::Vacant(vacant) => {
-                                        // new message
-                                        vacant.insert(id_sign);
                                     }
+                                } else if !messages.is_empty() {
+                                    // clearing an empty map still costs time proportional to its capacity
+                                    messages.clear();
+                                }
+                                let outbound = handler.handle_message(message);
+                                (outbound, handler.ready())
+                            };
+                            // most messages don't need a reply, so the sink is only locked when there is something to send
+                            if !outbound.is_empty() {
+                                let mut sink_lock = sink.lock().await;
+                                for message in outbound {
+                                    if let Err(error) = sink_lock.feed(message.into_message()).await {
+                                        log::error!("Failed to send message because of an error: {}", error);
+                                    };
+                                }
+                                if let Err(error) = sink_lock.flush().await {
+                                    log::error!("An error occurred while flushing WebSocket sink: {error:?}");
                                 }
-                            } else {
-                                messages.clear();
-                            }
-                            let messages = connection.handler.lock().handle_message(message);
-                            let mut sink_lock = sink.lock().await;
-                            for message in messages {
-                                if let Err(error) = sink_lock.send(message.into_message()).await {
-                                    log::error!("Failed to send message because of an error: {}", error);
-                                };
-                            }
-                            if let Err(error) = sink_lock.flush().await {
-                                log::error!("An error occurred while flushing WebSocket sink: {error:?}");
                             }
+                            ready.await;
                         }
                     },
                     // failed to receive message
@@ -301,8 +313,9 @@ impl<H: WebSocketHandler> WebSocketConnection<H> {
         let (websocket_stream, _) = tokio_tungstenite::connect_async(connection.url.clone()).await?;
         let (mut sink, mut stream) = websocket_stream.split();
 
-        let messages = connection.handler.lock().handle_start();
-        for message in messages {
+        // the handler is not locked while the messages are being prepared
+        let messages = connection.handler.lock().handle_start_async();
+        for message in messages.await {
             sink.send(message.into_message()).await?;
         }
         sink.flush().await?;
@@ -431,6 +444,92 @@ impl WebSocketMessage {
     }
 }
 
+/// A key which identifies a message, used to detect duplicate messages during reconnection.
+///
+/// See [WebSocketHandler::dedup_key()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DedupKey(u64);
+
+impl DedupKey {
+    /// Creates a `DedupKey` by hashing `value`.
+    pub fn new(value: impl Hash) -> Self {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        Self(hasher.finish())
+    }
+}
+
+impl From<u64> for DedupKey {
+    fn from(key: u64) -> Self {
+        Self(key)
+    }
+}
+
+/// Counts of messages received during reconnection, keyed by [DedupKey].
+///
+/// At most `capacity` keys are kept, and the least recently seen keys are evicted first.
+struct DedupWindow {
+    capacity: usize,
+    /// The count and when the key was last seen.
+    counts: HashMap<DedupKey, (isize, u64)>,
+    /// Keys in the order they were seen. An element is stale if the key has been seen again since.
+    order: VecDeque<(DedupKey, u64)>,
+    clock: u64,
+}
+
+impl DedupWindow {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            counts: HashMap::new(),
+            order: VecDeque::new(),
+            clock: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.counts.clear();
+        self.order.clear();
+    }
+
+    /// Marks `key` as seen and returns its count, or returns `None` if `key` is not in the window.
+    fn touch(&mut self, key: DedupKey) -> Option<&mut isize> {
+        self.remove_stale();
+        self.clock += 1;
+        let (count, last_seen) = self.counts.get_mut(&key)?;
+        *last_seen = self.clock;
+        self.order.push_back((key, self.clock));
+        Some(count)
+    }
+
+    fn insert(&mut self, key: DedupKey, count: isize) {
+        self.remove_stale();
+        self.clock += 1;
+        self.counts.insert(key, (count, self.clock));
+        self.order.push_back((key, self.clock));
+        while self.counts.len() > self.capacity {
+            let Some((key, seen)) = self.order.pop_front() else {
+                break;
+            };
+            if matches!(self.counts.get(&key), Some(&(_, last_seen)) if last_seen == seen) {
+                self.counts.remove(&key);
+            }
+        }
+    }
+
+    /// Keeps `order` from growing beyond twice the capacity when the same keys are seen repeatedly.
+    fn remove_stale(&mut self) {
+        if self.order.len() > self.capacity.saturating_mul(2) {
+            let counts = &self.counts;
+            self.order.retain(|(key, seen)| matches!(counts.get(key), Some(&(_, last_seen)) if last_seen == *seen));
+        }
+    }
+}
+
 /// A `trait` which is used to handle events on the [WebSocketConnection].
 ///
 /// The `struct` implementing this `trait` is required to be [Send] and `'static` because
@@ -449,9 +548,39 @@ pub trait WebSocketHandler: Send + 'static {
         vec![]
     }
 
+    /// Returns the messages to be sent when a connection is started, waiting for asynchronous work such as signing in another process.
+    ///
+    /// [WebSocketConnection] calls this instead of [handle_start()][Self::handle_start()] and awaits the returned future
+    /// without locking the handler, so that handlers can authenticate without blocking the runtime.
+    /// The default implementation calls [handle_start()][Self::handle_start()].
+    fn handle_start_async(&mut self) -> impl Future<Output = Vec<WebSocketMessage>> + Send + 'static {
+        std::future::ready(self.handle_start())
+    }
+
     /// Called when the [WebSocketConnection] received a message, returns messages to be sent to the server.
+    ///
+    /// This is called for every message, so return `vec![]` (which doesn't allocate) when there is nothing to send.
+    /// The connection is only locked for sending when the returned `Vec` is not empty.
     fn handle_message(&mut self, message: WebSocketMessage) -> Vec<WebSocketMessage>;
 
+    /// Returns a future which the [WebSocketConnection] awaits after each call to [handle_message()][Self::handle_message()]
+    /// before it handles the next message, so that a handler which can't keep up can slow down the connection.
+    ///
+    /// Messages which arrive in the meantime are held by the connection. The default implementation is ready immediately.
+    fn ready(&self) -> impl Future<Output = ()> + Send + 'static {
+        std::future::ready(())
+    }
+
+    /// Returns a key which identifies `message`, used to detect duplicate messages during reconnection.
+    ///
+    /// Messages with the same key are considered to be identical. This is only called while the connection is
+    /// reconnecting. Returns `None` by default, in which case the whole message is hashed.
+    /// See [WebSocketConfig::ignore_duplicate_during_reconnection] for more information.
+    #[allow(unused_variables)]
+    fn dedup_key(&self, message: &WebSocketMessage) -> Option<DedupKey> {
+        None
+    }
+
     /// Called when a websocket connection is closed.
     ///
     /// If the parameter `reconnect` is:
@@ -492,6 +621,11 @@ pub struct WebSocketConfig {
     /// When `ignore_duplicate_during_reconnection` is set to `true`, [WebSocketConnection] will wait for a
     /// certain amount of time to make sure no message is lost. [Default]s to 300ms
     pub reconnection_wait: Duration,
+    /// The number of messages which are remembered during reconnection to detect duplicates.
+    /// When more messages are received, the least recently seen ones are forgotten first. [Default]s to 4096.
+    ///
+    /// Messages are identified by [WebSocketHandler::dedup_key()].
+    pub dedup_window: usize,
     /// A reconnection will be triggered if no messages are received within this amount of time.
     /// [Default]s to [Duration::ZERO], which means no timeout will be applied.
     pub message_timeout: Duration,
@@ -512,6 +646,7 @@ impl Default for WebSocketConfig {
             url_prefix: String::new(),
             ignore_duplicate_during_reconnection: false,
             reconnection_wait: Duration::from_millis(300),
+            dedup_window: 4096,
             message_timeout: Duration::ZERO,
         }
     }
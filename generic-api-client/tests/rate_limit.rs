@@ -0,0 +1,95 @@
+use std::time::Duration;
+use generic_api_client::rate_limit::{RateLimit, RateLimiter};
+
+const INTERVAL: Duration = Duration::from_millis(200);
+
+fn assert_wait(wait: Duration, expected: Duration) {
+    // the bucket is refilled between the calls
+    assert!(wait <= expected && wait > expected.mul_f64(0.8), "{:?} is not about {:?}", wait, expected);
+}
+
+#[test]
+fn refill() {
+    let limiter = RateLimiter::new();
+    let limit = |cost| [RateLimit::new("bucket", 10, INTERVAL, cost)];
+    assert_eq!(limiter.usage("bucket"), None);
+
+    limiter.try_acquire(&limit(10)).unwrap();
+    let usage = limiter.usage("bucket").unwrap();
+    assert!(usage.used > 9.9, "{:?}", usage);
+    assert_eq!(usage.capacity, 10);
+
+    // half of the bucket is refilled in half of the interval
+    assert_wait(limiter.try_acquire(&limit(5)).unwrap_err(), INTERVAL / 2);
+    std::thread::sleep(INTERVAL / 2);
+    limiter.try_acquire(&limit(5)).unwrap();
+    assert!(limiter.try_acquire(&limit(5)).is_err());
+
+    std::thread::sleep(INTERVAL);
+    assert!(limiter.usage("bucket").unwrap().used < 0.1);
+}
+
+#[test]
+fn multiple_buckets() {
+    let limiter = RateLimiter::new();
+    let slow = RateLimit::new("slow", 10, INTERVAL * 5, 10);
+    let fast = RateLimit::new("fast", 10, INTERVAL, 4);
+    limiter.try_acquire(&[slow, fast.clone()]).unwrap();
+
+    // the wait is the longest one of the buckets
+    let slow_one = RateLimit::new("slow", 10, INTERVAL * 5, 1);
+    assert_wait(limiter.try_acquire(&[fast.clone(), slow_one]).unwrap_err(), INTERVAL / 2);
+    // no tokens are consumed when any of the buckets doesn't have enough
+    let used = limiter.usage("fast").unwrap().used;
+    assert!((3.9..=4.0).contains(&used), "{}", used);
+
+    // buckets with a capacity of 0 are ignored
+    limiter.try_acquire(&[fast, RateLimit::new("ignored", 0, INTERVAL, 100)]).unwrap();
+    assert_eq!(limiter.usage("ignored"), None);
+}
+
+#[test]
+fn cost_above_capacity() {
+    let limiter = RateLimiter::new();
+    let expensive = [RateLimit::new("bucket", 10, INTERVAL, 25)];
+    let cheap = [RateLimit::new("bucket", 10, INTERVAL, 1)];
+
+    // allowed when the bucket is full, and only empties it
+    limiter.try_acquire(&expensive).unwrap();
+    let used = limiter.usage("bucket").unwrap().used;
+    assert!((9.9..=10.0).contains(&used), "{}", used);
+    assert_wait(limiter.try_acquire(&cheap).unwrap_err(), INTERVAL / 10);
+
+    // waits until the bucket is full again
+    std::thread::sleep(INTERVAL / 2);
+    assert_wait(limiter.try_acquire(&expensive).unwrap_err(), INTERVAL / 2);
+}
+
+#[test]
+fn reported_usage() {
+    let limiter = RateLimiter::new();
+    let limit = [RateLimit::new("bucket", 10, INTERVAL, 1)];
+    // unknown buckets are not created
+    limiter.set_used("bucket", 5);
+    assert_eq!(limiter.usage("bucket"), None);
+
+    limiter.try_acquire(&limit).unwrap();
+    limiter.set_used("bucket", 10);
+    assert_wait(limiter.try_acquire(&limit).unwrap_err(), INTERVAL / 10);
+    // usage above the capacity has to be refilled as well
+    limiter.set_used("bucket", 15);
+    assert_wait(limiter.try_acquire(&limit).unwrap_err(), INTERVAL * 6 / 10);
+}
+
+#[tokio::test]
+async fn acquire_waits() {
+    let limiter = RateLimiter::new();
+    let limit = [RateLimit::new("bucket", 2, INTERVAL, 1)];
+    assert_eq!(limiter.acquire(&limit, None).await, Ok(false));
+    assert_eq!(limiter.acquire(&limit, None).await, Ok(false));
+    // waiting longer than max_wait is refused without consuming tokens
+    assert!(limiter.acquire(&limit, Some(Duration::from_millis(10))).await.is_err());
+    let started = std::time::Instant::now();
+    assert_eq!(limiter.acquire(&limit, None).await, Ok(true));
+    assert!(started.elapsed() >= INTERVAL / 2 - Duration::from_millis(10));
+}
@@ -0,0 +1,239 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    sync::{Arc, Mutex, atomic::{AtomicUsize, Ordering}},
+    time::{Duration, Instant, SystemTime},
+};
+use generic_api_client::http::{header::{HeaderValue, RETRY_AFTER}, *};
+
+/// Starts an HTTP server which responds with each of `responses` in order, and returns its url and the number of
+/// requests received so far. `418` is returned once `responses` run out.
+fn start_server(responses: Vec<&'static str>) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let responses = Arc::new(Mutex::new(responses.into_iter()));
+    let count = Arc::new(AtomicUsize::new(0));
+    let received = Arc::clone(&count);
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let responses = Arc::clone(&responses);
+            let received = Arc::clone(&received);
+            std::thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                loop {
+                    let mut content_length = 0;
+                    loop {
+                        let mut line = String::new();
+                        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                            return;
+                        }
+                        if line.trim_end().is_empty() {
+                            break;
+                        }
+                        if let Some((name, value)) = line.split_once(':') {
+                            if name.eq_ignore_ascii_case("content-length") {
+                                content_length = value.trim().parse().unwrap();
+                            }
+                        }
+                    }
+                    let mut body = vec![0; content_length];
+                    reader.read_exact(&mut body).unwrap();
+                    received.fetch_add(1, Ordering::SeqCst);
+                    let response = responses.lock().unwrap().next()
+                        .unwrap_or("HTTP/1.1 418 I'm a teapot\r\nContent-Length: 0\r\n\r\n");
+                    stream.write_all(response.as_bytes()).unwrap();
+                }
+            });
+        }
+    });
+    (url, count)
+}
+
+const OK: &str = "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n";
+const INTERNAL_SERVER_ERROR: &str = "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\n\r\n";
+const TOO_MANY_REQUESTS: &str = "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 0\r\nContent-Length: 0\r\n\r\n";
+const BANNED: &str = "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 7200\r\nContent-Length: 0\r\n\r\n";
+/// The body is never completed, so receiving it times out.
+const STALLED_BODY: &str = "HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nabc";
+
+/// Returns the status of the response without checking it.
+struct StatusHandler {
+    config: RequestConfig,
+}
+
+impl RequestHandler<()> for StatusHandler {
+    type Successful = StatusCode;
+    type Unsuccessful = ();
+    type BuildError = ();
+
+    fn request_config(&self) -> RequestConfig {
+        self.config.clone()
+    }
+
+    fn build_request(&self, builder: RequestBuilder, _: &Option<()>, _: u8) -> Result<Request, Self::BuildError> {
+        builder.build().map_err(|_| ())
+    }
+
+    fn handle_response(&self, status: StatusCode, _: HeaderMap, _: Bytes) -> Result<Self::Successful, Self::Unsuccessful> {
+        Ok(status)
+    }
+}
+
+fn config(url: String, max_try: u8) -> RequestConfig {
+    let mut config = RequestConfig::new();
+    config.url_prefix = url;
+    config.max_try = max_try;
+    config.retry_cooldown = Duration::ZERO;
+    config.retry_jitter = 0.0;
+    config
+}
+
+async fn send(method: Method, config: RequestConfig) -> Response<StatusCode> {
+    Client::new().request_with_metadata::<(), (), _>(method, "/", None, None, &StatusHandler { config }).await.unwrap()
+}
+
+#[test]
+fn classification() {
+    let headers = HeaderMap::new();
+    let mut retry_after = HeaderMap::new();
+    retry_after.insert(RETRY_AFTER, HeaderValue::from_static("3"));
+    let decision = |method: &Method, status: u16, headers: &HeaderMap| {
+        default_retry_decision(method, StatusCode::from_u16(status).unwrap(), headers)
+    };
+
+    assert_eq!(decision(&Method::GET, 200, &headers), RetryDecision::DoNotRetry);
+    assert_eq!(decision(&Method::GET, 400, &headers), RetryDecision::DoNotRetry);
+    assert_eq!(decision(&Method::GET, 418, &retry_after), RetryDecision::DoNotRetry);
+    assert_eq!(decision(&Method::POST, 429, &headers), RetryDecision::Retry);
+    assert_eq!(decision(&Method::POST, 429, &retry_after), RetryDecision::RetryAfter(Duration::from_secs(3)));
+    // the server might have processed non-idempotent requests
+    assert_eq!(decision(&Method::GET, 500, &headers), RetryDecision::Retry);
+    assert_eq!(decision(&Method::DELETE, 502, &headers), RetryDecision::Retry);
+    assert_eq!(decision(&Method::POST, 500, &headers), RetryDecision::DoNotRetry);
+    assert_eq!(decision(&Method::POST, 503, &headers), RetryDecision::DoNotRetry);
+    assert_eq!(decision(&Method::POST, 503, &retry_after), RetryDecision::RetryAfter(Duration::from_secs(3)));
+}
+
+#[test]
+fn retry_after() {
+    let parse = |value: &str| {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(value).unwrap());
+        parse_retry_after(&headers)
+    };
+    assert_eq!(parse_retry_after(&HeaderMap::new()), None);
+    assert_eq!(parse("120"), Some(Duration::from_secs(120)));
+    assert_eq!(parse(" 5 "), Some(Duration::from_secs(5)));
+    assert_eq!(parse("soon"), None);
+    assert_eq!(parse("-1"), None);
+
+    let date = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(60));
+    let wait = parse(&date).unwrap();
+    assert!(wait > Duration::from_secs(58) && wait <= Duration::from_secs(60), "{:?}", wait);
+    assert_eq!(parse("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+}
+
+#[tokio::test]
+async fn server_errors_are_retried_until_max_try() {
+    let (url, count) = start_server(vec![INTERNAL_SERVER_ERROR, INTERNAL_SERVER_ERROR, OK]);
+    let response = send(Method::GET, config(url, 3)).await;
+    assert_eq!(response.body, StatusCode::OK);
+    assert_eq!(response.metadata.attempt_count, 3);
+    assert_eq!(count.load(Ordering::SeqCst), 3);
+
+    // the last response is returned when giving up
+    let (url, count) = start_server(vec![INTERNAL_SERVER_ERROR, INTERNAL_SERVER_ERROR, OK]);
+    let response = send(Method::GET, config(url, 2)).await;
+    assert_eq!(response.body, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(response.metadata.attempt_count, 2);
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn non_idempotent_server_errors_are_not_retried() {
+    let (url, count) = start_server(vec![INTERNAL_SERVER_ERROR, OK]);
+    let response = send(Method::POST, config(url, 3)).await;
+    assert_eq!(response.body, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(response.metadata.attempt_count, 1);
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn too_many_requests_is_retried() {
+    let (url, count) = start_server(vec![TOO_MANY_REQUESTS, OK]);
+    let mut config = config(url, 3);
+    // Retry-After takes precedence over the configured cooldown
+    config.retry_cooldown = Duration::from_secs(60);
+    let response = tokio::time::timeout(Duration::from_secs(5), send(Method::POST, config)).await.unwrap();
+    assert_eq!(response.body, StatusCode::OK);
+    assert_eq!(response.metadata.attempt_count, 2);
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn other_client_errors_are_not_retried() {
+    let (url, count) = start_server(vec![]);
+    let response = send(Method::GET, config(url, 3)).await;
+    assert_eq!(response.body, StatusCode::IM_A_TEAPOT);
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn backoff_is_capped() {
+    let (url, count) = start_server(vec![INTERNAL_SERVER_ERROR, INTERNAL_SERVER_ERROR, INTERNAL_SERVER_ERROR, OK]);
+    let mut config = config(url, 4);
+    config.retry_cooldown = Duration::from_millis(50);
+    config.retry_backoff = 10.0;
+    config.retry_max_cooldown = Duration::from_millis(60);
+
+    // 50ms, 60ms and 60ms instead of 50ms, 500ms and 5s
+    let started = Instant::now();
+    let response = send(Method::GET, config).await;
+    let elapsed = started.elapsed();
+    assert_eq!(response.body, StatusCode::OK);
+    assert_eq!(count.load(Ordering::SeqCst), 4);
+    assert!(elapsed >= Duration::from_millis(170) && elapsed < Duration::from_millis(500), "{:?}", elapsed);
+}
+
+#[tokio::test]
+async fn max_elapsed_stops_retrying() {
+    let (url, count) = start_server(vec![INTERNAL_SERVER_ERROR; 10]);
+    let mut config = config(url, 10);
+    config.retry_cooldown = Duration::from_millis(100);
+    config.retry_backoff = 1.0;
+    config.retry_max_elapsed = Duration::from_millis(250);
+
+    // the attempts start at about 0ms, 100ms and 200ms. the next one would start after 300ms
+    let response = send(Method::GET, config).await;
+    assert_eq!(response.body, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(response.metadata.attempt_count, 3);
+    assert_eq!(count.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn long_retry_after_is_not_waited_for() {
+    let (url, count) = start_server(vec![BANNED, OK]);
+    let response = tokio::time::timeout(Duration::from_secs(5), send(Method::GET, config(url, 3))).await.unwrap();
+    assert_eq!(response.body, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.metadata.attempt_count, 1);
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn body_timeout_is_retried_for_idempotent_requests() {
+    let (url, count) = start_server(vec![STALLED_BODY, OK]);
+    let mut config = config(url, 3);
+    config.timeout = Duration::from_millis(200);
+    let response = send(Method::GET, config.clone()).await;
+    assert_eq!(response.body, StatusCode::OK);
+    assert_eq!(response.metadata.attempt_count, 2);
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+
+    // the server might have processed the request
+    let (url, count) = start_server(vec![STALLED_BODY, OK]);
+    config.url_prefix = url;
+    let error = Client::new().request_with_metadata::<(), (), _>(Method::POST, "/", None, None, &StatusHandler { config }).await.unwrap_err();
+    assert!(matches!(&error, RequestError::ReceiveResponse(error) if error.is_timeout()), "{:?}", error);
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+}
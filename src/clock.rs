@@ -0,0 +1,235 @@
+//! A module for synchronizing the local clock with the clocks of exchange servers.
+//!
+//! Signed requests contain a timestamp, and exchanges reject requests whose timestamp is too far from the server time.
+//! Every handler takes timestamps from the [ServerClock] in its options, so running [Client::spawn_clock_sync()][crate::Client::spawn_clock_sync()]
+//! keeps requests valid even if the local clock drifts.
+
+use std::{
+    fmt::Debug,
+    sync::{Arc, atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering}},
+    time::{Duration, SystemTime},
+};
+use tokio::sync::Notify;
+use generic_api_client::http::{*, header::HeaderMap};
+
+/// The type returned by [Client::sync_clock()][crate::Client::sync_clock()].
+pub type ClockSyncError = RequestError<&'static str, &'static str>;
+
+/// A `trait` that represents a source of the local time.
+///
+/// [ServerClock] uses [SystemClock] by default. Other implementations, such as [FixedClock],
+/// can be used with [ServerClock::with_clock()] to make signatures deterministic in tests.
+pub trait Clock: Send + Sync + Debug {
+    /// Returns the current time.
+    fn now(&self) -> SystemTime;
+}
+
+/// A [Clock] which returns [SystemTime::now()].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    #[inline(always)]
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A [Clock] which always returns the same time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedClock(pub SystemTime);
+
+impl FixedClock {
+    /// Creates a `FixedClock` which returns the given number of milliseconds since the Unix epoch.
+    pub fn from_millis(millis: u64) -> Self {
+        Self(SystemTime::UNIX_EPOCH + Duration::from_millis(millis))
+    }
+}
+
+impl Clock for FixedClock {
+    #[inline(always)]
+    fn now(&self) -> SystemTime {
+        self.0
+    }
+}
+
+/// A clock which estimates the time of an exchange server.
+///
+/// The clock is the local clock plus an offset, which is estimated from samples of the server time.
+/// This `struct` uses an [Arc] internally, so clones of a `ServerClock` (and the options containing it) share the offset.
+#[derive(Debug, Clone, Default)]
+pub struct ServerClock {
+    inner: Arc<ServerClockInner>,
+}
+
+#[derive(Debug)]
+struct ServerClockInner {
+    /// the local clock
+    clock: Box<dyn Clock>,
+    /// server time - local time, in microseconds
+    offset: AtomicI64,
+    /// round trip time of the samples in microseconds. `0` means that there are no samples
+    rtt: AtomicU64,
+    /// `true` if the offset is known to be wrong, and the next sample should replace it.
+    stale: AtomicBool,
+    resync: Notify,
+}
+
+impl Default for ServerClockInner {
+    fn default() -> Self {
+        Self {
+            clock: Box::new(SystemClock),
+            offset: AtomicI64::default(),
+            rtt: AtomicU64::default(),
+            stale: AtomicBool::default(),
+            resync: Notify::default(),
+        }
+    }
+}
+
+impl ServerClock {
+    /// Creates a new `ServerClock` with no offset, which uses [SystemClock] as the local clock.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new `ServerClock` with no offset, which uses `clock` as the local clock.
+    pub fn with_clock(clock: impl Clock + 'static) -> Self {
+        Self {
+            inner: Arc::new(ServerClockInner {
+                clock: Box::new(clock),
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Returns the current time of the local clock.
+    pub fn local_now(&self) -> SystemTime {
+        self.inner.clock.now()
+    }
+
+    /// Returns the estimated current time of the server.
+    pub fn now(&self) -> SystemTime {
+        add_micros(self.local_now(), self.inner.offset.load(Ordering::Relaxed))
+    }
+
+    /// Returns the estimated current time of the server as milliseconds since the Unix epoch.
+    pub fn now_millis(&self) -> u64 {
+        self.now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis() as u64 // always after the epoch
+    }
+
+    /// Returns the estimated offset of the server clock relative to the local clock, in microseconds.
+    pub fn offset_micros(&self) -> i64 {
+        self.inner.offset.load(Ordering::Relaxed)
+    }
+
+    /// Returns the smoothed round trip time of the samples, or `None` if there are no samples.
+    pub fn rtt(&self) -> Option<Duration> {
+        match self.inner.rtt.load(Ordering::Relaxed) {
+            0 => None,
+            rtt => Some(Duration::from_micros(rtt)),
+        }
+    }
+
+    /// Updates the offset with a sample of the server time.
+    ///
+    /// `sent` and `received` are the times of the local clock (see [local_now()][Self::local_now()]) when the request was sent and when the response was received,
+    /// and `server_time` is the time reported in the response.
+    /// Samples with a round trip time much longer than the previous samples are ignored because they are inaccurate.
+    pub fn add_sample(&self, sent: SystemTime, received: SystemTime, server_time: SystemTime) {
+        let rtt = received.duration_since(sent).unwrap_or_default().as_micros().max(1) as u64;
+        let offset = micros_between(add_micros(sent, rtt as i64 / 2), server_time);
+
+        let previous_rtt = self.inner.rtt.load(Ordering::Relaxed);
+        if previous_rtt == 0 || self.inner.stale.swap(false, Ordering::Relaxed) {
+            self.inner.offset.store(offset, Ordering::Relaxed);
+            self.inner.rtt.store(rtt, Ordering::Relaxed);
+        } else if rtt <= previous_rtt * 2 {
+            let previous_offset = self.inner.offset.load(Ordering::Relaxed);
+            self.inner.offset.store((previous_offset + offset) / 2, Ordering::Relaxed);
+            self.inner.rtt.store((previous_rtt + rtt) / 2, Ordering::Relaxed);
+        } else {
+            log::debug!("Ignoring server time sample because of long round trip time: {}us", rtt);
+            return;
+        }
+        log::debug!("Server clock offset: {}us, round trip time: {}us", self.offset_micros(), rtt);
+    }
+
+    /// Corrects the offset with the `Date` header of a response, if the offset is off by more than a second.
+    ///
+    /// The header only has a resolution of one second, so this is only useful for recovering from large errors.
+    pub fn observe_date_header(&self, headers: &HeaderMap) {
+        let date = headers.get(header::DATE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| httpdate::parse_http_date(value).ok());
+        if let Some(date) = date {
+            // the server time is somewhere in [date, date + 1s)
+            let difference = micros_between(self.now(), date + Duration::from_millis(500));
+            if difference.abs() > 1_000_000 {
+                log::debug!("Correcting server clock offset with the Date header by {}us", difference);
+                self.inner.offset.fetch_add(difference, Ordering::Relaxed);
+                self.inner.stale.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Marks the offset as wrong and asks the task started by [Client::spawn_clock_sync()][crate::Client::spawn_clock_sync()]
+    /// to take a new sample immediately.
+    ///
+    /// Handlers call this when the server rejects a request because of its timestamp.
+    pub fn request_resync(&self) {
+        self.inner.stale.store(true, Ordering::Relaxed);
+        self.inner.resync.notify_one();
+    }
+
+    /// Completes when [request_resync()][Self::request_resync()] is called.
+    pub(crate) async fn resync_requested(&self) {
+        self.inner.resync.notified().await
+    }
+}
+
+/// A [RequestHandler] used to sample the server time.
+pub(crate) struct ServerTimeHandler {
+    pub(crate) parse: fn(&HeaderMap, &[u8]) -> Option<SystemTime>,
+}
+
+impl RequestHandler<()> for ServerTimeHandler {
+    type Successful = SystemTime;
+    type Unsuccessful = &'static str;
+    type BuildError = &'static str;
+
+    fn build_request(&self, builder: RequestBuilder, _: &Option<()>, _: u8) -> Result<Request, Self::BuildError> {
+        builder.build().or(Err("failed to build request"))
+    }
+
+    fn handle_response(&self, status: StatusCode, headers: HeaderMap, response_body: Bytes) -> Result<Self::Successful, Self::Unsuccessful> {
+        if !status.is_success() {
+            return Err("unsuccessful response");
+        }
+        (self.parse)(&headers, &response_body).ok_or("failed to parse server time")
+    }
+}
+
+/// Parses the `Date` header of a response as the server time.
+#[cfg(any(feature = "bitflyer", feature = "coincheck"))]
+pub(crate) fn parse_date_header(headers: &HeaderMap, _: &[u8]) -> Option<SystemTime> {
+    let date = headers.get(header::DATE)?.to_str().ok()?;
+    // the server time is somewhere in [date, date + 1s)
+    Some(httpdate::parse_http_date(date).ok()? + Duration::from_millis(500))
+}
+
+fn add_micros(time: SystemTime, micros: i64) -> SystemTime {
+    if micros >= 0 {
+        time + Duration::from_micros(micros as u64)
+    } else {
+        time - Duration::from_micros(micros.unsigned_abs())
+    }
+}
+
+/// Returns `to - from` in microseconds
+fn micros_between(from: SystemTime, to: SystemTime) -> i64 {
+    match to.duration_since(from) {
+        Ok(duration) => duration.as_micros() as i64,
+        Err(error) => -(error.duration().as_micros() as i64),
+    }
+}
@@ -0,0 +1,186 @@
+//! A module for loading API keys and secrets at the time they are used.
+//!
+//! Handlers ask the [CredentialProvider] in their options for the credentials every time they sign an HTTP request
+//! or authenticate a WebSocket connection (including re-authentication after a reconnection).
+//! Rotated credentials are therefore used without recreating [Client][crate::Client]s or connections.
+
+use std::{
+    fmt::{self, Debug, Display},
+    path::PathBuf,
+    time::SystemTime,
+};
+use parking_lot::Mutex as SyncMutex;
+use serde::Deserialize;
+use crate::secret::Secret;
+
+/// A pair of an API key and its secret.
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    /// API key
+    pub key: String,
+    /// API secret. This can be `None` when a [Signer][crate::signer::Signer] is used.
+    pub secret: Option<Secret>,
+}
+
+impl Credentials {
+    /// Creates new `Credentials` with a secret.
+    pub fn new(key: impl Into<String>, secret: impl Into<Secret>) -> Self {
+        Self {
+            key: key.into(),
+            secret: Some(secret.into()),
+        }
+    }
+}
+
+/// The error returned when a [CredentialProvider] could not provide credentials.
+#[derive(Debug, Clone)]
+pub struct CredentialError(String);
+
+impl CredentialError {
+    /// Creates a new `CredentialError` with the given message.
+    pub fn new(message: impl Into<String>) -> Self {
+        Self(message.into())
+    }
+}
+
+impl Display for CredentialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to load credentials: {}", self.0)
+    }
+}
+
+impl std::error::Error for CredentialError {}
+
+/// A `trait` that represents a source of [Credentials].
+///
+/// [credentials()][Self::credentials()] is called every time a request is signed, so it should be fast.
+pub trait CredentialProvider: Send + Sync + Debug {
+    /// Returns the current credentials.
+    fn credentials(&self) -> Result<Credentials, CredentialError>;
+}
+
+/// A [CredentialProvider] which always returns the same [Credentials].
+#[derive(Debug, Clone)]
+pub struct StaticCredentials(pub Credentials);
+
+impl CredentialProvider for StaticCredentials {
+    #[inline(always)]
+    fn credentials(&self) -> Result<Credentials, CredentialError> {
+        Ok(self.0.clone())
+    }
+}
+
+/// A [CredentialProvider] which reads the credentials from environment variables every time they are used.
+#[derive(Debug, Clone)]
+pub struct EnvCredentials {
+    key_variable: String,
+    secret_variable: String,
+}
+
+impl EnvCredentials {
+    /// Creates a new `EnvCredentials` which reads the API key from `key_variable` and the secret from `secret_variable`.
+    ///
+    /// If `secret_variable` is not set, the secret is `None`.
+    pub fn new(key_variable: impl Into<String>, secret_variable: impl Into<String>) -> Self {
+        Self {
+            key_variable: key_variable.into(),
+            secret_variable: secret_variable.into(),
+        }
+    }
+}
+
+impl CredentialProvider for EnvCredentials {
+    fn credentials(&self) -> Result<Credentials, CredentialError> {
+        let key = std::env::var(&self.key_variable)
+            .map_err(|error| CredentialError(format!("{}: {}", self.key_variable, error)))?;
+        Ok(Credentials {
+            key,
+            secret: std::env::var(&self.secret_variable).ok().map(Secret::new),
+        })
+    }
+}
+
+/// A [CredentialProvider] which reads the credentials from a JSON file, and reloads it when it is modified.
+///
+/// The file looks like `{"key": "...", "secret": "..."}`, where `secret` is optional.
+/// To rotate credentials, write the new file to a temporary path and rename it to `path`, so that a partially written file is never read.
+/// If the modified file can't be read, the last credentials are used and an error is logged.
+#[derive(Debug)]
+pub struct FileCredentials {
+    path: PathBuf,
+    cache: SyncMutex<Option<(SystemTime, Credentials)>>,
+}
+
+#[derive(Deserialize)]
+struct CredentialsFile {
+    key: String,
+    secret: Option<String>,
+}
+
+impl FileCredentials {
+    /// Creates a new `FileCredentials` which reads the file at `path`. The file is read when the credentials are first used.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            cache: SyncMutex::new(None),
+        }
+    }
+
+    fn load(&self) -> Result<Credentials, CredentialError> {
+        let contents = std::fs::read(&self.path)
+            .map_err(|error| CredentialError(format!("{}: {}", self.path.display(), error)))?;
+        let file: CredentialsFile = serde_json::from_slice(&contents)
+            .map_err(|error| CredentialError(format!("{}: {}", self.path.display(), error)))?;
+        Ok(Credentials {
+            key: file.key,
+            secret: file.secret.map(Secret::new),
+        })
+    }
+}
+
+impl CredentialProvider for FileCredentials {
+    fn credentials(&self) -> Result<Credentials, CredentialError> {
+        let modified = std::fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(|error| CredentialError(format!("{}: {}", self.path.display(), error)));
+
+        let mut cache = self.cache.lock();
+        if let (Ok(modified), Some((cached_modified, credentials))) = (&modified, cache.as_ref()) {
+            if modified == cached_modified {
+                return Ok(credentials.clone());
+            }
+        }
+        match self.load() {
+            Ok(credentials) => {
+                if let Ok(modified) = modified {
+                    log::debug!("Loaded credentials from {}", self.path.display());
+                    *cache = Some((modified, credentials.clone()));
+                }
+                Ok(credentials)
+            },
+            Err(error) => match cache.as_ref() {
+                Some((_, credentials)) => {
+                    log::error!("{}, using the last credentials", error);
+                    Ok(credentials.clone())
+                },
+                None => Err(error),
+            },
+        }
+    }
+}
+
+/// Returns the credentials from `provider` if it is set, otherwise the key and secret set in the options.
+#[cfg(any(feature = "binance", feature = "bitflyer", feature = "bybit", feature = "coincheck"))]
+pub(crate) fn resolve(
+    provider: &Option<std::sync::Arc<dyn CredentialProvider>>,
+    key: &Option<String>,
+    secret: &Option<Secret>,
+) -> Result<Credentials, crate::error::BuildError> {
+    match provider {
+        Some(provider) => provider.credentials().map_err(crate::error::BuildError::CredentialProvider),
+        None => Ok(Credentials {
+            key: key.clone().ok_or(crate::error::BuildError::MissingCredential("API key"))?,
+            secret: secret.clone(),
+        }),
+    }
+}
@@ -0,0 +1,183 @@
+//! A module for errors shared by the exchange modules.
+
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    io,
+    time::Duration,
+};
+use generic_api_client::http::{header::InvalidHeaderValue, RequestError, StatusCode};
+use crate::{credentials::CredentialError, signer::KeyError};
+
+/// The error returned when a handler failed to build a request.
+///
+/// This is the `BuildError` of the request handlers of all exchanges, so it is returned in
+/// [RequestError::BuildRequestError].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum BuildError {
+    /// A credential required for authentication, such as `"API key"` or `"API secret"`, is not set.
+    MissingCredential(&'static str),
+    /// The [CredentialProvider][crate::credentials::CredentialProvider] failed to provide credentials.
+    CredentialProvider(CredentialError),
+    /// The body of the request could not be serialized.
+    Serialize {
+        /// The `Content-Type` that the body was serialized as, such as `"application/json"`.
+        content_type: &'static str,
+        source: Box<dyn Error + Send + Sync>,
+    },
+    /// A value could not be used in a header. The value itself is not included because it may be an API key.
+    InvalidHeaderValue {
+        /// The name of the header.
+        header: &'static str,
+        source: InvalidHeaderValue,
+    },
+    /// The [Signer][crate::signer::Signer] failed to sign the request.
+    Sign(io::Error),
+    /// The key of the [Signer][crate::signer::Signer] can't be used with this API.
+    UnsupportedKey(&'static str),
+    /// The private key set with a `PrivateKeyPem` option could not be loaded.
+    InvalidKey(KeyError),
+    /// The nonce could not be generated.
+    Nonce(io::Error),
+    /// [RequestBuilder::build()][generic_api_client::http::RequestBuilder::build()] failed.
+    Request(reqwest::Error),
+    /// The account profile selected with an `Account` option is not registered in the [Client][crate::Client].
+    UnknownAccount(String),
+}
+
+impl BuildError {
+    /// Creates a [BuildError::Serialize].
+    #[cfg(any(feature = "binance", feature = "bitflyer", feature = "bybit", feature = "coincheck"))]
+    pub(crate) fn serialize(content_type: &'static str, source: impl Error + Send + Sync + 'static) -> Self {
+        Self::Serialize {
+            content_type,
+            source: Box::new(source),
+        }
+    }
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingCredential(credential) => write!(f, "{} not set", credential),
+            Self::CredentialProvider(error) => write!(f, "{}", error),
+            Self::Serialize { content_type, source } => write!(f, "could not serialize body as {}: {}", content_type, source),
+            Self::InvalidHeaderValue { header, .. } => write!(f, "invalid character in {} header", header),
+            Self::Sign(error) => write!(f, "failed to sign: {}", error),
+            Self::UnsupportedKey(message) => f.write_str(message),
+            Self::InvalidKey(error) => write!(f, "{}", error),
+            Self::Nonce(error) => write!(f, "failed to generate nonce: {}", error),
+            Self::Request(error) => write!(f, "failed to build request: {}", error),
+            Self::UnknownAccount(name) => write!(f, "account {:?} is not registered", name),
+        }
+    }
+}
+
+impl Error for BuildError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::MissingCredential(_) | Self::UnsupportedKey(_) | Self::UnknownAccount(_) => None,
+            Self::CredentialProvider(error) => Some(error),
+            Self::Serialize { source, .. } => Some(&**source),
+            Self::InvalidHeaderValue { source, .. } => Some(source),
+            Self::Sign(error) | Self::Nonce(error) => Some(error),
+            Self::InvalidKey(error) => Some(error),
+            Self::Request(error) => Some(error),
+        }
+    }
+}
+
+/// A `trait` for classifying the errors of all exchanges in the same way, for example in generic retry and alerting code.
+///
+/// This is implemented for the `Unsuccessful` errors of the handlers of all exchanges, and for [RequestError]s containing them.
+pub trait ExchangeError {
+    /// Whether the request was rejected because of a rate limit, including bans for exceeding the limits.
+    fn is_rate_limited(&self) -> bool;
+
+    /// How long to wait before sending the next request, if it is known.
+    #[inline(always)]
+    fn retry_after(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Whether the request was rejected because of the API key, the signature or missing permissions.
+    fn is_auth_error(&self) -> bool;
+
+    /// Whether sending the same request again later may succeed, for example after rate limits, server errors
+    /// and rejected timestamps. This does not consider whether it is safe to send the request twice.
+    fn is_retryable(&self) -> bool;
+
+    /// The error code returned by the exchange, such as `code` of Binance or `retCode` of Bybit.
+    #[inline(always)]
+    fn exchange_code(&self) -> Option<i64> {
+        None
+    }
+
+    /// The HTTP status of the response, if it is known.
+    #[inline(always)]
+    fn status(&self) -> Option<StatusCode> {
+        None
+    }
+
+    /// The body of a response which could not be parsed.
+    #[inline(always)]
+    fn raw_body(&self) -> Option<&[u8]> {
+        None
+    }
+}
+
+impl<E, R: ExchangeError> ExchangeError for RequestError<E, R> {
+    fn is_rate_limited(&self) -> bool {
+        match self {
+            Self::RateLimitExceeded { .. } => true,
+            Self::ResponseHandleError(error) => error.is_rate_limited(),
+            _ => false,
+        }
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::RateLimitExceeded { wait } => Some(*wait),
+            Self::ResponseHandleError(error) => error.retry_after(),
+            _ => None,
+        }
+    }
+
+    fn is_auth_error(&self) -> bool {
+        match self {
+            Self::ResponseHandleError(error) => error.is_auth_error(),
+            _ => false,
+        }
+    }
+
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::SendRequest(_) | Self::ReceiveResponse(_) | Self::RateLimitExceeded { .. } => true,
+            Self::BuildRequestError(_) => false,
+            Self::ResponseHandleError(error) => error.is_retryable(),
+        }
+    }
+
+    fn exchange_code(&self) -> Option<i64> {
+        match self {
+            Self::ResponseHandleError(error) => error.exchange_code(),
+            _ => None,
+        }
+    }
+
+    fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::SendRequest(error) | Self::ReceiveResponse(error) => error.status(),
+            Self::ResponseHandleError(error) => error.status(),
+            _ => None,
+        }
+    }
+
+    fn raw_body(&self) -> Option<&[u8]> {
+        match self {
+            Self::ResponseHandleError(error) => error.raw_body(),
+            _ => None,
+        }
+    }
+}
@@ -1,3 +1,7 @@
+#[cfg(any(feature = "binance", feature = "bitflyer", feature = "bybit", feature = "coincheck"))]
+#[macro_use]
+mod macros;
+
 #[cfg(feature = "binance")]
 #[cfg_attr(docsrs, doc(cfg(feature = "binance")))]
 pub mod binance;
@@ -1,122 +1,141 @@
 // A module for communicating with the [Binance API](https://binance-docs.github.io/apidocs/spot/en/).
 
 use std::{
-    str::FromStr,
+    future::Future,
     marker::PhantomData,
+    sync::Arc,
     time::{SystemTime, Duration},
 };
-use hmac::{Hmac, Mac};
-use sha2::Sha256;
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use generic_api_client::{http::*, websocket::*};
-use crate::traits::*;
+use serde::{Deserialize, Serialize};
+use generic_api_client::{http::*, websocket::*, rate_limit::{RateLimit, RateLimitUsage, RateLimiter}};
+use crate::{traits::*, util, json, response::ResponseBody, error::{BuildError, ExchangeError}, clock::ServerClock, credentials::{self, CredentialProvider}, secret::Secret, signer::{self, Signer, Signing}};
 
 /// The type returned by [Client::request()].
 pub type BinanceRequestResult<T> = Result<T, BinanceRequestError>;
-pub type BinanceRequestError = RequestError<&'static str, BinanceHandlerError>;
-
-/// Options that can be set when creating handlers
-pub enum BinanceOption {
-    /// [Default] variant, does nothing
-    Default,
-    /// API key
-    Key(String),
-    /// Api secret
-    Secret(String),
-    /// Base url for HTTP requests
-    HttpUrl(BinanceHttpUrl),
-    /// Authentication type for HTTP requests
-    HttpAuth(BinanceAuth),
-    /// [RequestConfig] used when sending requests.
-    /// `url_prefix` will be overridden by [HttpUrl](Self::HttpUrl) unless `HttpUrl` is [BinanceHttpUrl::None].
-    RequestConfig(RequestConfig),
-    /// Base url for WebSocket connections
-    WebSocketUrl(BinanceWebSocketUrl),
-    /// [WebSocketConfig] used for creating [WebSocketConnection]s
-    /// `url_prefix` will be overridden by [WebSocketUrl](Self::WebSocketUrl) unless `WebSocketUrl` is [BinanceWebSocketUrl::None].
-    /// By default, `refresh_after` is set to 12 hours and `ignore_duplicate_during_reconnection` is set to `true`.
-    WebSocketConfig(WebSocketConfig),
-}
+pub type BinanceRequestError = RequestError<BuildError, BinanceHandlerError>;
 
-/// A `struct` that represents a set of [BinanceOption] s.
-#[derive(Clone, Debug)]
-pub struct BinanceOptions {
-    /// see [BinanceOption::Key]
-    pub key: Option<String>,
-    /// see [BinanceOption::Secret]
-    pub secret: Option<String>,
-    /// see [BinanceOption::HttpUrl]
-    pub http_url: BinanceHttpUrl,
-    /// see [BinanceOption::HttpAuth]
-    pub http_auth: BinanceAuth,
-    /// see [BinanceOption::RequestConfig]
-    pub request_config: RequestConfig,
-    /// see [BinanceOption::WebSocketUrl]
-    pub websocket_url: BinanceWebSocketUrl,
-    /// see [BinanceOption::WebSocketConfig]
-    pub websocket_config: WebSocketConfig,
+exchange_options! {
+    option: BinanceOption,
+    options: BinanceOptions,
+    request_handler: BinanceRequestHandler,
+    websocket_handler: BinanceWebSocketHandler(serde_json::Value) {},
+    variants: {
+        /// API key
+        Key(String) => key: Option<String> = None, |v| Some(v);
+        /// Api secret
+        Secret(String) => secret: Option<Secret> = None, |v| Some(Secret::new(v));
+        /// [CredentialProvider] consulted every time a request is signed or a WebSocket connection is authenticated.
+        /// If this is set, [Key](Self::Key) and [Secret](Self::Secret) are ignored.
+        Credentials(Arc<dyn CredentialProvider>) => credentials: Option<Arc<dyn CredentialProvider>> = None, |v| Some(v);
+        /// [Signer] used for authentication instead of the HMAC of [Secret](Self::Secret).
+        ///
+        /// See [UnixSocketSigner][crate::signer::UnixSocketSigner] for keeping the secret out of the process.
+        Signer(Arc<dyn Signer>) => signer: Option<Arc<dyn Signer>> = None, |v| Some(v);
+        /// PEM encoded Ed25519 or RSA private key used for authentication instead of [Secret](Self::Secret).
+        /// This sets [Signer](Self::Signer) with [signer_from_pem()][crate::signer::signer_from_pem()], so the `ed25519` or `rsa` feature is required.
+        ///
+        /// Signatures are encoded in base64 instead of hex for these keys. If the key could not be loaded, signed requests
+        /// fail to be built with [BuildError::InvalidKey]. Use [signer_from_pem()][crate::signer::signer_from_pem()] and
+        /// [Signer](Self::Signer) to handle the error earlier.
+        PrivateKeyPem(String) => signer, |v| Some(signer::signer_from_pem_or_invalid(v));
+        /// [ServerClock] used for timestamps in authentication.
+        ///
+        /// By default, each [Client][crate::Client] has its own clock which is shared by its clones.
+        /// See [Client::spawn_clock_sync()][crate::Client::spawn_clock_sync()] for synchronizing it.
+        ServerClock(ServerClock) => server_clock: ServerClock = ServerClock::new(), |v| v;
+        /// Base url for HTTP requests
+        HttpUrl(BinanceHttpUrl) => http_url: BinanceHttpUrl = BinanceHttpUrl::None, |v| v;
+        /// Authentication type for HTTP requests
+        HttpAuth(BinanceAuth) => http_auth: BinanceAuth = BinanceAuth::None, |v| v;
+        /// [RequestConfig] used when sending requests.
+        /// `url_prefix` will be overridden by [HttpUrl](Self::HttpUrl) unless `HttpUrl` is [BinanceHttpUrl::None].
+        /// When retrying is enabled with `max_try`, `418` and `429` responses are retried after the time in the `Retry-After` header.
+        RequestConfig(RequestConfig) => request_config: RequestConfig = RequestConfig::default(), |v| v;
+        /// Whether [BinanceRequestHandler] should apply client-side rate limits. [Default]s to `true`.
+        ///
+        /// The limits are shared by all clones of a [Client][crate::Client]. See [BinanceRequestHandler::rate_limits()] for details.
+        /// Requests wait until they are allowed unless [RequestConfig::max_rate_limit_wait] is set.
+        /// The current usage can be obtained with [Client::binance_usage()][crate::Client::binance_usage()].
+        RateLimit(bool) => rate_limit: bool = true, |v| v;
+        /// The weight of the request, used for client-side rate limiting.
+        /// If this is not set, the weight is estimated from the endpoint and the parameters of the request.
+        RequestWeight(u32) => request_weight: Option<u32> = None, |v| Some(v);
+        /// Base url for WebSocket connections
+        WebSocketUrl(BinanceWebSocketUrl) => websocket_url: BinanceWebSocketUrl = BinanceWebSocketUrl::None, |v| v;
+        /// [WebSocketConfig] used for creating [WebSocketConnection]s
+        /// `url_prefix` will be overridden by [WebSocketUrl](Self::WebSocketUrl) unless `WebSocketUrl` is [BinanceWebSocketUrl::None].
+        /// By default, `refresh_after` is set to 12 hours and `ignore_duplicate_during_reconnection` is set to `true`.
+        WebSocketConfig(WebSocketConfig) => websocket_config: WebSocketConfig = {
+            let mut websocket_config = WebSocketConfig::new();
+            websocket_config.refresh_after = Duration::from_secs(60 * 60 * 12);
+            websocket_config.ignore_duplicate_during_reconnection = true;
+            websocket_config
+        }, |v| v;
+    }
 }
 
-/// A `enum` that represents the base url of the Binance REST API.
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
-#[non_exhaustive]
-pub enum BinanceHttpUrl {
-    /// `https://api.binance.com`
-    Spot,
-    /// `https://api1.binance.com`
-    Spot1,
-    /// `https://api2.binance.com`
-    Spot2,
-    /// `https://api3.binance.com`
-    Spot3,
-    /// `https://api4.binance.com`
-    Spot4,
-    /// `https://testnet.binance.vision`
-    SpotTest,
-    /// `https://data.binance.com`
-    SpotData,
-    /// `https://fapi.binance.com`
-    FuturesUsdM,
-    /// `https://dapi.binance.com`
-    FuturesCoinM,
-    /// `https://testnet.binancefuture.com`
-    FuturesTest,
-    /// `https://eapi.binance.com`
-    EuropeanOptions,
-    /// The url will not be modified by [BinanceRequestHandler]
-    None,
+base_urls! {
+    /// A `enum` that represents the base url of the Binance REST API.
+    #[non_exhaustive]
+    pub enum BinanceHttpUrl {
+        /// `https://api.binance.com`
+        Spot => "https://api.binance.com",
+        /// `https://api1.binance.com`
+        Spot1 => "https://api1.binance.com",
+        /// `https://api2.binance.com`
+        Spot2 => "https://api2.binance.com",
+        /// `https://api3.binance.com`
+        Spot3 => "https://api3.binance.com",
+        /// `https://api4.binance.com`
+        Spot4 => "https://api4.binance.com",
+        /// `https://testnet.binance.vision`
+        SpotTest => "https://testnet.binance.vision",
+        /// `https://data.binance.com`
+        SpotData => "https://data.binance.com",
+        /// `https://fapi.binance.com`
+        FuturesUsdM => "https://fapi.binance.com",
+        /// `https://dapi.binance.com`
+        FuturesCoinM => "https://dapi.binance.com",
+        /// `https://testnet.binancefuture.com`
+        FuturesTest => "https://testnet.binancefuture.com",
+        /// `https://eapi.binance.com`
+        EuropeanOptions => "https://eapi.binance.com",
+        /// The url will not be modified by [BinanceRequestHandler]
+        None => "",
+    }
 }
 
-/// A `enum` that represents the base url of the Binance WebSocket API
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
-#[non_exhaustive]
-pub enum BinanceWebSocketUrl {
-    /// `wss://stream.binance.com:9443`
-    Spot9443,
-    /// `wss://stream.binance.com:443`
-    Spot443,
-    /// `wss://testnet.binance.vision`
-    SpotTest,
-    /// `wss://data-stream.binance.com`
-    SpotData,
-    /// `wss://ws-api.binance.com:443`
-    WebSocket443,
-    /// `wss://ws-api.binance.com:9443`
-    WebSocket9443,
-    /// `wss://fstream.binance.com`
-    FuturesUsdM,
-    /// `wss://fstream-auth.binance.com`
-    FuturesUsdMAuth,
-    /// `wss://dstream.binance.com`
-    FuturesCoinM,
-    /// `wss://stream.binancefuture.com`
-    FuturesUsdMTest,
-    /// `wss://dstream.binancefuture.com`
-    FuturesCoinMTest,
-    /// `wss://nbstream.binance.com`
-    EuropeanOptions,
-    /// The url will not be modified by [BinanceRequestHandler]
-    None,
+base_urls! {
+    /// A `enum` that represents the base url of the Binance WebSocket API
+    #[non_exhaustive]
+    pub enum BinanceWebSocketUrl {
+        /// `wss://stream.binance.com:9443`
+        Spot9443 => "wss://stream.binance.com:9443",
+        /// `wss://stream.binance.com:443`
+        Spot443 => "wss://stream.binance.com:443",
+        /// `wss://testnet.binance.vision`
+        SpotTest => "wss://testnet.binance.vision",
+        /// `wss://data-stream.binance.com`
+        SpotData => "wss://data-stream.binance.com",
+        /// `wss://ws-api.binance.com:443`
+        WebSocket443 => "wss://ws-api.binance.com:443",
+        /// `wss://ws-api.binance.com:9443`
+        WebSocket9443 => "wss://ws-api.binance.com:9443",
+        /// `wss://fstream.binance.com`
+        FuturesUsdM => "wss://fstream.binance.com",
+        /// `wss://fstream-auth.binance.com`
+        FuturesUsdMAuth => "wss://fstream-auth.binance.com",
+        /// `wss://dstream.binance.com`
+        FuturesCoinM => "wss://dstream.binance.com",
+        /// `wss://stream.binancefuture.com`
+        FuturesUsdMTest => "wss://stream.binancefuture.com",
+        /// `wss://dstream.binancefuture.com`
+        FuturesCoinMTest => "wss://dstream.binancefuture.com",
+        /// `wss://nbstream.binance.com`
+        EuropeanOptions => "wss://nbstream.binance.com",
+        /// The url will not be modified by [BinanceRequestHandler]
+        None => "",
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
@@ -130,7 +149,8 @@ pub enum BinanceAuth {
 pub enum BinanceHandlerError {
     ApiError(BinanceError),
     RateLimitError { retry_after: Option<u32> },
-    ParseError,
+    /// The response could not be parsed.
+    ParseError { status: StatusCode, body: Bytes },
 }
 
 #[derive(Deserialize, Debug)]
@@ -139,8 +159,339 @@ pub struct BinanceError {
     pub msg: String,
 }
 
+/// Documented values of [BinanceError::code]. See the documentation of the [spot](https://binance-docs.github.io/apidocs/spot/en/#error-codes)
+/// and [futures](https://binance-docs.github.io/apidocs/futures/en/#error-codes) APIs for the full list.
+///
+/// Codes which are not listed here are [Futures](Self::Futures) or [Other](Self::Other), and may get their own variant in a later version.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[non_exhaustive]
+pub enum BinanceErrorCode {
+    /// `-1000` UNKNOWN: An unknown error occurred while processing the request.
+    Unknown,
+    /// `-1001` DISCONNECTED: Internal error; unable to process the request.
+    Disconnected,
+    /// `-1002` UNAUTHORIZED: The API key is not authorized to execute this request.
+    Unauthorized,
+    /// `-1003` TOO_MANY_REQUESTS: Too many requests were sent.
+    TooManyRequests,
+    /// `-1006` UNEXPECTED_RESP: An unexpected response was received from the message bus. The execution status is unknown.
+    UnexpectedResponse,
+    /// `-1007` TIMEOUT: Timeout waiting for the response from the backend server. The execution status is unknown.
+    Timeout,
+    /// `-1008` SERVER_BUSY: The server is overloaded.
+    ServerBusy,
+    /// `-1013` INVALID_MESSAGE: The request was rejected by the API, for example because of a filter failure.
+    InvalidMessage,
+    /// `-1014` UNKNOWN_ORDER_COMPOSITION: The combination of order parameters is not supported.
+    UnknownOrderComposition,
+    /// `-1015` TOO_MANY_ORDERS: Too many new orders were sent.
+    TooManyOrders,
+    /// `-1016` SERVICE_SHUTTING_DOWN: The service is no longer available.
+    ServiceShuttingDown,
+    /// `-1020` UNSUPPORTED_OPERATION: The operation is not supported.
+    UnsupportedOperation,
+    /// `-1021` INVALID_TIMESTAMP: The timestamp of the request is outside of the `recvWindow`.
+    InvalidTimestamp,
+    /// `-1022` INVALID_SIGNATURE: The signature of the request is invalid.
+    InvalidSignature,
+    /// `-1100` ILLEGAL_CHARS: A parameter contains illegal characters.
+    IllegalChars,
+    /// `-1101` TOO_MANY_PARAMETERS: Too many parameters were sent.
+    TooManyParameters,
+    /// `-1102` MANDATORY_PARAM_EMPTY_OR_MALFORMED: A mandatory parameter was not sent, was empty or was malformed.
+    MandatoryParamEmptyOrMalformed,
+    /// `-1103` UNKNOWN_PARAM: An unknown parameter was sent.
+    UnknownParam,
+    /// `-1111` BAD_PRECISION: The precision of a parameter is over the maximum defined for the asset.
+    BadPrecision,
+    /// `-1121` BAD_SYMBOL: The symbol is invalid.
+    BadSymbol,
+    /// `-2010` NEW_ORDER_REJECTED: The new order was rejected, for example because of an insufficient balance.
+    NewOrderRejected,
+    /// `-2011` CANCEL_REJECTED: The cancellation was rejected.
+    CancelRejected,
+    /// `-2013` NO_SUCH_ORDER: The order does not exist.
+    NoSuchOrder,
+    /// `-2014` BAD_API_KEY_FMT: The format of the API key is invalid.
+    BadApiKeyFormat,
+    /// `-2015` REJECTED_MBX_KEY: The API key, the IP address or the permissions are invalid.
+    RejectedApiKey,
+    /// `-2019` MARGIN_NOT_SUFFICIENT: (futures) The margin is insufficient.
+    MarginNotSufficient,
+    /// `-2021` ORDER_WOULD_IMMEDIATELY_TRIGGER: (futures) The order would immediately trigger.
+    OrderWouldImmediatelyTrigger,
+    /// `-2022` REDUCE_ONLY_REJECT: (futures) The reduce-only order was rejected.
+    ReduceOnlyRejected,
+    /// `-4003` QTY_LESS_THAN_ZERO: (futures) The quantity is less than zero.
+    QuantityLessThanZero,
+    /// `-4004` QTY_LESS_THAN_MIN_QTY: (futures) The quantity is less than the minimum quantity.
+    QuantityLessThanMinQuantity,
+    /// `-4005` QTY_GREATER_THAN_MAX_QTY: (futures) The quantity is greater than the maximum quantity.
+    QuantityGreaterThanMaxQuantity,
+    /// `-4013` PRICE_LESS_THAN_MIN_PRICE: (futures) The price is less than the minimum price.
+    PriceLessThanMinPrice,
+    /// `-4014` PRICE_NOT_INCREASED_BY_TICK_SIZE: (futures) The price is not a multiple of the tick size.
+    PriceNotIncreasedByTickSize,
+    /// `-4015` INVALID_CL_ORD_ID_LEN: (futures) The client order id is longer than 36 characters.
+    InvalidClientOrderIdLength,
+    /// `-4016` PRICE_HIGHTER_THAN_MULTIPLIER_UP: (futures) The price is higher than the mark price multiplied by the upper limit.
+    PriceHigherThanMultiplierUp,
+    /// `-4023` QTY_NOT_INCREASED_BY_STEP_SIZE: (futures) The quantity is not a multiple of the step size.
+    QuantityNotIncreasedByStepSize,
+    /// `-4024` PRICE_LOWER_THAN_MULTIPLIER_DOWN: (futures) The price is lower than the mark price multiplied by the lower limit.
+    PriceLowerThanMultiplierDown,
+    /// `-4045` REACH_MAX_STOP_ORDER_LIMIT: (futures) The maximum number of stop orders was reached.
+    MaxStopOrderLimitReached,
+    /// `-4046` NO_NEED_TO_CHANGE_MARGIN_TYPE: (futures) The margin type is already the requested one.
+    NoNeedToChangeMarginType,
+    /// `-4050` CROSS_BALANCE_INSUFFICIENT: (futures) The cross margin balance is insufficient.
+    CrossBalanceInsufficient,
+    /// `-4051` ISOLATED_BALANCE_INSUFFICIENT: (futures) The isolated margin balance is insufficient.
+    IsolatedBalanceInsufficient,
+    /// `-4059` NO_NEED_TO_CHANGE_POSITION_SIDE: (futures) The position mode is already the requested one.
+    NoNeedToChangePositionSide,
+    /// `-4061` INVALID_POSITION_SIDE: (futures) The position side of the order does not match the position mode.
+    InvalidPositionSide,
+    /// `-4117` STOP_ORDER_TRIGGERING: (futures) The stop order is being triggered.
+    StopOrderTriggering,
+    /// `-4118` REDUCE_ONLY_MARGIN_CHECK_FAILED: (futures) The reduce-only order failed the margin check.
+    ReduceOnlyMarginCheckFailed,
+    /// `-4131` MARKET_ORDER_REJECT: (futures) The best price of the counterparty does not meet the `PERCENT_PRICE` filter.
+    MarketOrderRejected,
+    /// `-4164` MIN_NOTIONAL: (futures) The notional value of the order is below the minimum, unless it is reduce-only.
+    MinNotional,
+    /// Any other code from `-4000` to `-4999`: (futures) the request was rejected because of its parameters or the state of the position.
+    Futures(i32),
+    /// Any other code.
+    Other(i32),
+}
+
+impl BinanceErrorCode {
+    /// Returns the numeric code.
+    pub fn code(&self) -> i32 {
+        match self {
+            Self::Unknown => -1000,
+            Self::Disconnected => -1001,
+            Self::Unauthorized => -1002,
+            Self::TooManyRequests => -1003,
+            Self::UnexpectedResponse => -1006,
+            Self::Timeout => -1007,
+            Self::ServerBusy => -1008,
+            Self::InvalidMessage => -1013,
+            Self::UnknownOrderComposition => -1014,
+            Self::TooManyOrders => -1015,
+            Self::ServiceShuttingDown => -1016,
+            Self::UnsupportedOperation => -1020,
+            Self::InvalidTimestamp => -1021,
+            Self::InvalidSignature => -1022,
+            Self::IllegalChars => -1100,
+            Self::TooManyParameters => -1101,
+            Self::MandatoryParamEmptyOrMalformed => -1102,
+            Self::UnknownParam => -1103,
+            Self::BadPrecision => -1111,
+            Self::BadSymbol => -1121,
+            Self::NewOrderRejected => -2010,
+            Self::CancelRejected => -2011,
+            Self::NoSuchOrder => -2013,
+            Self::BadApiKeyFormat => -2014,
+            Self::RejectedApiKey => -2015,
+            Self::MarginNotSufficient => -2019,
+            Self::OrderWouldImmediatelyTrigger => -2021,
+            Self::ReduceOnlyRejected => -2022,
+            Self::QuantityLessThanZero => -4003,
+            Self::QuantityLessThanMinQuantity => -4004,
+            Self::QuantityGreaterThanMaxQuantity => -4005,
+            Self::PriceLessThanMinPrice => -4013,
+            Self::PriceNotIncreasedByTickSize => -4014,
+            Self::InvalidClientOrderIdLength => -4015,
+            Self::PriceHigherThanMultiplierUp => -4016,
+            Self::QuantityNotIncreasedByStepSize => -4023,
+            Self::PriceLowerThanMultiplierDown => -4024,
+            Self::MaxStopOrderLimitReached => -4045,
+            Self::NoNeedToChangeMarginType => -4046,
+            Self::CrossBalanceInsufficient => -4050,
+            Self::IsolatedBalanceInsufficient => -4051,
+            Self::NoNeedToChangePositionSide => -4059,
+            Self::InvalidPositionSide => -4061,
+            Self::StopOrderTriggering => -4117,
+            Self::ReduceOnlyMarginCheckFailed => -4118,
+            Self::MarketOrderRejected => -4131,
+            Self::MinNotional => -4164,
+            Self::Futures(code) | Self::Other(code) => *code,
+        }
+    }
+
+    /// Whether the request was rejected because of a rate limit.
+    pub fn is_rate_limited(&self) -> bool {
+        matches!(self, Self::TooManyRequests | Self::TooManyOrders)
+    }
+
+    /// Whether the request was rejected because of the API key, the signature or missing permissions.
+    pub fn is_auth_error(&self) -> bool {
+        matches!(self, Self::Unauthorized | Self::InvalidSignature | Self::BadApiKeyFormat | Self::RejectedApiKey)
+    }
+
+    /// Whether sending the same request again later may succeed.
+    ///
+    /// [UnexpectedResponse](Self::UnexpectedResponse) and [Timeout](Self::Timeout) are not retryable
+    /// because the request may have been executed.
+    /// [MarketOrderRejected](Self::MarketOrderRejected) depends on the order book at the time of the request.
+    pub fn is_retryable(&self) -> bool {
+        self.is_rate_limited() || matches!(
+            self,
+            Self::Disconnected | Self::ServerBusy | Self::InvalidTimestamp | Self::StopOrderTriggering | Self::MarketOrderRejected
+        )
+    }
+
+    /// Whether an order was rejected, or could not be canceled, because of its parameters or the state of the account or the order.
+    pub fn is_order_rejected(&self) -> bool {
+        matches!(
+            self,
+            Self::InvalidMessage | Self::UnknownOrderComposition | Self::NewOrderRejected | Self::CancelRejected | Self::NoSuchOrder
+            | Self::MarginNotSufficient | Self::OrderWouldImmediatelyTrigger | Self::ReduceOnlyRejected
+            | Self::QuantityLessThanZero | Self::QuantityLessThanMinQuantity | Self::QuantityGreaterThanMaxQuantity
+            | Self::PriceLessThanMinPrice | Self::PriceNotIncreasedByTickSize | Self::InvalidClientOrderIdLength
+            | Self::PriceHigherThanMultiplierUp | Self::QuantityNotIncreasedByStepSize | Self::PriceLowerThanMultiplierDown
+            | Self::MaxStopOrderLimitReached | Self::CrossBalanceInsufficient | Self::IsolatedBalanceInsufficient
+            | Self::InvalidPositionSide | Self::StopOrderTriggering | Self::ReduceOnlyMarginCheckFailed
+            | Self::MarketOrderRejected | Self::MinNotional | Self::Futures(_)
+        )
+    }
+}
+
+impl From<i32> for BinanceErrorCode {
+    fn from(code: i32) -> Self {
+        match code {
+            -1000 => Self::Unknown,
+            -1001 => Self::Disconnected,
+            -1002 => Self::Unauthorized,
+            -1003 => Self::TooManyRequests,
+            -1006 => Self::UnexpectedResponse,
+            -1007 => Self::Timeout,
+            -1008 => Self::ServerBusy,
+            -1013 => Self::InvalidMessage,
+            -1014 => Self::UnknownOrderComposition,
+            -1015 => Self::TooManyOrders,
+            -1016 => Self::ServiceShuttingDown,
+            -1020 => Self::UnsupportedOperation,
+            -1021 => Self::InvalidTimestamp,
+            -1022 => Self::InvalidSignature,
+            -1100 => Self::IllegalChars,
+            -1101 => Self::TooManyParameters,
+            -1102 => Self::MandatoryParamEmptyOrMalformed,
+            -1103 => Self::UnknownParam,
+            -1111 => Self::BadPrecision,
+            -1121 => Self::BadSymbol,
+            -2010 => Self::NewOrderRejected,
+            -2011 => Self::CancelRejected,
+            -2013 => Self::NoSuchOrder,
+            -2014 => Self::BadApiKeyFormat,
+            -2015 => Self::RejectedApiKey,
+            -2019 => Self::MarginNotSufficient,
+            -2021 => Self::OrderWouldImmediatelyTrigger,
+            -2022 => Self::ReduceOnlyRejected,
+            -4003 => Self::QuantityLessThanZero,
+            -4004 => Self::QuantityLessThanMinQuantity,
+            -4005 => Self::QuantityGreaterThanMaxQuantity,
+            -4013 => Self::PriceLessThanMinPrice,
+            -4014 => Self::PriceNotIncreasedByTickSize,
+            -4015 => Self::InvalidClientOrderIdLength,
+            -4016 => Self::PriceHigherThanMultiplierUp,
+            -4023 => Self::QuantityNotIncreasedByStepSize,
+            -4024 => Self::PriceLowerThanMultiplierDown,
+            -4045 => Self::MaxStopOrderLimitReached,
+            -4046 => Self::NoNeedToChangeMarginType,
+            -4050 => Self::CrossBalanceInsufficient,
+            -4051 => Self::IsolatedBalanceInsufficient,
+            -4059 => Self::NoNeedToChangePositionSide,
+            -4061 => Self::InvalidPositionSide,
+            -4117 => Self::StopOrderTriggering,
+            -4118 => Self::ReduceOnlyMarginCheckFailed,
+            -4131 => Self::MarketOrderRejected,
+            -4164 => Self::MinNotional,
+            -4999..=-4000 => Self::Futures(code),
+            code => Self::Other(code),
+        }
+    }
+}
+
+impl BinanceError {
+    /// Returns the [BinanceErrorCode] of [code](Self::code).
+    #[inline(always)]
+    pub fn error_code(&self) -> BinanceErrorCode {
+        BinanceErrorCode::from(self.code)
+    }
+}
+
+// https://binance-docs.github.io/apidocs/spot/en/#error-codes
+impl ExchangeError for BinanceHandlerError {
+    fn is_rate_limited(&self) -> bool {
+        match self {
+            Self::RateLimitError { .. } => true,
+            Self::ApiError(error) => error.error_code().is_rate_limited(),
+            Self::ParseError { status, .. } => *status == 429 || *status == 418,
+        }
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::RateLimitError { retry_after } => retry_after.map(|seconds| Duration::from_secs(seconds.into())),
+            _ => None,
+        }
+    }
+
+    fn is_auth_error(&self) -> bool {
+        match self {
+            Self::ApiError(error) => error.error_code().is_auth_error(),
+            Self::ParseError { status, .. } => *status == 401,
+            _ => false,
+        }
+    }
+
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::ApiError(error) => error.error_code().is_retryable(),
+            // the status of requests which failed with 5xx is unknown, but sending them again may succeed
+            Self::ParseError { status, .. } => status.is_server_error() || self.is_rate_limited(),
+            Self::RateLimitError { .. } => true,
+        }
+    }
+
+    fn exchange_code(&self) -> Option<i64> {
+        match self {
+            Self::ApiError(error) => Some(error.code.into()),
+            _ => None,
+        }
+    }
+
+    fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::ParseError { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    fn raw_body(&self) -> Option<&[u8]> {
+        match self {
+            Self::ParseError { body, .. } => Some(body),
+            _ => None,
+        }
+    }
+}
+
+/// The usage of Binance's rate limits tracked by a [Client][crate::Client].
+///
+/// Returned by [Client::binance_usage()][crate::Client::binance_usage()].
+#[derive(Debug, Clone, Default)]
+pub struct BinanceUsage {
+    /// The usage of the `REQUEST_WEIGHT` limit per minute.
+    pub weight: Option<RateLimitUsage>,
+    /// The usage of the `ORDERS` limits, as pairs of the interval (such as `"10s"`) and the usage.
+    pub orders: Vec<(&'static str, RateLimitUsage)>,
+}
+
 /// A `struct` that implements [RequestHandler]
-pub struct BinanceRequestHandler<'a, R: DeserializeOwned> {
+pub struct BinanceRequestHandler<'a, R: ResponseBody> {
     options: BinanceOptions,
     _phantom: PhantomData<&'a R>,
 }
@@ -151,29 +502,17 @@ pub struct BinanceWebSocketHandler {
     options: BinanceOptions,
 }
 
-// https://binance-docs.github.io/apidocs/spot/en/#general-api-information
-impl<'a, B, R> RequestHandler<B> for BinanceRequestHandler<'a, R>
-where
-    B: Serialize,
-    R: DeserializeOwned,
-{
-    type Successful = R;
-    type Unsuccessful = BinanceHandlerError;
-    type BuildError = &'static str;
-
-    fn request_config(&self) -> RequestConfig {
-        let mut config = self.options.request_config.clone();
-        if self.options.http_url != BinanceHttpUrl::None {
-            config.url_prefix = self.options.http_url.as_str().to_owned();
+impl<'a, R> BinanceRequestHandler<'a, R> where R: ResponseBody {
+    async fn build<B>(&self, mut builder: RequestBuilder, request_body: &Option<B>, signing: Signing) -> Result<Request, BuildError>
+    where
+        B: Serialize,
+    {
+        if let Some(name) = &self.options.unknown_account {
+            return Err(BuildError::UnknownAccount(name.clone()));
         }
-        config
-    }
-
-    fn build_request(&self, mut builder: RequestBuilder, request_body: &Option<B>, _: u8) -> Result<Request, Self::BuildError> {
         if let Some(body) = request_body {
-            let encoded = serde_urlencoded::to_string(body).or(
-                Err("could not serialize body as application/x-www-form-urlencoded"),
-            )?;
+            let encoded = serde_urlencoded::to_string(body)
+                .map_err(|error| BuildError::serialize("application/x-www-form-urlencoded", error))?;
             builder = builder
                 .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
                 .body(encoded);
@@ -181,69 +520,136 @@ where
 
         if self.options.http_auth != BinanceAuth::None {
             // https://binance-docs.github.io/apidocs/spot/en/#signed-trade-user_data-and-margin-endpoint-security
-            let key = self.options.key.as_deref().ok_or("API key not set")?;
-            builder = builder.header("X-MBX-APIKEY", key);
+            let credentials = credentials::resolve(&self.options.credentials, &self.options.key, &self.options.secret)?;
+            builder = builder.header("X-MBX-APIKEY", &credentials.key);
 
             if self.options.http_auth == BinanceAuth::Sign {
-                let time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap(); // always after the epoch
-                let timestamp = time.as_millis();
+                let timestamp = self.options.server_clock.now_millis();
 
                 builder = builder.query(&[("timestamp", timestamp)]);
 
-                let secret = self.options.secret.as_deref().ok_or("API secret not set")?;
-                let mut hmac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap(); // hmac accepts key of any length
+                let signer = signer::signer_or_hmac(&self.options.signer, credentials.secret.as_ref())?;
 
-                let mut request = builder.build().or(Err("Failed to build request"))?;
+                let mut request = builder.build().map_err(BuildError::Request)?;
                 let query = request.url().query().unwrap(); // we added the timestamp query
                 let body = request.body().and_then(|body| body.as_bytes()).unwrap_or_default();
 
-                hmac.update(&[query.as_bytes(), body].concat());
-                let signature = hex::encode(hmac.finalize().into_bytes());
+                // https://binance-docs.github.io/apidocs/spot/en/#signed-endpoint-examples-for-post-api-v3-order-rsa-keys
+                let signature = signer::sign_encoded(&*signer, &[query.as_bytes(), body].concat(), signing).await?;
 
                 request.url_mut().query_pairs_mut().append_pair("signature", &signature);
 
                 return Ok(request);
             }
         }
-        builder.build().or(Err("failed to build request"))
+        builder.build().map_err(BuildError::Request)
     }
+}
 
-    fn handle_response(&self, status: StatusCode, headers: HeaderMap, response_body: Bytes) -> Result<Self::Successful, Self::Unsuccessful> {
-        if status.is_success() {
-            serde_json::from_slice(&response_body).map_err(|error| {
-                log::debug!("Failed to parse response due to an error: {}", error);
-                BinanceHandlerError::ParseError
-            })
-        } else {
-            // https://binance-docs.github.io/apidocs/spot/en/#limits
-            if status == 429 || status == 418 {
-                let retry_after = if let Some(value) = headers.get("Retry-After") {
-                    if let Ok(string) = value.to_str() {
-                        if let Ok(retry_after) = u32::from_str(string) {
-                            Some(retry_after)
-                        } else {
-                            log::debug!("Invalid number in Retry-After header");
-                            None
-                        }
-                    } else {
-                        log::debug!("Non-ASCII character in Retry-After header");
-                        None
-                    }
-                } else {
-                    None
-                };
-                return Err(BinanceHandlerError::RateLimitError { retry_after });
+// https://binance-docs.github.io/apidocs/spot/en/#general-api-information
+impl<'a, B, R> RequestHandler<B> for BinanceRequestHandler<'a, R>
+where
+    B: Serialize,
+    R: ResponseBody,
+{
+    type Successful = R;
+    type Unsuccessful = BinanceHandlerError;
+    type BuildError = BuildError;
+
+    fn request_config(&self) -> RequestConfig {
+        let mut config = self.options.request_config.clone();
+        if self.options.http_url != BinanceHttpUrl::None {
+            config.url_prefix = self.options.http_url.as_str().to_owned();
+        }
+        config
+    }
+
+    /// Applies the `REQUEST_WEIGHT` limit of the base url, and the `ORDERS` limits of the API key if the request places an order.
+    ///
+    /// The weight of a request is [BinanceOption::RequestWeight] if it is set. Otherwise, it is estimated from the endpoint and parameters.
+    /// The usage is synchronized with the `X-MBX-USED-WEIGHT-*` and `X-MBX-ORDER-COUNT-*` headers of the responses.
+    fn rate_limits(&self, request: &Request) -> Vec<RateLimit> {
+        if !self.options.rate_limit {
+            return vec![];
+        }
+        let group = match LimitGroup::from_host(request.url().host_str().unwrap_or_default()) {
+            Some(group) => group,
+            None => return vec![],
+        };
+        let weight = self.options.request_weight.unwrap_or_else(|| request_weight(request));
+        let mut limits = vec![RateLimit::new(group.weight_key("1m"), group.weight_per_minute, Duration::from_secs(60), weight)];
+        if let Ok(credentials) = credentials::resolve(&self.options.credentials, &self.options.key, &self.options.secret) {
+            if is_order(request) {
+                let key = util::key_id(&credentials.key);
+                for &(interval_name, limit, interval) in group.order_limits {
+                    limits.push(RateLimit::new(group.order_key(&key, interval_name), limit, interval, 1));
+                }
             }
+        }
+        limits
+    }
 
-            let error = match serde_json::from_slice(&response_body) {
-                Ok(parsed_error) => BinanceHandlerError::ApiError(parsed_error),
-                Err(error) => {
-                    log::debug!("Failed to parse error response due to an error: {}", error);
-                    BinanceHandlerError::ParseError
+    fn reported_rate_limit_usage(&self, url: &Url, _: StatusCode, headers: &HeaderMap) -> Vec<(String, u32)> {
+        // https://binance-docs.github.io/apidocs/spot/en/#limits
+        let group = match LimitGroup::from_host(url.host_str().unwrap_or_default()) {
+            Some(group) => group,
+            None => return vec![],
+        };
+        let key = credentials::resolve(&self.options.credentials, &self.options.key, &self.options.secret).ok().map(|credentials| util::key_id(&credentials.key));
+        headers.iter().filter_map(|(name, value)| {
+            let used = value.to_str().ok()?.parse().ok()?;
+            let name = name.as_str(); // always lowercase
+            if let Some(interval) = name.strip_prefix("x-mbx-used-weight-") {
+                Some((group.weight_key(interval), used))
+            } else if let Some(interval) = name.strip_prefix("x-mbx-order-count-") {
+                Some((group.order_key(key.as_deref()?, interval), used))
+            } else {
+                None
+            }
+        }).collect()
+    }
+
+    fn build_request(&self, builder: RequestBuilder, request_body: &Option<B>, _: u8) -> Result<Request, Self::BuildError> {
+        signer::blocking(self.build(builder, request_body, Signing::Blocking))
+    }
+
+    fn build_request_async(&self, builder: RequestBuilder, request_body: &Option<B>, _: u8) -> impl Future<Output = Result<Request, Self::BuildError>> {
+        self.build(builder, request_body, Signing::Async)
+    }
+
+    fn retry_decision(&self, method: &Method, status: StatusCode, headers: &HeaderMap, response_body: &Bytes) -> RetryDecision {
+        // https://binance-docs.github.io/apidocs/spot/en/#limits
+        if status == 418 {
+            // the IP has been auto-banned. the ban is lifted after Retry-After
+            return parse_retry_after(headers).map_or(RetryDecision::DoNotRetry, RetryDecision::RetryAfter);
+        }
+        if status == 400 {
+            if let Ok(error) = json::from_slice::<BinanceError>(response_body) {
+                if error.error_code() == BinanceErrorCode::InvalidTimestamp {
+                    // Timestamp for this request is outside of the recvWindow.
+                    // the request is signed again with the corrected clock when retrying
+                    log::debug!("Timestamp rejected by the server: {}", error.msg);
+                    self.options.server_clock.observe_date_header(headers);
+                    self.options.server_clock.request_resync();
+                    return RetryDecision::Retry;
                 }
-            };
-            Err(error)
+            }
         }
+        default_retry_decision(method, status, headers)
+    }
+
+    fn handle_response(&self, status: StatusCode, headers: HeaderMap, response_body: Bytes) -> Result<Self::Successful, Self::Unsuccessful> {
+        let parse_error = |status, body| BinanceHandlerError::ParseError { status, body };
+        util::handle_json_response(status, &headers, response_body, parse_error, |status, headers, parsed| {
+            // https://binance-docs.github.io/apidocs/spot/en/#limits
+            if status == 429 || status == 418 {
+                // in whole seconds, rounded up
+                let retry_after = parse_retry_after(headers)
+                    .map(|wait| u32::try_from(wait.as_secs() + u64::from(wait.subsec_nanos() > 0)).unwrap_or(u32::MAX));
+                return Some(BinanceHandlerError::RateLimitError { retry_after });
+            }
+            parsed.map(BinanceHandlerError::ApiError)
+        })
     }
 }
 
@@ -259,7 +665,7 @@ impl WebSocketHandler for BinanceWebSocketHandler {
     fn handle_message(&mut self, message: WebSocketMessage) -> Vec<WebSocketMessage> {
         match message {
             WebSocketMessage::Text(message) => {
-                if let Ok(message) = serde_json::from_str(&message) {
+                if let Ok(message) = json::from_string(message) {
                     (self.message_handler)(message);
                 } else {
                     log::debug!("Invalid JSON message received");
@@ -270,119 +676,195 @@ impl WebSocketHandler for BinanceWebSocketHandler {
         }
         vec![]
     }
-}
 
-impl BinanceHttpUrl {
-    /// The URL that this variant represents.
-    #[inline(always)]
-    fn as_str(&self) -> &'static str {
-        match self {
-            Self::Spot => "https://api.binance.com",
-            Self::Spot1 => "https://api1.binance.com",
-            Self::Spot2 => "https://api2.binance.com",
-            Self::Spot3 => "https://api3.binance.com",
-            Self::Spot4 => "https://api4.binance.com",
-            Self::SpotTest => "https://testnet.binance.vision",
-            Self::SpotData => "https://data.binance.com",
-            Self::FuturesUsdM => "https://fapi.binance.com",
-            Self::FuturesCoinM => "https://dapi.binance.com",
-            Self::FuturesTest => "https://testnet.binancefuture.com",
-            Self::EuropeanOptions => "https://eapi.binance.com",
-            Self::None => "",
+    /// Identifies messages which have an update id (`u`) by the stream, event type, symbol, event time (`E`) and the update id.
+    fn dedup_key(&self, message: &WebSocketMessage) -> Option<DedupKey> {
+        #[derive(Deserialize)]
+        struct Event<'a> {
+            stream: Option<&'a str>,
+            #[serde(borrow)]
+            data: Option<Box<Event<'a>>>,
+            #[serde(rename = "e")]
+            event_type: Option<&'a str>,
+            #[serde(rename = "s")]
+            symbol: Option<&'a str>,
+            #[serde(rename = "E")]
+            event_time: Option<u64>,
+            #[serde(rename = "u")]
+            update_id: Option<u64>,
         }
+
+        let WebSocketMessage::Text(message) = message else {
+            return None;
+        };
+        let message: Event = serde_json::from_str(message).ok()?;
+        // messages of combined streams are wrapped in {"stream":"<streamName>","data":<rawPayload>}
+        let event = message.data.as_deref().unwrap_or(&message);
+        Some(DedupKey::new((message.stream, event.event_type, event.symbol, event.event_time, event.update_id?)))
     }
 }
 
-impl BinanceWebSocketUrl {
-    /// The URL that this variant represents.
-    #[inline(always)]
-    pub fn as_str(&self) -> &'static str {
-        match self {
-            Self::Spot9443 => "wss://stream.binance.com:9443",
-            Self::Spot443 => "wss://stream.binance.com:443",
-            Self::SpotTest => "wss://testnet.binance.vision",
-            Self::SpotData => "wss://data-stream.binance.com",
-            Self::WebSocket443 => "wss://ws-api.binance.com:443",
-            Self::WebSocket9443 => "wss://ws-api.binance.com:9443",
-            Self::FuturesUsdM => "wss://fstream.binance.com",
-            Self::FuturesUsdMAuth => "wss://fstream-auth.binance.com",
-            Self::FuturesCoinM => "wss://dstream.binance.com",
-            Self::FuturesUsdMTest => "wss://stream.binancefuture.com",
-            Self::FuturesCoinMTest => "wss://dstream.binancefuture.com",
-            Self::EuropeanOptions => "wss://nbstream.binance.com",
-            Self::None => "",
-        }
+/// Returns the usage of Binance's rate limits tracked by `limiter`. See [Client::binance_usage()][crate::Client::binance_usage()].
+pub(crate) fn usage(limiter: &RateLimiter, url: BinanceHttpUrl, key: Option<&str>) -> BinanceUsage {
+    let host = url.as_str().trim_start_matches("https://");
+    let group = match LimitGroup::from_host(host) {
+        Some(group) => group,
+        None => return BinanceUsage::default(),
+    };
+    let orders = match key.map(util::key_id) {
+        Some(key) => group.order_limits.iter()
+            .filter_map(|&(interval, ..)| Some((interval, limiter.usage(&group.order_key(&key, interval))?)))
+            .collect(),
+        None => vec![],
+    };
+    BinanceUsage {
+        weight: limiter.usage(&group.weight_key("1m")),
+        orders,
     }
 }
 
-impl HandlerOptions for BinanceOptions {
-    type OptionItem = BinanceOption;
-
-    fn update(&mut self, option: Self::OptionItem) {
-        match option {
-            BinanceOption::Default => (),
-            BinanceOption::Key(v) => self.key = Some(v),
-            BinanceOption::Secret(v) => self.secret = Some(v),
-            BinanceOption::HttpUrl(v) => self.http_url = v,
-            BinanceOption::HttpAuth(v) => self.http_auth = v,
-            BinanceOption::RequestConfig(v) => self.request_config = v,
-            BinanceOption::WebSocketUrl(v) => self.websocket_url = v,
-            BinanceOption::WebSocketConfig(v) => self.websocket_config = v,
-        }
-    }
+/// A set of base urls which share rate limits.
+struct LimitGroup {
+    name: &'static str,
+    weight_per_minute: u32,
+    /// (interval name in the headers, limit, interval)
+    order_limits: &'static [(&'static str, u32, Duration)],
 }
 
-impl Default for BinanceOptions {
-    fn default() -> Self {
-        let mut websocket_config = WebSocketConfig::new();
-        websocket_config.refresh_after = Duration::from_secs(60 * 60 * 12);
-        websocket_config.ignore_duplicate_during_reconnection = true;
-        Self {
-            key: None,
-            secret: None,
-            http_url: BinanceHttpUrl::None,
-            http_auth: BinanceAuth::None,
-            request_config: RequestConfig::default(),
-            websocket_url: BinanceWebSocketUrl::None,
-            websocket_config,
-        }
+impl LimitGroup {
+    // https://binance-docs.github.io/apidocs/spot/en/#limits
+    // https://binance-docs.github.io/apidocs/futures/en/#limits
+    // https://binance-docs.github.io/apidocs/delivery/en/#limits
+    const SPOT_ORDERS: &'static [(&'static str, u32, Duration)] = &[
+        ("10s", 50, Duration::from_secs(10)),
+        ("1d", 160_000, Duration::from_secs(60 * 60 * 24)),
+    ];
+    const FUTURES_USDM_ORDERS: &'static [(&'static str, u32, Duration)] = &[
+        ("10s", 300, Duration::from_secs(10)),
+        ("1m", 1200, Duration::from_secs(60)),
+    ];
+    const FUTURES_COINM_ORDERS: &'static [(&'static str, u32, Duration)] = &[
+        ("1m", 1200, Duration::from_secs(60)),
+    ];
+
+    fn from_host(host: &str) -> Option<Self> {
+        let (name, weight_per_minute, order_limits) = match host {
+            "api.binance.com" | "api1.binance.com" | "api2.binance.com" | "api3.binance.com" | "api4.binance.com" => ("spot", 6000, Self::SPOT_ORDERS),
+            "testnet.binance.vision" => ("spot_test", 6000, Self::SPOT_ORDERS),
+            "data.binance.com" => ("spot_data", 6000, Self::SPOT_ORDERS),
+            "fapi.binance.com" => ("futures_usdm", 2400, Self::FUTURES_USDM_ORDERS),
+            "dapi.binance.com" => ("futures_coinm", 2400, Self::FUTURES_COINM_ORDERS),
+            "testnet.binancefuture.com" => ("futures_test", 2400, Self::FUTURES_USDM_ORDERS),
+            "eapi.binance.com" => ("options", 400, &[][..]),
+            _ => return None,
+        };
+        Some(Self { name, weight_per_minute, order_limits })
     }
-}
 
-impl<'a, R, B> HttpOption<'a, R, B> for BinanceOption
-where
-    R: DeserializeOwned + 'a,
-    B: Serialize,
-{
-    type RequestHandler = BinanceRequestHandler<'a, R>;
+    fn weight_key(&self, interval: &str) -> String {
+        format!("binance:{}:weight:{}", self.name, interval.to_ascii_lowercase())
+    }
 
-    #[inline(always)]
-    fn request_handler(options: Self::Options) -> Self::RequestHandler {
-        BinanceRequestHandler::<'a, R> {
-            options,
-            _phantom: PhantomData,
-        }
+    fn order_key(&self, key_id: &str, interval: &str) -> String {
+        format!("binance:{}:{}:orders:{}", self.name, key_id, interval.to_ascii_lowercase())
     }
 }
 
-impl<H: FnMut(serde_json::Value) + Send + 'static> WebSocketOption<H> for BinanceOption {
-    type WebSocketHandler = BinanceWebSocketHandler;
+/// Returns `true` if the request counts towards the `ORDERS` limits.
+fn is_order(request: &Request) -> bool {
+    matches!(*request.method(), Method::POST | Method::PUT) && matches!(
+        request.url().path(),
+        "/api/v3/order" | "/api/v3/order/oco" | "/api/v3/order/cancelReplace" | "/api/v3/orderList/oco"
+        | "/api/v3/orderList/oto" | "/api/v3/orderList/otoco" | "/api/v3/sor/order"
+        | "/fapi/v1/order" | "/fapi/v1/batchOrders" | "/dapi/v1/order" | "/dapi/v1/batchOrders",
+    )
+}
 
-    #[inline(always)]
-    fn websocket_handler(handler: H, options: Self::Options) -> Self::WebSocketHandler {
-        BinanceWebSocketHandler {
-            message_handler: Box::new(handler),
-            options,
-        }
+/// Estimates the weight of a request for the `REQUEST_WEIGHT` limit. Returns `1` for unknown endpoints.
+///
+/// The weights are the ones documented in December 2023.
+fn request_weight(request: &Request) -> u32 {
+    let url = request.url();
+    let param = |name: &str| url.query_pairs().find(|(key, _)| key == name).map(|(_, value)| value.into_owned());
+    let has_symbol = param("symbol").is_some();
+    let limit = |default: u32| param("limit").and_then(|limit| limit.parse().ok()).unwrap_or(default);
+
+    match (request.method(), url.path()) {
+        // spot
+        (_, "/api/v3/exchangeInfo") => 20,
+        (_, "/api/v3/depth") => match limit(100) {
+            0..=100 => 5,
+            101..=500 => 25,
+            501..=1000 => 50,
+            _ => 250,
+        },
+        (_, "/api/v3/trades" | "/api/v3/historicalTrades") => 25,
+        (_, "/api/v3/aggTrades" | "/api/v3/klines" | "/api/v3/uiKlines" | "/api/v3/avgPrice") => 2,
+        (_, "/api/v3/ticker/24hr" | "/api/v3/ticker/tradingDay") if has_symbol => 2,
+        (_, "/api/v3/ticker/24hr" | "/api/v3/ticker/tradingDay") => 80,
+        (_, "/api/v3/ticker/price" | "/api/v3/ticker/bookTicker") if has_symbol => 2,
+        (_, "/api/v3/ticker/price" | "/api/v3/ticker/bookTicker") => 4,
+        (&Method::GET, "/api/v3/order") => 4,
+        (&Method::GET, "/api/v3/openOrders") if has_symbol => 6,
+        (&Method::GET, "/api/v3/openOrders") => 80,
+        (_, "/api/v3/allOrders" | "/api/v3/account" | "/api/v3/myTrades") => 20,
+        (_, "/api/v3/rateLimit/order") => 40,
+        (_, "/api/v3/userDataStream") => 2,
+        // USDⓈ-M futures
+        (_, "/fapi/v1/depth") => match limit(500) {
+            0..=50 => 2,
+            51..=100 => 5,
+            101..=500 => 10,
+            _ => 20,
+        },
+        (_, "/fapi/v1/klines" | "/fapi/v1/continuousKlines" | "/fapi/v1/indexPriceKlines" | "/fapi/v1/markPriceKlines") => match limit(500) {
+            0..=99 => 1,
+            100..=499 => 2,
+            500..=1000 => 5,
+            _ => 10,
+        },
+        (_, "/fapi/v1/aggTrades" | "/fapi/v1/trades" | "/fapi/v1/historicalTrades") => 20,
+        (_, "/fapi/v1/ticker/24hr") if has_symbol => 1,
+        (_, "/fapi/v1/ticker/24hr") => 40,
+        (_, "/fapi/v1/ticker/price") if has_symbol => 1,
+        (_, "/fapi/v1/ticker/price") => 2,
+        (_, "/fapi/v1/ticker/bookTicker") if has_symbol => 2,
+        (_, "/fapi/v1/ticker/bookTicker") => 5,
+        (&Method::POST, "/fapi/v1/order") => 0,
+        (&Method::POST, "/fapi/v1/batchOrders") => 5,
+        (&Method::GET, "/fapi/v1/openOrders") if has_symbol => 1,
+        (&Method::GET, "/fapi/v1/openOrders") => 40,
+        (_, "/fapi/v1/allOrders" | "/fapi/v1/userTrades" | "/fapi/v2/account" | "/fapi/v2/balance" | "/fapi/v2/positionRisk") => 5,
+        _ => 1,
     }
 }
 
-impl HandlerOption for BinanceOption {
-    type Options = BinanceOptions;
-}
+impl ServerTimeOption for BinanceOption {
+    fn server_time_url(options: &Self::Options) -> String {
+        let path = match options.http_url {
+            BinanceHttpUrl::FuturesUsdM | BinanceHttpUrl::FuturesTest => "/fapi/v1/time",
+            BinanceHttpUrl::FuturesCoinM => "/dapi/v1/time",
+            BinanceHttpUrl::EuropeanOptions => "/eapi/v1/time",
+            _ => "/api/v3/time",
+        };
+        let prefix = match options.http_url {
+            BinanceHttpUrl::None => BinanceHttpUrl::Spot.as_str(),
+            url => url.as_str(),
+        };
+        format!("{prefix}{path}")
+    }
+
+    fn parse_server_time(_: &HeaderMap, response_body: &[u8]) -> Option<SystemTime> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct ServerTime {
+            server_time: u64,
+        }
+        let response: ServerTime = serde_json::from_slice(response_body).ok()?;
+        Some(SystemTime::UNIX_EPOCH + Duration::from_millis(response.server_time))
+    }
 
-impl Default for BinanceOption {
-    fn default() -> Self {
-        Self::Default
+    fn server_clock(options: &Self::Options) -> &ServerClock {
+        &options.server_clock
     }
 }
@@ -2,88 +2,91 @@
 //! For example usages, see files in the examples/ directory.
 
 use std::{
+    future::Future,
     marker::PhantomData,
-    time::SystemTime,
+    sync::Arc,
+    time::{SystemTime, Duration},
 };
-use hmac::{Hmac, Mac};
-use sha2::Sha256;
-use rand::{Rng, distributions::Alphanumeric};
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use generic_api_client::{http::{*, header::HeaderValue}, websocket::*};
-use crate::traits::*;
+use generic_api_client::{http::{*, header::HeaderValue}, websocket::*, rate_limit::RateLimit};
+use crate::{traits::*, util, json, response::ResponseBody, error::{BuildError, ExchangeError}, clock::ServerClock, nonce::{NonceSource, RandomNonce}, credentials::{self, CredentialProvider}, secret::Secret, signer::{self, Signer, Signing}};
 
 /// The type returned by [Client::request()].
 pub type BitFlyerRequestResult<T> = Result<T, BitFlyerRequestError>;
-pub type BitFlyerRequestError = RequestError<&'static str, BitFlyerHandlerError>;
-
-/// Options that can be set when creating handlers
-pub enum BitFlyerOption {
-    /// [Default] variant, does nothing
-    Default,
-    /// API key
-    Key(String),
-    /// Api secret
-    Secret(String),
-    /// Base url for HTTP requests
-    HttpUrl(BitFlyerHttpUrl),
-    /// Whether [BitFlyerRequestHandler] should perform authentication
-    HttpAuth(bool),
-    /// [RequestConfig] used when sending requests.
-    /// `url_prefix` will be overridden by [HttpUrl](Self::HttpUrl) unless `HttpUrl` is [BitFlyerHttpUrl::None].
-    RequestConfig(RequestConfig),
-    /// Base url for WebSocket connections
-    WebSocketUrl(BitFlyerWebSocketUrl),
-    /// Whether [BitFlyerWebSocketHandler] should perform authentication
-    WebSocketAuth(bool),
-    /// The channels to be subscribed by [BitFlyerWebSocketHandler].
-    WebSocketChannels(Vec<String>),
-    /// [WebSocketConfig] used for creating [WebSocketConnection]s
-    /// `url_prefix` will be overridden by [WebSocketUrl](Self::WebSocketUrl) unless `WebSocketUrl` is [BitFlyerWebSocketUrl::None].
-    /// By default, ignore_duplicate_during_reconnection` is set to `true`.
-    WebSocketConfig(WebSocketConfig),
-}
+pub type BitFlyerRequestError = RequestError<BuildError, BitFlyerHandlerError>;
 
-/// A `struct` that represents a set of [BitFlyerOption] s.
-#[derive(Clone, Debug)]
-pub struct BitFlyerOptions {
-    /// see [BitFlyerOption::Key]
-    pub key: Option<String>,
-    /// see [BitFlyerOption::Secret]
-    pub secret: Option<String>,
-    /// see [BitFlyerOption::HttpUrl]
-    pub http_url: BitFlyerHttpUrl,
-    /// see [BitFlyerOption::HttpAuth]
-    pub http_auth: bool,
-    /// see [BitFlyerOption::RequestConfig]
-    pub request_config: RequestConfig,
-    /// see [BitFlyerOption::WebSocketUrl]
-    pub websocket_url: BitFlyerWebSocketUrl,
-    /// see [BitFlyerOption::WebSocketAuth]
-    pub websocket_auth: bool,
-    /// see [BitFlyerOption::WebSocketChannels]
-    pub websocket_channels: Vec<String>,
-    /// see [BitFlyerOption::WebSocketConfig]
-    pub websocket_config: WebSocketConfig,
+exchange_options! {
+    option: BitFlyerOption,
+    options: BitFlyerOptions,
+    request_handler: BitFlyerRequestHandler,
+    websocket_handler: BitFlyerWebSocketHandler(BitFlyerChannelMessage) { auth_id: None },
+    variants: {
+        /// API key
+        Key(String) => key: Option<String> = None, |v| Some(v);
+        /// Api secret
+        Secret(String) => secret: Option<Secret> = None, |v| Some(Secret::new(v));
+        /// [CredentialProvider] consulted every time a request is signed or a WebSocket connection is authenticated.
+        /// If this is set, [Key](Self::Key) and [Secret](Self::Secret) are ignored.
+        Credentials(Arc<dyn CredentialProvider>) => credentials: Option<Arc<dyn CredentialProvider>> = None, |v| Some(v);
+        /// [Signer] used for authentication instead of the HMAC of [Secret](Self::Secret).
+        ///
+        /// See [UnixSocketSigner][crate::signer::UnixSocketSigner] for keeping the secret out of the process.
+        Signer(Arc<dyn Signer>) => signer: Option<Arc<dyn Signer>> = None, |v| Some(v);
+        /// [ServerClock] used for timestamps in authentication.
+        ///
+        /// By default, each [Client][crate::Client] has its own clock which is shared by its clones.
+        /// See [Client::spawn_clock_sync()][crate::Client::spawn_clock_sync()] for synchronizing it.
+        ServerClock(ServerClock) => server_clock: ServerClock = ServerClock::new(), |v| v;
+        /// [NonceSource] used for nonces in WebSocket authentication. [Default]s to [RandomNonce].
+        NonceSource(Arc<dyn NonceSource>) => nonce_source: Arc<dyn NonceSource> = Arc::new(RandomNonce), |v| v;
+        /// Base url for HTTP requests
+        HttpUrl(BitFlyerHttpUrl) => http_url: BitFlyerHttpUrl = BitFlyerHttpUrl::Default, |v| v;
+        /// Whether [BitFlyerRequestHandler] should perform authentication
+        HttpAuth(bool) => http_auth: bool = false, |v| v;
+        /// [RequestConfig] used when sending requests.
+        /// `url_prefix` will be overridden by [HttpUrl](Self::HttpUrl) unless `HttpUrl` is [BitFlyerHttpUrl::None].
+        RequestConfig(RequestConfig) => request_config: RequestConfig = RequestConfig::default(), |v| v;
+        /// Whether [BitFlyerRequestHandler] should apply client-side rate limits. [Default]s to `true`.
+        ///
+        /// The limits are shared by all clones of a [Client][crate::Client]. See [BitFlyerRequestHandler::rate_limits()] for details.
+        RateLimit(bool) => rate_limit: bool = true, |v| v;
+        /// Base url for WebSocket connections
+        WebSocketUrl(BitFlyerWebSocketUrl) => websocket_url: BitFlyerWebSocketUrl = BitFlyerWebSocketUrl::Default, |v| v;
+        /// Whether [BitFlyerWebSocketHandler] should perform authentication
+        WebSocketAuth(bool) => websocket_auth: bool = false, |v| v;
+        /// The channels to be subscribed by [BitFlyerWebSocketHandler].
+        WebSocketChannels(Vec<String>) => websocket_channels: Vec<String> = vec![], |v| v;
+        /// [WebSocketConfig] used for creating [WebSocketConnection]s
+        /// `url_prefix` will be overridden by [WebSocketUrl](Self::WebSocketUrl) unless `WebSocketUrl` is [BitFlyerWebSocketUrl::None].
+        /// By default, ignore_duplicate_during_reconnection` is set to `true`.
+        WebSocketConfig(WebSocketConfig) => websocket_config: WebSocketConfig = {
+            let mut websocket_config = WebSocketConfig::new();
+            websocket_config.ignore_duplicate_during_reconnection = true;
+            websocket_config
+        }, |v| v;
+    }
 }
 
-/// A `enum` that represents the base url of the BitFlyer HTTP API.
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
-pub enum BitFlyerHttpUrl {
-    /// `https://api.bitflyer.com`
-    Default,
-    /// The url will not be modified by [BitFlyerRequestHandler]
-    None,
+base_urls! {
+    /// A `enum` that represents the base url of the BitFlyer HTTP API.
+    pub enum BitFlyerHttpUrl {
+        /// `https://api.bitflyer.com`
+        Default => "https://api.bitflyer.com",
+        /// The url will not be modified by [BitFlyerRequestHandler]
+        None => "",
+    }
 }
 
-/// A `enum` that represents the base url of the BitFlyer Realtime API
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
-#[non_exhaustive]
-pub enum BitFlyerWebSocketUrl {
-    /// `wss://ws.lightstream.bitflyer.com`
-    Default,
-    /// The url will not be modified by [BitFlyerWebSocketHandler]
-    None,
+base_urls! {
+    /// A `enum` that represents the base url of the BitFlyer Realtime API
+    #[non_exhaustive]
+    pub enum BitFlyerWebSocketUrl {
+        /// `wss://ws.lightstream.bitflyer.com`
+        Default => "wss://ws.lightstream.bitflyer.com",
+        /// The url will not be modified by [BitFlyerWebSocketHandler]
+        None => "",
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -94,12 +97,104 @@ pub struct BitFlyerChannelMessage {
 
 #[derive(Debug)]
 pub enum BitFlyerHandlerError {
-    ApiError(serde_json::Value),
-    ParseError,
+    ApiError { status: StatusCode, body: serde_json::Value },
+    /// A response with a successful HTTP status whose `status` is negative.
+    ErrorStatus {
+        /// The well-known meaning of `status`.
+        code: BitFlyerErrorCode,
+        /// The `status` of the response.
+        status: i64,
+        /// The `error_message` of the response.
+        error_message: String,
+        /// The whole response.
+        body: serde_json::Value,
+    },
+    /// The response could not be parsed.
+    ParseError { status: StatusCode, body: Bytes },
+}
+
+/// Well-known negative values of `status` in bitFlyer responses.
+///
+/// Codes which are not listed here are [Other](Self::Other), and may get their own variant in a later version.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[non_exhaustive]
+pub enum BitFlyerErrorCode {
+    /// `-106`: the price is too low.
+    PriceTooLow,
+    /// `-110`: the size is smaller than the minimum order size.
+    BelowMinimumSize,
+    /// `-205`: the margin is insufficient for the order.
+    InsufficientMargin,
+    /// `-208`: the order was not accepted. Sending it again later may succeed.
+    OrderNotAccepted,
+    /// `-500`: the API key was not found.
+    KeyNotFound,
+    /// Any other code.
+    Other(i64),
+}
+
+impl From<i64> for BitFlyerErrorCode {
+    fn from(code: i64) -> Self {
+        match code {
+            -106 => Self::PriceTooLow,
+            -110 => Self::BelowMinimumSize,
+            -205 => Self::InsufficientMargin,
+            -208 => Self::OrderNotAccepted,
+            -500 => Self::KeyNotFound,
+            code => Self::Other(code),
+        }
+    }
+}
+
+impl BitFlyerHandlerError {
+    fn http_status(&self) -> StatusCode {
+        match self {
+            Self::ApiError { status, .. } | Self::ParseError { status, .. } => *status,
+            Self::ErrorStatus { .. } => StatusCode::OK,
+        }
+    }
+}
+
+impl ExchangeError for BitFlyerHandlerError {
+    #[inline(always)]
+    fn is_rate_limited(&self) -> bool {
+        self.http_status() == 429
+    }
+
+    fn is_auth_error(&self) -> bool {
+        self.http_status() == 401 || self.exchange_code().map(BitFlyerErrorCode::from) == Some(BitFlyerErrorCode::KeyNotFound)
+    }
+
+    fn is_retryable(&self) -> bool {
+        self.is_rate_limited()
+            || self.http_status().is_server_error()
+            || self.exchange_code().map(BitFlyerErrorCode::from) == Some(BitFlyerErrorCode::OrderNotAccepted)
+    }
+
+    fn exchange_code(&self) -> Option<i64> {
+        match self {
+            Self::ErrorStatus { status, .. } => Some(*status),
+            // errors look like {"status": -200, "error_message": "...", "data": null}
+            Self::ApiError { body, .. } => body["status"].as_i64(),
+            Self::ParseError { .. } => None,
+        }
+    }
+
+    #[inline(always)]
+    fn status(&self) -> Option<StatusCode> {
+        Some(self.http_status())
+    }
+
+    fn raw_body(&self) -> Option<&[u8]> {
+        match self {
+            Self::ParseError { body, .. } => Some(body),
+            _ => None,
+        }
+    }
 }
 
 /// A `struct` that implements [RequestHandler]
-pub struct BitFlyerRequestHandler<'a, R: DeserializeOwned> {
+pub struct BitFlyerRequestHandler<'a, R: ResponseBody> {
     options: BitFlyerOptions,
     _phantom: PhantomData<&'a R>,
 }
@@ -111,37 +206,26 @@ pub struct BitFlyerWebSocketHandler {
     options: BitFlyerOptions,
 }
 
-impl<'a, B, R> RequestHandler<B> for BitFlyerRequestHandler<'a, R>
-where
-    B: Serialize,
-    R: DeserializeOwned,
-{
-    type Successful = R;
-    type Unsuccessful = BitFlyerHandlerError;
-    type BuildError = &'static str;
-
-    fn request_config(&self) -> RequestConfig {
-        let mut config = self.options.request_config.clone();
-        if self.options.http_url != BitFlyerHttpUrl::None {
-            config.url_prefix = self.options.http_url.as_str().to_owned();
+impl<'a, R> BitFlyerRequestHandler<'a, R> where R: ResponseBody {
+    async fn build<B>(&self, mut builder: RequestBuilder, request_body: &Option<B>, signing: Signing) -> Result<Request, BuildError>
+    where
+        B: Serialize,
+    {
+        if let Some(name) = &self.options.unknown_account {
+            return Err(BuildError::UnknownAccount(name.clone()));
         }
-        config
-    }
-
-    fn build_request(&self, mut builder: RequestBuilder, request_body: &Option<B>, _: u8) -> Result<Request, Self::BuildError> {
         if let Some(body) = request_body {
-            let json = serde_json::to_vec(body).or(Err("could not serialize body as application/json"))?;
+            let json = serde_json::to_vec(body).map_err(|error| BuildError::serialize("application/json", error))?;
             builder = builder
                 .header(header::CONTENT_TYPE, "application/json")
                 .body(json);
         }
 
-        let mut request = builder.build().or(Err("failed to build request"))?;
+        let mut request = builder.build().map_err(BuildError::Request)?;
 
         if self.options.http_auth {
             // https://lightning.bitflyer.com/docs?lang=en#authentication
-            let time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap(); // always after the epoch
-            let timestamp = time.as_millis() as u64;
+            let timestamp = self.options.server_clock.now_millis();
 
             let mut path = request.url().path().to_owned();
             if let Some(query) = request.url().query() {
@@ -155,15 +239,12 @@ where
 
             let sign_contents = format!("{}{}{}{}", timestamp, request.method(), path, body);
 
-            let secret = self.options.secret.as_deref().ok_or("API secret not set")?;
-            let mut hmac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap(); // hmac accepts key of any length
+            let credentials = credentials::resolve(&self.options.credentials, &self.options.key, &self.options.secret)?;
+            let signer = signer::signer_or_hmac(&self.options.signer, credentials.secret.as_ref())?;
+            let signature = signer::sign_hex(&*signer, sign_contents.as_bytes(), signing).await?;
 
-            hmac.update(sign_contents.as_bytes());
-            let signature = hex::encode(hmac.finalize().into_bytes());
-
-            let key = HeaderValue::from_str(self.options.key.as_deref().ok_or("API key not set")?).or(
-                Err("invalid character in API key")
-            )?;
+            let key = HeaderValue::from_str(&credentials.key)
+                .map_err(|source| BuildError::InvalidHeaderValue { header: "ACCESS-KEY", source })?;
             let headers = request.headers_mut();
             headers.insert("ACCESS-KEY", key);
             headers.insert("ACCESS-TIMESTAMP", HeaderValue::from(timestamp));
@@ -173,26 +254,99 @@ where
 
         Ok(request)
     }
+}
 
-    fn handle_response(&self, status: StatusCode, _: HeaderMap, response_body: Bytes) -> Result<Self::Successful, Self::Unsuccessful> {
-        if status.is_success() {
-            serde_json::from_slice(&response_body).map_err(|error| {
-                log::debug!("Failed to parse response due to an error: {}", error);
-                BitFlyerHandlerError::ParseError
-            })
-        } else {
-            let error = match serde_json::from_slice(&response_body) {
-                Ok(parsed_error) => BitFlyerHandlerError::ApiError(parsed_error),
-                Err(error) => {
-                    log::debug!("Failed to parse error response due to an error: {}", error);
-                    BitFlyerHandlerError::ParseError
+impl<'a, B, R> RequestHandler<B> for BitFlyerRequestHandler<'a, R>
+where
+    B: Serialize,
+    R: ResponseBody,
+{
+    type Successful = R;
+    type Unsuccessful = BitFlyerHandlerError;
+    type BuildError = BuildError;
+
+    fn request_config(&self) -> RequestConfig {
+        let mut config = self.options.request_config.clone();
+        if self.options.http_url != BitFlyerHttpUrl::None {
+            config.url_prefix = self.options.http_url.as_str().to_owned();
+        }
+        config
+    }
+
+    /// Applies the limit per IP (500 requests per 5 minutes), the limit per API key (500 requests per 5 minutes), and
+    /// the limit for order endpoints per API key (300 requests per 5 minutes).
+    fn rate_limits(&self, request: &Request) -> Vec<RateLimit> {
+        if !self.options.rate_limit {
+            return vec![];
+        }
+        // https://lightning.bitflyer.com/docs?lang=en#api-limits
+        const INTERVAL: Duration = Duration::from_secs(5 * 60);
+        let mut limits = vec![RateLimit::new("bitflyer:ip", 500, INTERVAL, 1)];
+        if self.options.http_auth {
+            if let Ok(credentials) = credentials::resolve(&self.options.credentials, &self.options.key, &self.options.secret) {
+                let key = util::key_id(&credentials.key);
+                limits.push(RateLimit::new(format!("bitflyer:{key}"), 500, INTERVAL, 1));
+                let path = request.url().path();
+                if matches!(path, "/v1/me/sendchildorder" | "/v1/me/sendparentorder" | "/v1/me/cancelallchildorders") {
+                    limits.push(RateLimit::new(format!("bitflyer:{key}:order"), 300, INTERVAL, 1));
                 }
-            };
-            Err(error)
+            }
+        }
+        limits
+    }
+
+    fn build_request(&self, builder: RequestBuilder, request_body: &Option<B>, _: u8) -> Result<Request, Self::BuildError> {
+        signer::blocking(self.build(builder, request_body, Signing::Blocking))
+    }
+
+    fn build_request_async(&self, builder: RequestBuilder, request_body: &Option<B>, _: u8) -> impl Future<Output = Result<Request, Self::BuildError>> {
+        self.build(builder, request_body, Signing::Async)
+    }
+
+    /// Retries requests rejected because of `ACCESS-TIMESTAMP` after requesting a resynchronization of the [ServerClock].
+    fn retry_decision(&self, method: &Method, status: StatusCode, headers: &HeaderMap, response_body: &Bytes) -> RetryDecision {
+        // errors look like {"status": -200, "error_message": "...", "data": null} whatever the HTTP status is
+        if let Ok(BitFlyerResponseStatus { status: Some(..=-1), error_message: Some(message) }) = json::from_slice(response_body) {
+            if message.to_ascii_lowercase().contains("timestamp") {
+                // the request is signed again with the corrected clock when retrying
+                log::debug!("Timestamp rejected by the server: {}", message);
+                self.options.server_clock.observe_date_header(headers);
+                self.options.server_clock.request_resync();
+                return RetryDecision::Retry;
+            }
+        }
+        default_retry_decision(method, status, headers)
+    }
+
+    fn handle_response(&self, status: StatusCode, headers: HeaderMap, response_body: Bytes) -> Result<Self::Successful, Self::Unsuccessful> {
+        let parse_error = |status, body| BitFlyerHandlerError::ParseError { status, body };
+        if status.is_success() {
+            // some errors are returned with HTTP 200 and a negative status
+            if let Ok(BitFlyerResponseStatus { status: Some(error_status @ ..=-1), error_message }) = json::from_slice(&response_body) {
+                return match json::from_slice(&response_body) {
+                    Ok(body) => Err(BitFlyerHandlerError::ErrorStatus {
+                        code: BitFlyerErrorCode::from(error_status),
+                        status: error_status,
+                        error_message: error_message.unwrap_or_default(),
+                        body,
+                    }),
+                    Err(_) => Err(parse_error(status, response_body)),
+                };
+            }
         }
+        util::handle_json_response(status, &headers, response_body, parse_error, |status, _, parsed| {
+            parsed.map(|body| BitFlyerHandlerError::ApiError { status, body })
+        })
     }
 }
 
+/// The part of a response used to detect errors.
+#[derive(Deserialize)]
+struct BitFlyerResponseStatus {
+    status: Option<i64>,
+    error_message: Option<String>,
+}
+
 impl WebSocketHandler for BitFlyerWebSocketHandler {
     fn websocket_config(&self) -> WebSocketConfig {
         let mut config = self.options.websocket_config.clone();
@@ -203,44 +357,11 @@ impl WebSocketHandler for BitFlyerWebSocketHandler {
     }
 
     fn handle_start(&mut self) -> Vec<WebSocketMessage> {
-        if self.options.websocket_auth {
-            // https://bf-lightning-api.readme.io/docs/realtime-api-auth
-            if let Some(key) = self.options.key.as_deref() {
-                if let Some(secret) = self.options.secret.as_deref() {
-                    let time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap(); // always after the epoch
-                    let timestamp = time.as_millis() as u64;
-                    let nonce: String = rand::thread_rng()
-                        .sample_iter(&Alphanumeric)
-                        .take(16)
-                        .map(char::from)
-                        .collect();
-
-                    let mut hmac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap(); // hmac accepts key of any length
-
-                    hmac.update(format!("{timestamp}{nonce}").as_bytes());
-                    let signature = hex::encode(hmac.finalize().into_bytes());
-
-                    let id = format!("_auth{}", time.as_nanos());
-                    self.auth_id = Some(id.clone());
-
-                    return vec![WebSocketMessage::Text(json!({
-                        "method": "auth",
-                        "params": {
-                            "api_key": key,
-                            "timestamp": timestamp,
-                            "nonce": nonce,
-                            "signature": signature,
-                        },
-                        "id": id,
-                    }).to_string())];
-                } else {
-                    log::debug!("API secret not set.");
-                };
-            } else {
-                log::debug!("API key not set.");
-            };
-        }
-        self.message_subscribe()
+        signer::blocking(self.start(Signing::Blocking))
+    }
+
+    fn handle_start_async(&mut self) -> impl Future<Output = Vec<WebSocketMessage>> + Send + 'static {
+        self.start(Signing::Async)
     }
 
     fn handle_message(&mut self, message: WebSocketMessage) -> Vec<WebSocketMessage> {
@@ -256,7 +377,7 @@ impl WebSocketHandler for BitFlyerWebSocketHandler {
 
         match message {
             WebSocketMessage::Text(message) => {
-                let message: Message = match serde_json::from_str(&message) {
+                let message: Message = match json::from_string(message) {
                     Ok(message) => message,
                     Err(_) => {
                         log::debug!("Invalid JSON-RPC message received");
@@ -283,111 +404,101 @@ impl WebSocketHandler for BitFlyerWebSocketHandler {
         }
         vec![]
     }
-}
 
-impl BitFlyerWebSocketHandler {
-    #[inline]
-    fn message_subscribe(&self) -> Vec<WebSocketMessage> {
-        self.options.websocket_channels.clone().into_iter().map(|channel| {
-            WebSocketMessage::Text(json!({ "method": "subscribe", "params": { "channel": channel } }).to_string())
-        }).collect()
-    }
-}
+    /// Identifies messages of the executions channels by the channel and the `id`s of the executions.
+    fn dedup_key(&self, message: &WebSocketMessage) -> Option<DedupKey> {
+        #[derive(Deserialize)]
+        struct Message<'a> {
+            #[serde(borrow)]
+            params: Params<'a>,
+        }
 
-impl BitFlyerHttpUrl {
-    /// The base URL that this variant represents.
-    #[inline(always)]
-    fn as_str(&self) -> &'static str {
-        match self {
-            Self::Default => "https://api.bitflyer.com",
-            Self::None => "",
+        #[derive(Deserialize)]
+        struct Params<'a> {
+            channel: &'a str,
+            message: Vec<Execution>,
         }
-    }
-}
 
-impl BitFlyerWebSocketUrl {
-    /// The base URL that this variant represents.
-    #[inline(always)]
-    fn as_str(&self) -> &'static str {
-        match self {
-            Self::Default => "wss://ws.lightstream.bitflyer.com",
-            Self::None => "",
+        #[derive(Deserialize)]
+        struct Execution {
+            id: u64,
         }
+
+        let WebSocketMessage::Text(message) = message else {
+            return None;
+        };
+        let message: Message = serde_json::from_str(message).ok()?;
+        let ids: Vec<u64> = message.params.message.iter().map(|execution| execution.id).collect();
+        Some(DedupKey::new((message.params.channel, ids)))
     }
 }
 
-impl HandlerOptions for BitFlyerOptions {
-    type OptionItem = BitFlyerOption;
-
-    fn update(&mut self, option: Self::OptionItem) {
-        match option {
-            BitFlyerOption::Default => (),
-            BitFlyerOption::Key(v) => self.key = Some(v),
-            BitFlyerOption::Secret(v) => self.secret = Some(v),
-            BitFlyerOption::HttpUrl(v) => self.http_url = v,
-            BitFlyerOption::HttpAuth(v) => self.http_auth = v,
-            BitFlyerOption::RequestConfig(v) => self.request_config = v,
-            BitFlyerOption::WebSocketUrl(v) => self.websocket_url = v,
-            BitFlyerOption::WebSocketAuth(v) => self.websocket_auth = v,
-            BitFlyerOption::WebSocketChannels(v) => self.websocket_channels = v,
-            BitFlyerOption::WebSocketConfig(v) => self.websocket_config = v,
+impl BitFlyerWebSocketHandler {
+    /// Returns the first messages of a connection, which are the auth message or the subscriptions.
+    fn start(&mut self, signing: Signing) -> impl Future<Output = Vec<WebSocketMessage>> + Send + 'static {
+        let auth = self.options.websocket_auth.then(|| self.message_auth(signing));
+        let subscribe = self.message_subscribe();
+        async move {
+            if let Some(auth) = auth {
+                match auth.await {
+                    Ok(message) => return vec![message],
+                    Err(error) => log::error!("Failed to authenticate: {}", error),
+                }
+            }
+            subscribe
         }
     }
-}
 
-impl Default for BitFlyerOptions {
-    fn default() -> Self {
-        let mut websocket_config = WebSocketConfig::new();
-        websocket_config.ignore_duplicate_during_reconnection = true;
-        Self {
-            key: None,
-            secret: None,
-            http_url: BitFlyerHttpUrl::Default,
-            http_auth: false,
-            request_config: RequestConfig::default(),
-            websocket_url: BitFlyerWebSocketUrl::Default,
-            websocket_auth: false,
-            websocket_channels: vec![],
-            websocket_config,
+    /// Creates the auth message. Credentials are loaded on every (re)connection so that rotated credentials are used.
+    fn message_auth(&mut self, signing: Signing) -> impl Future<Output = Result<WebSocketMessage, BuildError>> + Send + 'static {
+        // https://bf-lightning-api.readme.io/docs/realtime-api-auth
+        let credentials = credentials::resolve(&self.options.credentials, &self.options.key, &self.options.secret)
+            .and_then(|credentials| Ok((signer::signer_or_hmac(&self.options.signer, credentials.secret.as_ref())?, credentials)));
+        let time = self.options.server_clock.now().duration_since(SystemTime::UNIX_EPOCH).unwrap(); // always after the epoch
+        let timestamp = time.as_millis() as u64;
+        let nonce = self.options.nonce_source.nonce();
+        let id = format!("_auth{}", time.as_nanos());
+        self.auth_id = Some(id.clone());
+        async move {
+            let (signer, credentials) = credentials?;
+            let signature = signer::sign_hex(&*signer, format!("{timestamp}{nonce}").as_bytes(), signing).await?;
+            Ok(WebSocketMessage::Text(json!({
+                "method": "auth",
+                "params": {
+                    "api_key": credentials.key,
+                    "timestamp": timestamp,
+                    "nonce": nonce,
+                    "signature": signature,
+                },
+                "id": id,
+            }).to_string()))
         }
     }
-}
-
-impl<'a, R, B> HttpOption<'a, R, B> for BitFlyerOption
-where
-    R: DeserializeOwned + 'a,
-    B: Serialize,
-{
-    type RequestHandler = BitFlyerRequestHandler<'a, R>;
 
-    #[inline(always)]
-    fn request_handler(options: Self::Options) -> Self::RequestHandler {
-        BitFlyerRequestHandler::<'a, R> {
-            options,
-            _phantom: PhantomData,
-        }
+    #[inline]
+    fn message_subscribe(&self) -> Vec<WebSocketMessage> {
+        self.options.websocket_channels.clone().into_iter().map(|channel| {
+            WebSocketMessage::Text(json!({ "method": "subscribe", "params": { "channel": channel } }).to_string())
+        }).collect()
     }
 }
 
-impl<H: FnMut(BitFlyerChannelMessage) + Send + 'static> WebSocketOption<H> for BitFlyerOption {
-    type WebSocketHandler = BitFlyerWebSocketHandler;
+impl ServerTimeOption for BitFlyerOption {
+    fn server_time_url(options: &Self::Options) -> String {
+        let prefix = match options.http_url {
+            BitFlyerHttpUrl::None => BitFlyerHttpUrl::Default.as_str(),
+            url => url.as_str(),
+        };
+        // bitFlyer has no endpoint for the server time, so the Date header of a lightweight endpoint is used
+        format!("{prefix}/v1/gethealth")
+    }
 
     #[inline(always)]
-    fn websocket_handler(handler: H, options: Self::Options) -> Self::WebSocketHandler {
-        BitFlyerWebSocketHandler {
-            message_handler: Box::new(handler),
-            auth_id: None,
-            options,
-        }
+    fn parse_server_time(headers: &HeaderMap, response_body: &[u8]) -> Option<SystemTime> {
+        crate::clock::parse_date_header(headers, response_body)
     }
-}
-
-impl HandlerOption for BitFlyerOption {
-    type Options = BitFlyerOptions;
-}
 
-impl Default for BitFlyerOption {
-    fn default() -> Self {
-        Self::Default
+    fn server_clock(options: &Self::Options) -> &ServerClock {
+        &options.server_clock
     }
 }
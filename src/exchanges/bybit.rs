@@ -1,96 +1,104 @@
 //! A module for communicating with the [Bybit API](https://bybit-exchange.github.io/docs/spot/v3/#t-introduction).
 //! For example usages, see files in the examples/ directory.
 
-use std::{time::SystemTime, borrow::Cow, marker::PhantomData, vec};
-use hmac::{Hmac, Mac};
-use sha2::Sha256;
-use serde::{Serialize, de::DeserializeOwned};
+use std::{time::{SystemTime, Duration}, borrow::Cow, future::Future, marker::PhantomData, ops::Range, sync::Arc, vec};
+use serde::{Serialize, Deserialize, Deserializer};
+use serde_json::value::RawValue;
 use serde_json::json;
-use generic_api_client::{http::{*, header::HeaderValue}, websocket::*};
-use crate::traits::*;
+use generic_api_client::{http::{*, header::HeaderValue}, websocket::*, rate_limit::RateLimit};
+use crate::{traits::*, util, json, response::ResponseBody, error::{BuildError, ExchangeError}, clock::ServerClock, credentials::{self, CredentialProvider}, secret::Secret, signer::{self, Signer, SignatureAlgorithm, Signing}};
 
 /// The type returned by [Client::request()].
 pub type BybitRequestResult<T> = Result<T, BybitRequestError>;
-pub type BybitRequestError = RequestError<&'static str, BybitHandlerError>;
-
-/// Options that can be set when creating handlers
-pub enum BybitOption {
-    /// [Default] variant, does nothing
-    Default,
-    /// API key
-    Key(String),
-    /// Api secret
-    Secret(String),
-    /// Base url for HTTP requests
-    HttpUrl(BybitHttpUrl),
-    /// Type of authentication used for HTTP requests.
-    HttpAuth(BybitHttpAuth),
-    /// receive window parameter used for requests
-    RecvWindow(i32),
-    /// [RequestConfig] used when sending requests.
-    /// `url_prefix` will be overridden by [HttpUrl](Self::HttpUrl) unless `HttpUrl` is [BybitHttpUrl::None].
-    RequestConfig(RequestConfig),
-    /// Base url for WebSocket connections
-    WebSocketUrl(BybitWebSocketUrl),
-    /// Whether [BybitWebSocketHandler] should perform authentication
-    WebSocketAuth(bool),
-    /// The topics to subscribe to.
-    WebSocketTopics(Vec<String>),
-    /// [WebSocketConfig] used for creating [WebSocketConnection]s
-    /// `url_prefix` will be overridden by [WebSocketUrl](Self::WebSocketUrl) unless `WebSocketUrl` is [BybitWebSocketUrl::None].
-    /// By default, `ignore_duplicate_during_reconnection` is set to `true`.
-    WebSocketConfig(WebSocketConfig),
-}
+pub type BybitRequestError = RequestError<BuildError, BybitHandlerError>;
 
-/// A `struct` that represents a set of [BybitOption] s.
-#[derive(Clone, Debug)]
-pub struct BybitOptions {
-    /// see [BybitOption::Key]
-    pub key: Option<String>,
-    /// see [BybitOption::Secret]
-    pub secret: Option<String>,
-    /// see [BybitOption::HttpUrl]
-    pub http_url: BybitHttpUrl,
-    /// see [BybitOption::HttpAuth]
-    pub http_auth: BybitHttpAuth,
-    /// see [BybitOption::RecvWindow]
-    pub recv_window: Option<i32>,
-    /// see [BybitOption::RequestConfig]
-    pub request_config: RequestConfig,
-    /// see [BybitOption::WebSocketUrl]
-    pub websocket_url: BybitWebSocketUrl,
-    /// see [BybitOption::WebSocketAuth]
-    pub websocket_auth: bool,
-    /// see [BybitOption::WebSocketTopics]
-    pub websocket_topics: Vec<String>,
-    /// see [BybitOption::WebSocketConfig]
-    pub websocket_config: WebSocketConfig,
+exchange_options! {
+    option: BybitOption,
+    options: BybitOptions,
+    request_handler: BybitRequestHandler,
+    websocket_handler: BybitWebSocketHandler(serde_json::Value) {},
+    variants: {
+        /// API key
+        Key(String) => key: Option<String> = None, |v| Some(v);
+        /// Api secret
+        Secret(String) => secret: Option<Secret> = None, |v| Some(Secret::new(v));
+        /// [CredentialProvider] consulted every time a request is signed or a WebSocket connection is authenticated.
+        /// If this is set, [Key](Self::Key) and [Secret](Self::Secret) are ignored.
+        Credentials(Arc<dyn CredentialProvider>) => credentials: Option<Arc<dyn CredentialProvider>> = None, |v| Some(v);
+        /// [Signer] used for authentication instead of the HMAC of [Secret](Self::Secret).
+        ///
+        /// See [UnixSocketSigner][crate::signer::UnixSocketSigner] for keeping the secret out of the process.
+        Signer(Arc<dyn Signer>) => signer: Option<Arc<dyn Signer>> = None, |v| Some(v);
+        /// PEM encoded RSA private key of a self-generated API key, used for authentication instead of [Secret](Self::Secret).
+        /// This sets [Signer](Self::Signer) with [signer_from_pem()][crate::signer::signer_from_pem()], so the `rsa` feature is required.
+        ///
+        /// Signatures are encoded in base64 instead of hex for RSA keys. Only [BybitHttpAuth::V3AndAbove] supports RSA keys.
+        /// If the key could not be loaded, authenticated requests fail to be built with [BuildError::InvalidKey].
+        /// Use [signer_from_pem()][crate::signer::signer_from_pem()] and [Signer](Self::Signer) to handle the error earlier.
+        PrivateKeyPem(String) => signer, |v| Some(signer::signer_from_pem_or_invalid(v));
+        /// [ServerClock] used for timestamps in authentication.
+        ///
+        /// By default, each [Client][crate::Client] has its own clock which is shared by its clones.
+        /// See [Client::spawn_clock_sync()][crate::Client::spawn_clock_sync()] for synchronizing it.
+        ServerClock(ServerClock) => server_clock: ServerClock = ServerClock::new(), |v| v;
+        /// Base url for HTTP requests
+        HttpUrl(BybitHttpUrl) => http_url: BybitHttpUrl = BybitHttpUrl::Bybit, |v| v;
+        /// Type of authentication used for HTTP requests.
+        HttpAuth(BybitHttpAuth) => http_auth: BybitHttpAuth = BybitHttpAuth::None, |v| v;
+        /// receive window parameter used for requests
+        RecvWindow(i32) => recv_window: Option<i32> = None, |v| Some(v);
+        /// Whether [BybitRequestHandler] should deserialize the `result` field of responses instead of the whole response. [Default]s to `false`.
+        UnwrapResult(bool) => unwrap_result: bool = false, |v| v;
+        /// [RequestConfig] used when sending requests.
+        /// `url_prefix` will be overridden by [HttpUrl](Self::HttpUrl) unless `HttpUrl` is [BybitHttpUrl::None].
+        RequestConfig(RequestConfig) => request_config: RequestConfig = RequestConfig::default(), |v| v;
+        /// Whether [BybitRequestHandler] should apply client-side rate limits. [Default]s to `true`.
+        ///
+        /// The limits are shared by all clones of a [Client][crate::Client]. See [BybitRequestHandler::rate_limits()] for details.
+        RateLimit(bool) => rate_limit: bool = true, |v| v;
+        /// Base url for WebSocket connections
+        WebSocketUrl(BybitWebSocketUrl) => websocket_url: BybitWebSocketUrl = BybitWebSocketUrl::Bybit, |v| v;
+        /// Whether [BybitWebSocketHandler] should perform authentication
+        WebSocketAuth(bool) => websocket_auth: bool = false, |v| v;
+        /// The topics to subscribe to.
+        WebSocketTopics(Vec<String>) => websocket_topics: Vec<String> = vec![], |v| v;
+        /// [WebSocketConfig] used for creating [WebSocketConnection]s
+        /// `url_prefix` will be overridden by [WebSocketUrl](Self::WebSocketUrl) unless `WebSocketUrl` is [BybitWebSocketUrl::None].
+        /// By default, `ignore_duplicate_during_reconnection` is set to `true`.
+        WebSocketConfig(WebSocketConfig) => websocket_config: WebSocketConfig = {
+            let mut websocket_config = WebSocketConfig::new();
+            websocket_config.ignore_duplicate_during_reconnection = true;
+            websocket_config
+        }, |v| v;
+    }
 }
 
-/// A `enum` that represents the base url of the Bybit REST API.
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
-pub enum BybitHttpUrl {
-    /// `https://api.bybit.com`
-    Bybit,
-    /// `https://api.bytick.com`
-    Bytick,
-    /// `https://api-testnet.bybit.com`
-    Test,
-    /// The url will not be modified by [BybitRequestHandler]
-    None,
+base_urls! {
+    /// A `enum` that represents the base url of the Bybit REST API.
+    pub enum BybitHttpUrl {
+        /// `https://api.bybit.com`
+        Bybit => "https://api.bybit.com",
+        /// `https://api.bytick.com`
+        Bytick => "https://api.bytick.com",
+        /// `https://api-testnet.bybit.com`
+        Test => "https://api-testnet.bybit.com",
+        /// The url will not be modified by [BybitRequestHandler]
+        None => "",
+    }
 }
 
-/// A `enum` that represents the base url of the Bybit WebSocket API.
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
-pub enum BybitWebSocketUrl {
-    /// `wss://stream.bybit.com`
-    Bybit,
-    /// `wss://stream.bytick.com`
-    Bytick,
-    /// `wss://stream-testnet.bybit.com`
-    Test,
-    /// The url will not be modified by [BybitWebSocketHandler]
-    None,
+base_urls! {
+    /// A `enum` that represents the base url of the Bybit WebSocket API.
+    pub enum BybitWebSocketUrl {
+        /// `wss://stream.bybit.com`
+        Bybit => "wss://stream.bybit.com",
+        /// `wss://stream.bytick.com`
+        Bytick => "wss://stream.bytick.com",
+        /// `wss://stream-testnet.bybit.com`
+        Test => "wss://stream-testnet.bybit.com",
+        /// The url will not be modified by [BybitWebSocketHandler]
+        None => "",
+    }
 }
 
 /// Represents the auth type.
@@ -115,11 +123,136 @@ pub enum BybitHttpAuth {
 pub enum BybitHandlerError {
     ApiError(serde_json::Value),
     IpBan(serde_json::Value),
-    ParseError,
+    /// A response with a successful HTTP status whose `retCode` (`ret_code` in older APIs) is not `0`.
+    /// Bybit returns most errors this way.
+    RetCode {
+        /// The well-known meaning of `ret_code`.
+        code: BybitErrorCode,
+        /// The `retCode` of the response.
+        ret_code: i64,
+        /// The `retMsg` of the response.
+        ret_msg: String,
+        /// The whole response, which may contain details such as `retExtInfo`.
+        body: serde_json::Value,
+    },
+    /// The response could not be parsed.
+    ParseError { status: StatusCode, body: Bytes },
+}
+
+/// Well-known values of `retCode`. See the [documentation](https://bybit-exchange.github.io/docs/v5/error) for the full list.
+///
+/// Codes which are not listed here are [Other](Self::Other), and may get their own variant in a later version.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[non_exhaustive]
+pub enum BybitErrorCode {
+    /// `10001`: the parameters of the request are invalid.
+    InvalidRequest,
+    /// `10002`: the timestamp of the request is outside of the receive window.
+    InvalidTimestamp,
+    /// `10003`: the API key is invalid.
+    InvalidApiKey,
+    /// `10004`: the signature is invalid.
+    InvalidSignature,
+    /// `10005`: the API key does not have the permission.
+    PermissionDenied,
+    /// `10006`: too many requests were sent.
+    TooManyVisits,
+    /// `10007`: the user could not be authenticated.
+    AuthenticationFailed,
+    /// `10010`: the IP address is not allowed for the API key.
+    IpNotAllowed,
+    /// `10016`: an error occurred on the server.
+    ServerError,
+    /// `10018`: the rate limit of the IP address was exceeded.
+    IpRateLimitExceeded,
+    /// `33004`: the API key has expired.
+    ApiKeyExpired,
+    /// `110004`, `110007`, `110012` and `170131`: the balance is insufficient.
+    InsufficientBalance,
+    /// Any other code.
+    Other(i64),
+}
+
+impl From<i64> for BybitErrorCode {
+    fn from(code: i64) -> Self {
+        match code {
+            10001 => Self::InvalidRequest,
+            10002 => Self::InvalidTimestamp,
+            10003 => Self::InvalidApiKey,
+            10004 => Self::InvalidSignature,
+            10005 => Self::PermissionDenied,
+            10006 => Self::TooManyVisits,
+            10007 => Self::AuthenticationFailed,
+            10010 => Self::IpNotAllowed,
+            10016 => Self::ServerError,
+            10018 => Self::IpRateLimitExceeded,
+            33004 => Self::ApiKeyExpired,
+            110004 | 110007 | 110012 | 170131 => Self::InsufficientBalance,
+            code => Self::Other(code),
+        }
+    }
+}
+
+// https://bybit-exchange.github.io/docs/v5/error
+impl ExchangeError for BybitHandlerError {
+    fn is_rate_limited(&self) -> bool {
+        match self {
+            Self::IpBan(_) => true,
+            Self::ParseError { status, .. } => *status == 403 || *status == 429,
+            _ => matches!(self.exchange_code().map(BybitErrorCode::from), Some(BybitErrorCode::TooManyVisits | BybitErrorCode::IpRateLimitExceeded)),
+        }
+    }
+
+    fn is_auth_error(&self) -> bool {
+        match self {
+            Self::ParseError { status, .. } => *status == 401,
+            _ => matches!(
+                self.exchange_code().map(BybitErrorCode::from),
+                Some(
+                    BybitErrorCode::InvalidApiKey | BybitErrorCode::InvalidSignature | BybitErrorCode::PermissionDenied
+                    | BybitErrorCode::AuthenticationFailed | BybitErrorCode::IpNotAllowed | BybitErrorCode::ApiKeyExpired
+                ),
+            ),
+        }
+    }
+
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::IpBan(_) => true,
+            Self::ParseError { status, .. } => self.is_rate_limited() || status.is_server_error(),
+            _ => self.is_rate_limited()
+                || matches!(self.exchange_code().map(BybitErrorCode::from), Some(BybitErrorCode::InvalidTimestamp | BybitErrorCode::ServerError)),
+        }
+    }
+
+    fn exchange_code(&self) -> Option<i64> {
+        match self {
+            Self::RetCode { ret_code, .. } => Some(*ret_code),
+            // older APIs use `ret_code` instead of `retCode`
+            Self::ApiError(body) | Self::IpBan(body) => body.get("retCode").or_else(|| body.get("ret_code"))?.as_i64(),
+            Self::ParseError { .. } => None,
+        }
+    }
+
+    fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::IpBan(_) => Some(StatusCode::FORBIDDEN),
+            Self::RetCode { .. } => Some(StatusCode::OK),
+            Self::ParseError { status, .. } => Some(*status),
+            Self::ApiError(_) => None,
+        }
+    }
+
+    fn raw_body(&self) -> Option<&[u8]> {
+        match self {
+            Self::ParseError { body, .. } => Some(body),
+            _ => None,
+        }
+    }
 }
 
 /// A `struct` that implements [RequestHandler]
-pub struct BybitRequestHandler<'a, R: DeserializeOwned> {
+pub struct BybitRequestHandler<'a, R: ResponseBody> {
     options: BybitOptions,
     _phantom: PhantomData<&'a R>,
 }
@@ -132,11 +265,11 @@ pub struct BybitWebSocketHandler {
 impl<'a, B, R> RequestHandler<B> for BybitRequestHandler<'a, R>
 where
     B: Serialize,
-    R: DeserializeOwned,
+    R: ResponseBody,
 {
     type Successful = R;
     type Unsuccessful = BybitHandlerError;
-    type BuildError = &'static str;
+    type BuildError = BuildError;
 
     fn request_config(&self) -> RequestConfig {
         let mut config = self.options.request_config.clone();
@@ -146,63 +279,168 @@ where
         config
     }
 
-    fn build_request(&self, mut builder: RequestBuilder, request_body: &Option<B>, _: u8) -> Result<Request, Self::BuildError> {
+    /// Retries requests rejected because of the timestamp (`retCode` 10002) after requesting a resynchronization
+    /// of the [ServerClock].
+    fn retry_decision(&self, method: &Method, status: StatusCode, headers: &HeaderMap, response_body: &Bytes) -> RetryDecision {
+        let ret_code = json::from_borrowed_slice::<RetCode>(response_body).ok().and_then(|body| body.ret_code);
+        if ret_code == Some(10002) {
+            log::debug!("Timestamp rejected by the server");
+            self.options.server_clock.observe_date_header(headers);
+            self.options.server_clock.request_resync();
+            return RetryDecision::Retry;
+        }
+        default_retry_decision(method, status, headers)
+    }
+
+    /// Applies the limit per IP (600 requests per 5 seconds), and a limit of 10 requests per second per endpoint
+    /// for each API key, which is the lowest limit of the V5 API.
+    fn rate_limits(&self, request: &Request) -> Vec<RateLimit> {
+        if !self.options.rate_limit {
+            return vec![];
+        }
+        // https://bybit-exchange.github.io/docs/v5/rate-limit
+        let host = request.url().host_str().unwrap_or_default();
+        let mut limits = vec![RateLimit::new(format!("bybit:{host}:ip"), 600, Duration::from_secs(5), 1)];
+        if self.options.http_auth != BybitHttpAuth::None {
+            if let Ok(credentials) = credentials::resolve(&self.options.credentials, &self.options.key, &self.options.secret) {
+                let key = util::key_id(&credentials.key);
+                let path = request.url().path();
+                limits.push(RateLimit::new(format!("bybit:{host}:{key}:{path}"), 10, Duration::from_secs(1), 1));
+            }
+        }
+        limits
+    }
+
+    fn build_request(&self, builder: RequestBuilder, request_body: &Option<B>, _: u8) -> Result<Request, Self::BuildError> {
+        signer::blocking(self.build(builder, request_body, Signing::Blocking))
+    }
+
+    fn build_request_async(&self, builder: RequestBuilder, request_body: &Option<B>, _: u8) -> impl Future<Output = Result<Request, Self::BuildError>> {
+        self.build(builder, request_body, Signing::Async)
+    }
+
+    fn handle_response(&self, status: StatusCode, headers: HeaderMap, response_body: Bytes) -> Result<Self::Successful, Self::Unsuccessful> {
+        // https://bybit-exchange.github.io/docs/spot/v3/#t-ratelimits
+        let parse_error = |status, body| BybitHandlerError::ParseError { status, body };
+        if status.is_success() {
+            let envelope = BybitEnvelope::parse(&response_body);
+            // most errors are returned with HTTP 200 and a non-zero retCode
+            if let Some(BybitEnvelope { ret_code: Some(ret_code), ret_msg, .. }) = &envelope {
+                if *ret_code != 0 {
+                    return match json::from_slice(&response_body) {
+                        Ok(body) => Err(BybitHandlerError::RetCode {
+                            code: BybitErrorCode::from(*ret_code),
+                            ret_code: *ret_code,
+                            ret_msg: ret_msg.clone().unwrap_or_default(),
+                            body,
+                        }),
+                        Err(_) => Err(parse_error(status, response_body)),
+                    };
+                }
+            }
+            if self.options.unwrap_result {
+                // `result` is sliced out of the body without copying it, so that it can be kept by a ResponseBuffer
+                let result = match envelope.and_then(|envelope| envelope.result) {
+                    Some(range) => R::from_body(response_body.slice(range)).map_err(|(error, _)| error),
+                    None => Err(<serde_json::Error as serde::de::Error>::missing_field("result")),
+                };
+                return result.map_err(|error| {
+                    log::debug!("Failed to parse response due to an error: {}", error);
+                    parse_error(status, response_body)
+                });
+            }
+        }
+        util::handle_json_response(status, &headers, response_body, parse_error, |status, _, parsed| {
+            parsed.map(|parsed| if status == 403 {
+                BybitHandlerError::IpBan(parsed)
+            } else {
+                BybitHandlerError::ApiError(parsed)
+            })
+        })
+    }
+}
+
+/// The code of a response, which is all that [retry_decision()][RequestHandler::retry_decision()] needs.
+#[derive(Deserialize)]
+struct RetCode {
+    #[serde(rename = "retCode", alias = "ret_code")]
+    ret_code: Option<i64>,
+}
+
+/// The fields shared by all responses. Older APIs use `ret_code` instead of `retCode`.
+#[derive(Deserialize)]
+struct RawEnvelope<'a> {
+    #[serde(rename = "retCode", alias = "ret_code")]
+    ret_code: Option<i64>,
+    #[serde(rename = "retMsg", alias = "ret_msg")]
+    ret_msg: Option<String>,
+    #[serde(borrow, default, deserialize_with = "some_raw_value")]
+    result: Option<&'a RawValue>,
+}
+
+/// Deserializes a present field into `Some`, even if it is `null`.
+fn some_raw_value<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<&'de RawValue>, D::Error> {
+    <&RawValue>::deserialize(deserializer).map(Some)
+}
+
+/// A [RawEnvelope] which doesn't borrow the body. `result` is the position of the `result` field in the body.
+struct BybitEnvelope {
+    ret_code: Option<i64>,
+    ret_msg: Option<String>,
+    result: Option<Range<usize>>,
+}
+
+impl BybitEnvelope {
+    fn parse(body: &[u8]) -> Option<Self> {
+        let envelope: RawEnvelope = serde_json::from_slice(body).ok()?;
+        Some(Self {
+            ret_code: envelope.ret_code,
+            ret_msg: envelope.ret_msg,
+            result: envelope.result.map(|result| {
+                let start = result.get().as_ptr() as usize - body.as_ptr() as usize;
+                start..start + result.get().len()
+            }),
+        })
+    }
+}
+
+impl<'a, R> BybitRequestHandler<'a, R> where R: ResponseBody {
+    async fn build<B>(&self, mut builder: RequestBuilder, request_body: &Option<B>, signing: Signing) -> Result<Request, BuildError>
+    where
+        B: Serialize,
+    {
+        if let Some(name) = &self.options.unknown_account {
+            return Err(BuildError::UnknownAccount(name.clone()));
+        }
         if self.options.http_auth == BybitHttpAuth::None {
             if let Some(body) = request_body {
-                let json = serde_json::to_string(body).or(Err("could not serialize body as application/json"))?;
+                let json = serde_json::to_string(body).map_err(|error| BuildError::serialize("application/json", error))?;
                 builder = builder
                     .header(header::CONTENT_TYPE, "application/json")
                     .body(json);
             }
-            return builder.build().or(Err("failed to build request"));
+            return builder.build().map_err(BuildError::Request);
         }
 
-        let key = self.options.key.as_deref().ok_or("API key not set")?;
-        let secret = self.options.secret.as_deref().ok_or("API secret not set")?;
-
-        let time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap(); // always after the epoch
-        let timestamp = time.as_millis();
+        let credentials = credentials::resolve(&self.options.credentials, &self.options.key, &self.options.secret)?;
+        let key = credentials.key.as_str();
+        let signer = signer::signer_or_hmac(&self.options.signer, credentials.secret.as_ref())?;
 
-        let hmac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap(); // hmac accepts key of any length
+        let timestamp = u128::from(self.options.server_clock.now_millis());
 
         match self.options.http_auth {
-            BybitHttpAuth::SpotV1 => Self::v1_auth(builder, request_body, key, timestamp, hmac, true, self.options.recv_window),
-            BybitHttpAuth::BelowV3 => Self::v1_auth(builder, request_body, key, timestamp, hmac, false, self.options.recv_window),
-            BybitHttpAuth::UsdcContractV1 => Self::v3_auth(builder, request_body, key, timestamp, hmac, true, self.options.recv_window),
-            BybitHttpAuth::V3AndAbove => Self::v3_auth(builder, request_body, key, timestamp, hmac, false, self.options.recv_window),
+            BybitHttpAuth::SpotV1 => Self::v1_auth(builder, request_body, key, timestamp, &*signer, true, self.options.recv_window, signing).await,
+            BybitHttpAuth::BelowV3 => Self::v1_auth(builder, request_body, key, timestamp, &*signer, false, self.options.recv_window, signing).await,
+            BybitHttpAuth::UsdcContractV1 => Self::v3_auth(builder, request_body, key, timestamp, &*signer, true, self.options.recv_window, signing).await,
+            BybitHttpAuth::V3AndAbove => Self::v3_auth(builder, request_body, key, timestamp, &*signer, false, self.options.recv_window, signing).await,
             BybitHttpAuth::None => unreachable!(), // we've already handled this case
         }
     }
 
-    fn handle_response(&self, status: StatusCode, _: HeaderMap, response_body: Bytes) -> Result<Self::Successful, Self::Unsuccessful> {
-        if status.is_success() {
-            serde_json::from_slice(&response_body).map_err(|error| {
-                log::debug!("Failed to parse response due to an error: {}", error);
-                BybitHandlerError::ParseError
-            })
-        } else {
-            // https://bybit-exchange.github.io/docs/spot/v3/#t-ratelimits
-            let error = match serde_json::from_slice(&response_body) {
-                Ok(parsed) => {
-                    if status == 403 {
-                        BybitHandlerError::IpBan(parsed)
-                    } else {
-                        BybitHandlerError::ApiError(parsed)
-                    }
-                }
-                Err(error) => {
-                    log::debug!("Failed to parse error response due to an error: {}", error);
-                    BybitHandlerError::ParseError
-                },
-            };
-            Err(error)
-        }
-    }
-}
-
-impl<'a, R> BybitRequestHandler<'a, R> where R: DeserializeOwned {
-    fn v1_auth<B>(builder: RequestBuilder, request_body: &Option<B>, key: &str, timestamp: u128, mut hmac: Hmac<Sha256>, spot: bool, window: Option<i32>)
-        -> Result<Request, <BybitRequestHandler<'a, R> as RequestHandler<B>>::BuildError>
+    #[allow(clippy::too_many_arguments)]
+    async fn v1_auth<B>(
+        builder: RequestBuilder, request_body: &Option<B>, key: &str, timestamp: u128, signer: &dyn Signer, spot: bool, window: Option<i32>, signing: Signing,
+    ) -> Result<Request, <BybitRequestHandler<'a, R> as RequestHandler<B>>::BuildError>
     where
         B: Serialize,
     {
@@ -224,7 +462,11 @@ impl<'a, R> BybitRequestHandler<'a, R> where R: DeserializeOwned {
             urlencoded
         }
 
-        let mut request = builder.build().or(Err("failed to build request"))?;
+        if signer.algorithm() != SignatureAlgorithm::HmacSha256 {
+            return Err(BuildError::UnsupportedKey("only HMAC keys are supported by this version of the API"));
+        }
+
+        let mut request = builder.build().map_err(BuildError::Request)?;
         if matches!(*request.method(), Method::GET | Method::DELETE) {
             let mut queries: Vec<_> = request.url().query_pairs().collect();
             if let Some(window) = window {
@@ -237,25 +479,24 @@ impl<'a, R> BybitRequestHandler<'a, R> where R: DeserializeOwned {
             let query = sort_and_add(queries, key, timestamp);
             request.url_mut().set_query(Some(&query));
 
-            hmac.update(query.as_bytes());
-            let signature = hex::encode(hmac.finalize().into_bytes());
+            let signature = signer::sign_hex(signer, query.as_bytes(), signing).await?;
 
             request.url_mut().query_pairs_mut().append_pair("sign", &signature);
 
             if let Some(body) = request_body {
                 if spot {
-                    let body_string = serde_urlencoded::to_string(body).or(Err("could not serialize body as application/x-www-form-urlencoded"))?;
+                    let body_string = serde_urlencoded::to_string(body).map_err(|error| BuildError::serialize("application/x-www-form-urlencoded", error))?;
                     *request.body_mut() = Some(body_string.into());
                     request.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static("application/x-www-form-urlencoded"));
                 } else {
-                    let body_string = serde_json::to_string(body).or(Err("could not serialize body as application/json"))?;
+                    let body_string = serde_json::to_string(body).map_err(|error| BuildError::serialize("application/json", error))?;
                     *request.body_mut() = Some(body_string.into());
                     request.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
                 }
             }
         } else {
             let mut body = if let Some(body) = request_body {
-                serde_urlencoded::to_string(body).or(Err("could not serialize body as application/x-www-form-urlencoded"))?
+                serde_urlencoded::to_string(body).map_err(|error| BuildError::serialize("application/x-www-form-urlencoded", error))?
             } else {
                 String::new()
             };
@@ -277,8 +518,7 @@ impl<'a, R> BybitRequestHandler<'a, R> where R: DeserializeOwned {
                 .collect();
             let mut sorted_query_string = sort_and_add(pairs, key, timestamp);
 
-            hmac.update(sorted_query_string.as_bytes());
-            let signature = hex::encode(hmac.finalize().into_bytes());
+            let signature = signer::sign_hex(signer, sorted_query_string.as_bytes(), signing).await?;
 
             sorted_query_string.push_str(&format!("&sign={signature}"));
 
@@ -294,13 +534,20 @@ impl<'a, R> BybitRequestHandler<'a, R> where R: DeserializeOwned {
         Ok(request)
     }
 
-    fn v3_auth<B>(mut builder: RequestBuilder, request_body: &Option<B>, key: &str, timestamp: u128, mut hmac: Hmac<Sha256>, version_header: bool, window: Option<i32>)
-        -> Result<Request, <BybitRequestHandler<'a, R> as RequestHandler<B>>::BuildError>
+    #[allow(clippy::too_many_arguments)]
+    async fn v3_auth<B>(
+        mut builder: RequestBuilder, request_body: &Option<B>, key: &str, timestamp: u128, signer: &dyn Signer, version_header: bool, window: Option<i32>, signing: Signing,
+    ) -> Result<Request, <BybitRequestHandler<'a, R> as RequestHandler<B>>::BuildError>
     where
         B: Serialize,
     {
+        // the USDC contract API only documents HMAC signatures (`X-BAPI-SIGN-TYPE: 2`)
+        if version_header && signer.algorithm() != SignatureAlgorithm::HmacSha256 {
+            return Err(BuildError::UnsupportedKey("only HMAC keys are supported by this version of the API"));
+        }
+
         let body = if let Some(body) = request_body {
-            let json = serde_json::to_value(body).or(Err("could not serialize body as application/json"))?;
+            let json = serde_json::to_value(body).map_err(|error| BuildError::serialize("application/json", error))?;
             builder = builder
                 .header(header::CONTENT_TYPE, "application/json")
                 .body(json.to_string());
@@ -309,7 +556,7 @@ impl<'a, R> BybitRequestHandler<'a, R> where R: DeserializeOwned {
             None
         };
 
-        let mut request = builder.build().or(Err("failed to build request"))?;
+        let mut request = builder.build().map_err(BuildError::Request)?;
 
         let mut sign_contents = format!("{timestamp}{key}");
         if let Some(window) = window {
@@ -329,15 +576,20 @@ impl<'a, R> BybitRequestHandler<'a, R> where R: DeserializeOwned {
             sign_contents.push_str(&body.to_string());
         }
 
-        hmac.update(sign_contents.as_bytes());
-        let signature = hex::encode(hmac.finalize().into_bytes());
+        // https://bybit-exchange.github.io/docs/v5/guide#create-a-request
+        // HMAC signatures are hex digits and RSA signatures are base64
+        let signature = signer::sign_encoded(signer, sign_contents.as_bytes(), signing).await?;
+        let signature = HeaderValue::from_str(&signature)
+            .map_err(|source| BuildError::InvalidHeaderValue { header: "X-BAPI-SIGN", source })?;
 
         let headers = request.headers_mut();
         if version_header {
+            // 2 means HMAC-SHA256
             headers.insert("X-BAPI-SIGN-TYPE", HeaderValue::from(2));
         }
-        headers.insert("X-BAPI-SIGN", HeaderValue::from_str(&signature).unwrap()); // hex digits are valid
-        headers.insert("X-BAPI-API-KEY", HeaderValue::from_str(key).or(Err("invalid character in API key"))?);
+        headers.insert("X-BAPI-SIGN", signature);
+        headers.insert("X-BAPI-API-KEY", HeaderValue::from_str(key)
+            .map_err(|source| BuildError::InvalidHeaderValue { header: "X-BAPI-API-KEY", source })?);
         headers.insert("X-BAPI-TIMESTAMP", HeaderValue::from(timestamp as u64));
         if let Some(window) = window {
             headers.insert("X-BAPI-RECV-WINDOW", HeaderValue::from(window));
@@ -356,37 +608,17 @@ impl WebSocketHandler for BybitWebSocketHandler {
     }
 
     fn handle_start(&mut self) -> Vec<WebSocketMessage> {
-        if self.options.websocket_auth {
-            if let Some(key) = self.options.key.as_deref() {
-                if let Some(secret) = self.options.secret.as_deref() {
-                    let time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap(); // always after the epoch
-                    let expires = time.as_millis() as u64 + 1000;
-
-                    let mut hmac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap(); // hmac accepts key of any length
-
-                    hmac.update(format!("GET/realtime{expires}").as_bytes());
-                    let signature = hex::encode(hmac.finalize().into_bytes());
-
-                    return vec![
-                        WebSocketMessage::Text(json!({
-                            "op": "auth",
-                            "args": [key, expires, signature],
-                        }).to_string()),
-                    ];
-                } else {
-                    log::debug!("API secret not set.");
-                };
-            } else {
-                log::debug!("API key not set.");
-            };
-        }
-        self.message_subscribe()
+        signer::blocking(self.start(Signing::Blocking))
+    }
+
+    fn handle_start_async(&mut self) -> impl Future<Output = Vec<WebSocketMessage>> + Send + 'static {
+        self.start(Signing::Async)
     }
 
     fn handle_message(&mut self, message: WebSocketMessage) -> Vec<WebSocketMessage> {
         match message {
             WebSocketMessage::Text(message) => {
-                let message: serde_json::Value = match serde_json::from_str(&message) {
+                let message: serde_json::Value = match json::from_string(message) {
                     Ok(message) => message,
                     Err(_) => {
                         log::debug!("Invalid JSON received");
@@ -417,116 +649,92 @@ impl WebSocketHandler for BybitWebSocketHandler {
         }
         vec![]
     }
-}
 
-impl BybitWebSocketHandler {
-    #[inline(always)]
-    fn message_subscribe(&self) -> Vec<WebSocketMessage> {
-        vec![WebSocketMessage::Text(
-            json!({ "op": "subscribe", "args": self.options.websocket_topics }).to_string(),
-        )]
-    }
-}
+    /// Identifies messages whose `data` has `seq` or `u` by the topic, type and those values.
+    fn dedup_key(&self, message: &WebSocketMessage) -> Option<DedupKey> {
+        #[derive(Deserialize)]
+        struct Message<'a> {
+            topic: &'a str,
+            #[serde(rename = "type")]
+            message_type: Option<&'a str>,
+            data: Data,
+        }
 
-impl BybitHttpUrl {
-    /// The URL that this variant represents.
-    #[inline(always)]
-    pub fn as_str(&self) -> &'static str {
-        match self {
-            Self::Bybit => "https://api.bybit.com",
-            Self::Bytick => "https://api.bytick.com",
-            Self::Test => "https://api-testnet.bybit.com",
-            Self::None => "",
+        #[derive(Deserialize)]
+        struct Data {
+            seq: Option<u64>,
+            u: Option<u64>,
         }
-    }
-}
 
-impl BybitWebSocketUrl {
-    /// The URL that this variant represents.
-    #[inline(always)]
-    pub fn as_str(&self) -> &'static str {
-        match self {
-            Self::Bybit => "wss://stream.bybit.com",
-            Self::Bytick => "wss://stream.bytick.com",
-            Self::Test => "wss://stream-testnet.bybit.com",
-            Self::None => "",
+        let WebSocketMessage::Text(message) = message else {
+            return None;
+        };
+        // messages whose data is an array, such as trades, fail to parse here
+        let message: Message = serde_json::from_str(message).ok()?;
+        if message.data.seq.is_none() && message.data.u.is_none() {
+            return None;
         }
+        Some(DedupKey::new((message.topic, message.message_type, message.data.seq, message.data.u)))
     }
 }
 
-impl HandlerOptions for BybitOptions {
-    type OptionItem = BybitOption;
-
-    fn update(&mut self, option: Self::OptionItem) {
-        match option {
-            BybitOption::Default => (),
-            BybitOption::Key(v) => self.key = Some(v),
-            BybitOption::Secret(v) => self.secret = Some(v),
-            BybitOption::HttpUrl(v) => self.http_url = v,
-            BybitOption::HttpAuth(v) => self.http_auth = v,
-            BybitOption::RecvWindow(v) => self.recv_window = Some(v),
-            BybitOption::RequestConfig(v) => self.request_config = v,
-            BybitOption::WebSocketUrl(v) => self.websocket_url = v,
-            BybitOption::WebSocketAuth(v) => self.websocket_auth = v,
-            BybitOption::WebSocketTopics(v) => self.websocket_topics = v,
-            BybitOption::WebSocketConfig(v) => self.websocket_config = v,
+impl BybitWebSocketHandler {
+    /// Returns the first messages of a connection, which are the auth message or the subscriptions.
+    fn start(&self, signing: Signing) -> impl Future<Output = Vec<WebSocketMessage>> + Send + 'static {
+        let auth = self.options.websocket_auth.then(|| self.message_auth(signing));
+        let subscribe = self.message_subscribe();
+        async move {
+            if let Some(auth) = auth {
+                match auth.await {
+                    Ok(message) => return vec![message],
+                    Err(error) => log::error!("Failed to authenticate: {}", error),
+                }
+            }
+            subscribe
         }
     }
-}
 
-impl Default for BybitOptions {
-    fn default() -> Self {
-        let mut websocket_config = WebSocketConfig::new();
-        websocket_config.ignore_duplicate_during_reconnection = true;
-        Self {
-            key: None,
-            secret: None,
-            http_url: BybitHttpUrl::Bybit,
-            http_auth: BybitHttpAuth::None,
-            recv_window: None,
-            request_config: RequestConfig::default(),
-            websocket_url: BybitWebSocketUrl::Bybit,
-            websocket_auth: false,
-            websocket_topics: vec![],
-            websocket_config,
+    /// Creates the auth message. Credentials are loaded on every (re)connection so that rotated credentials are used.
+    fn message_auth(&self, signing: Signing) -> impl Future<Output = Result<WebSocketMessage, BuildError>> + Send + 'static {
+        let credentials = credentials::resolve(&self.options.credentials, &self.options.key, &self.options.secret)
+            .and_then(|credentials| Ok((signer::signer_or_hmac(&self.options.signer, credentials.secret.as_ref())?, credentials)));
+        let expires = self.options.server_clock.now_millis() + 1000;
+        async move {
+            let (signer, credentials) = credentials?;
+            let signature = signer::sign_encoded(&*signer, format!("GET/realtime{expires}").as_bytes(), signing).await?;
+            Ok(WebSocketMessage::Text(json!({
+                "op": "auth",
+                "args": [credentials.key, expires, signature],
+            }).to_string()))
         }
     }
-}
-
-impl<'a, R, B> HttpOption<'a, R, B> for BybitOption
-where
-    R: DeserializeOwned + 'a,
-    B: Serialize,
-{
-    type RequestHandler = BybitRequestHandler<'a, R>;
 
     #[inline(always)]
-    fn request_handler(options: Self::Options) -> Self::RequestHandler {
-        BybitRequestHandler::<'a, R> {
-            options,
-            _phantom: PhantomData,
-        }
+    fn message_subscribe(&self) -> Vec<WebSocketMessage> {
+        vec![WebSocketMessage::Text(
+            json!({ "op": "subscribe", "args": self.options.websocket_topics }).to_string(),
+        )]
     }
 }
 
-impl <H: FnMut(serde_json::Value) + Send + 'static> WebSocketOption<H> for BybitOption {
-    type WebSocketHandler = BybitWebSocketHandler;
-
-    #[inline(always)]
-    fn websocket_handler(handler: H, options: Self::Options) -> Self::WebSocketHandler {
-        BybitWebSocketHandler {
-            message_handler: Box::new(handler),
-            options,
-        }
+impl ServerTimeOption for BybitOption {
+    fn server_time_url(options: &Self::Options) -> String {
+        let prefix = match options.http_url {
+            BybitHttpUrl::None => BybitHttpUrl::Bybit.as_str(),
+            ref url => url.as_str(),
+        };
+        format!("{prefix}/v5/market/time")
     }
-}
 
-impl HandlerOption for BybitOption {
-    type Options = BybitOptions;
-}
+    fn parse_server_time(_: &HeaderMap, response_body: &[u8]) -> Option<SystemTime> {
+        // {"retCode":0,"retMsg":"OK","result":{"timeSecond":"1688639403","timeNano":"1688639403423213947"},"time":1688639403423}
+        let response: serde_json::Value = serde_json::from_slice(response_body).ok()?;
+        let nanos = response["result"]["timeNano"].as_str().and_then(|nanos| nanos.parse().ok())
+            .or_else(|| response["time"].as_u64().map(|millis| millis * 1_000_000))?;
+        Some(SystemTime::UNIX_EPOCH + Duration::from_nanos(nanos))
+    }
 
-impl Default for BybitOption {
-    fn default() -> Self {
-        Self::Default
+    fn server_clock(options: &Self::Options) -> &ServerClock {
+        &options.server_clock
     }
 }
@@ -2,94 +2,146 @@
 //! For example usages, see files in the examples/ directory.
 
 use std::{
+    future::Future,
     marker::PhantomData,
-    time::SystemTime,
+    sync::Arc,
+    time::{SystemTime, Duration},
 };
-use hmac::{Hmac, Mac};
-use sha2::Sha256;
-use serde::{de::DeserializeOwned, Serialize};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use generic_api_client::{http::{*, header::HeaderValue}, websocket::*};
-use crate::traits::*;
+use generic_api_client::{http::{*, header::HeaderValue}, websocket::*, rate_limit::RateLimit};
+use crate::{traits::*, util, json, response::ResponseBody, error::{BuildError, ExchangeError}, clock::ServerClock, nonce::MonotonicNonce, credentials::{self, CredentialProvider}, secret::Secret, signer::{self, Signer, Signing}};
 
 /// The type returned by [Client::request()].
 pub type CoincheckRequestResult<T> = Result<T, CoincheckRequestError>;
-pub type CoincheckRequestError = RequestError<&'static str, CoincheckHandlerError>;
-
-/// Options that can be set when creating handlers
-pub enum CoincheckOption {
-    /// [Default] variant, does nothing
-    Default,
-    /// API key
-    Key(String),
-    /// Api secret
-    Secret(String),
-    /// Base url for HTTP requests
-    HttpUrl(CoincheckHttpUrl),
-    /// Whether [CoincheckRequestHandler] should perform authentication
-    HttpAuth(bool),
-    /// [RequestConfig] used when sending requests.
-    /// `url_prefix` will be overridden by [HttpUrl](Self::HttpUrl) unless `HttpUrl` is [CoincheckHttpUrl::None].
-    RequestConfig(RequestConfig),
-    /// Base url for WebSocket connections
-    WebSocketUrl(CoincheckWebSocketUrl),
-    /// The channels to be subscribed by [WebSocketHandler].
-    WebSocketChannels(Vec<String>),
-    /// [WebSocketConfig] used for creating [WebSocketConnection]s
-    /// `url_prefix` will be overridden by [WebSocketUrl](Self::WebSocketUrl) unless `WebSocketUrl` is [CoincheckWebSocketUrl::None].
-    /// By default, ignore_duplicate_during_reconnection` is set to `true`.
-    WebSocketConfig(WebSocketConfig),
-}
+pub type CoincheckRequestError = RequestError<BuildError, CoincheckHandlerError>;
 
-/// A `struct` that represents a set of [CoincheckOption] s.
-#[derive(Clone, Debug)]
-pub struct CoincheckOptions {
-    /// see [CoincheckOption::Key]
-    pub key: Option<String>,
-    /// see [CoincheckOption::Secret]
-    pub secret: Option<String>,
-    /// see [CoincheckOption::HttpUrl]
-    pub http_url: CoincheckHttpUrl,
-    /// see [CoincheckOption::HttpAuth]
-    pub http_auth: bool,
-    /// see [CoincheckOption::RequestConfig]
-    pub request_config: RequestConfig,
-    /// see [CoincheckOption::WebSocketUrl]
-    pub websocket_url: CoincheckWebSocketUrl,
-    /// see [CoincheckOption::WebSocketChannels]
-    pub websocket_channels: Vec<String>,
-    /// see [CoincheckOption::WebSocketConfig]
-    pub websocket_config: WebSocketConfig,
+exchange_options! {
+    option: CoincheckOption,
+    options: CoincheckOptions,
+    request_handler: CoincheckRequestHandler,
+    websocket_handler: CoincheckWebSocketHandler(serde_json::Value) {},
+    variants: {
+        /// API key
+        Key(String) => key: Option<String> = None, |v| Some(v);
+        /// Api secret
+        Secret(String) => secret: Option<Secret> = None, |v| Some(Secret::new(v));
+        /// [CredentialProvider] consulted every time a request is signed or a WebSocket connection is authenticated.
+        /// If this is set, [Key](Self::Key) and [Secret](Self::Secret) are ignored.
+        Credentials(Arc<dyn CredentialProvider>) => credentials: Option<Arc<dyn CredentialProvider>> = None, |v| Some(v);
+        /// [Signer] used for authentication instead of the HMAC of [Secret](Self::Secret).
+        ///
+        /// See [UnixSocketSigner][crate::signer::UnixSocketSigner] for keeping the secret out of the process.
+        Signer(Arc<dyn Signer>) => signer: Option<Arc<dyn Signer>> = None, |v| Some(v);
+        /// [ServerClock] used for timestamps in authentication.
+        ///
+        /// By default, each [Client][crate::Client] has its own clock which is shared by its clones.
+        /// See [Client::spawn_clock_sync()][crate::Client::spawn_clock_sync()] for synchronizing it.
+        ServerClock(ServerClock) => server_clock: ServerClock = ServerClock::new(), |v| v;
+        /// [MonotonicNonce] used for `ACCESS-NONCE`. The nonce is the time of the [ServerClock] in milliseconds,
+        /// increased if necessary so that nonces for the same API key are strictly increasing.
+        ///
+        /// [Default]s to [MonotonicNonce::shared()], which is shared by the whole process.
+        /// Use [MonotonicNonce::with_file()] if other processes use the same API key.
+        Nonce(MonotonicNonce) => nonce: MonotonicNonce = MonotonicNonce::shared(), |v| v;
+        /// Base url for HTTP requests
+        HttpUrl(CoincheckHttpUrl) => http_url: CoincheckHttpUrl = CoincheckHttpUrl::Default, |v| v;
+        /// Whether [CoincheckRequestHandler] should perform authentication
+        HttpAuth(bool) => http_auth: bool = false, |v| v;
+        /// [RequestConfig] used when sending requests.
+        /// `url_prefix` will be overridden by [HttpUrl](Self::HttpUrl) unless `HttpUrl` is [CoincheckHttpUrl::None].
+        RequestConfig(RequestConfig) => request_config: RequestConfig = RequestConfig::default(), |v| v;
+        /// Whether [CoincheckRequestHandler] should apply client-side rate limits. [Default]s to `true`.
+        ///
+        /// The limits are shared by all clones of a [Client][crate::Client]. See [CoincheckRequestHandler::rate_limits()] for details.
+        RateLimit(bool) => rate_limit: bool = true, |v| v;
+        /// Base url for WebSocket connections
+        WebSocketUrl(CoincheckWebSocketUrl) => websocket_url: CoincheckWebSocketUrl = CoincheckWebSocketUrl::Default, |v| v;
+        /// The channels to be subscribed by [WebSocketHandler].
+        WebSocketChannels(Vec<String>) => websocket_channels: Vec<String> = vec![], |v| v;
+        /// [WebSocketConfig] used for creating [WebSocketConnection]s
+        /// `url_prefix` will be overridden by [WebSocketUrl](Self::WebSocketUrl) unless `WebSocketUrl` is [CoincheckWebSocketUrl::None].
+        /// By default, ignore_duplicate_during_reconnection` is set to `true`.
+        WebSocketConfig(WebSocketConfig) => websocket_config: WebSocketConfig = {
+            let mut websocket_config = WebSocketConfig::new();
+            websocket_config.ignore_duplicate_during_reconnection = true;
+            websocket_config
+        }, |v| v;
+    }
 }
 
-/// A `enum` that represents the base url of the Coincheck HTTP API.
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
-pub enum CoincheckHttpUrl {
-    /// `https://coincheck.com`
-    Default,
-    /// The url will not be modified by [CoincheckRequestHandler]
-    None,
+base_urls! {
+    /// A `enum` that represents the base url of the Coincheck HTTP API.
+    pub enum CoincheckHttpUrl {
+        /// `https://coincheck.com`
+        Default => "https://coincheck.com",
+        /// The url will not be modified by [CoincheckRequestHandler]
+        None => "",
+    }
 }
 
-/// A `enum` that represents the base url of the Coincheck Realtime API
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
-#[non_exhaustive]
-pub enum CoincheckWebSocketUrl {
-    /// `wss://ws-api.coincheck.com/`
-    Default,
-    /// The url will not be modified by [CoincheckWebSocketHandler]
-    None,
+base_urls! {
+    /// A `enum` that represents the base url of the Coincheck Realtime API
+    #[non_exhaustive]
+    pub enum CoincheckWebSocketUrl {
+        /// `wss://ws-api.coincheck.com/`
+        Default => "wss://ws-api.coincheck.com/",
+        /// The url will not be modified by [CoincheckWebSocketHandler]
+        None => "",
+    }
 }
 
 #[derive(Debug)]
 pub enum CoincheckHandlerError {
-    ApiError(serde_json::Value),
+    ApiError { status: StatusCode, body: serde_json::Value },
     RequestLimitExceeded(serde_json::Value),
-    ParseError,
+    /// A response with a successful HTTP status whose `success` is `false`.
+    Failure {
+        /// The `error` of the response.
+        error: String,
+        /// The whole response.
+        body: serde_json::Value,
+    },
+    /// The response could not be parsed.
+    ParseError { status: StatusCode, body: Bytes },
+}
+
+impl ExchangeError for CoincheckHandlerError {
+    fn is_rate_limited(&self) -> bool {
+        match self {
+            Self::RequestLimitExceeded(_) => true,
+            Self::ApiError { status, .. } | Self::ParseError { status, .. } => *status == 429,
+            Self::Failure { .. } => false,
+        }
+    }
+
+    #[inline(always)]
+    fn is_auth_error(&self) -> bool {
+        self.status() == Some(StatusCode::UNAUTHORIZED)
+    }
+
+    fn is_retryable(&self) -> bool {
+        self.is_rate_limited() || self.status().is_some_and(|status| status.is_server_error())
+    }
+
+    fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::RequestLimitExceeded(_) => Some(StatusCode::TOO_MANY_REQUESTS),
+            Self::ApiError { status, .. } | Self::ParseError { status, .. } => Some(*status),
+            Self::Failure { .. } => Some(StatusCode::OK),
+        }
+    }
+
+    fn raw_body(&self) -> Option<&[u8]> {
+        match self {
+            Self::ParseError { body, .. } => Some(body),
+            _ => None,
+        }
+    }
 }
 
 /// A `struct` that implements [RequestHandler]
-pub struct CoincheckRequestHandler<'a, R: DeserializeOwned> {
+pub struct CoincheckRequestHandler<'a, R: ResponseBody> {
     options: CoincheckOptions,
     _phantom: PhantomData<&'a R>,
 }
@@ -100,37 +152,28 @@ pub struct CoincheckWebSocketHandler {
     options: CoincheckOptions,
 }
 
-impl<'a, B, R> RequestHandler<B> for CoincheckRequestHandler<'a, R>
-where
-    B: Serialize,
-    R: DeserializeOwned,
-{
-    type Successful = R;
-    type Unsuccessful = CoincheckHandlerError;
-    type BuildError = &'static str;
-
-    fn request_config(&self) -> RequestConfig {
-        let mut config = self.options.request_config.clone();
-        if self.options.http_url != CoincheckHttpUrl::None {
-            config.url_prefix = self.options.http_url.as_str().to_owned();
+impl<'a, R> CoincheckRequestHandler<'a, R> where R: ResponseBody {
+    async fn build<B>(&self, mut builder: RequestBuilder, request_body: &Option<B>, signing: Signing) -> Result<Request, BuildError>
+    where
+        B: Serialize,
+    {
+        if let Some(name) = &self.options.unknown_account {
+            return Err(BuildError::UnknownAccount(name.clone()));
         }
-        config
-    }
-
-    fn build_request(&self, mut builder: RequestBuilder, request_body: &Option<B>, _: u8) -> Result<Request, Self::BuildError> {
         if let Some(body) = request_body {
-            let encoded = serde_urlencoded::to_string(body).or(Err("could not serialize body as application/x-www-form-urlencoded"))?;
+            let encoded = serde_urlencoded::to_string(body).map_err(|error| BuildError::serialize("application/x-www-form-urlencoded", error))?;
             builder = builder
                 .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
                 .body(encoded);
         }
 
-        let mut request = builder.build().or(Err("failed to build request"))?;
+        let mut request = builder.build().map_err(BuildError::Request)?;
 
         if self.options.http_auth {
             // https://coincheck.com/ja/documents/exchange/api#auth
-            let time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap(); // always after the epoch
-            let nonce = time.as_millis() as u64;
+            let credentials = credentials::resolve(&self.options.credentials, &self.options.key, &self.options.secret)?;
+            let key = credentials.key.as_str();
+            let nonce = self.options.nonce.next(key, self.options.server_clock.now_millis()).map_err(BuildError::Nonce)?;
 
             let body = request.body()
                 .and_then(|body| body.as_bytes())
@@ -139,15 +182,11 @@ where
 
             let sign_contents = format!("{}{}{}", nonce, request.url(), body);
 
-            let secret = self.options.secret.as_deref().ok_or("API secret not set")?;
-            let mut hmac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap(); // hmac accepts key of any length
-
-            hmac.update(sign_contents.as_bytes());
-            let signature = hex::encode(hmac.finalize().into_bytes());
+            let signer = signer::signer_or_hmac(&self.options.signer, credentials.secret.as_ref())?;
+            let signature = signer::sign_hex(&*signer, sign_contents.as_bytes(), signing).await?;
 
-            let key = HeaderValue::from_str(self.options.key.as_deref().ok_or("API key not set")?).or(
-                Err("invalid character in API key")
-            )?;
+            let key = HeaderValue::from_str(key)
+                .map_err(|source| BuildError::InvalidHeaderValue { header: "ACCESS-KEY", source })?;
             let headers = request.headers_mut();
             headers.insert("ACCESS-KEY", key);
             headers.insert("ACCESS-NONCE", HeaderValue::from(nonce));
@@ -156,32 +195,105 @@ where
 
         Ok(request)
     }
+}
 
-    fn handle_response(&self, status: StatusCode, _: HeaderMap, response_body: Bytes) -> Result<Self::Successful, Self::Unsuccessful> {
+impl<'a, B, R> RequestHandler<B> for CoincheckRequestHandler<'a, R>
+where
+    B: Serialize,
+    R: ResponseBody,
+{
+    type Successful = R;
+    type Unsuccessful = CoincheckHandlerError;
+    type BuildError = BuildError;
+
+    fn request_config(&self) -> RequestConfig {
+        let mut config = self.options.request_config.clone();
+        if self.options.http_url != CoincheckHttpUrl::None {
+            config.url_prefix = self.options.http_url.as_str().to_owned();
+        }
+        config
+    }
+
+    /// Applies a conservative limit of 5 requests per second per IP and per API key,
+    /// because Coincheck does not document its limits in detail.
+    fn rate_limits(&self, _: &Request) -> Vec<RateLimit> {
+        if !self.options.rate_limit {
+            return vec![];
+        }
+        let mut limits = vec![RateLimit::new("coincheck:ip", 5, Duration::from_secs(1), 1)];
+        if self.options.http_auth {
+            if let Ok(credentials) = credentials::resolve(&self.options.credentials, &self.options.key, &self.options.secret) {
+                limits.push(RateLimit::new(format!("coincheck:{}", util::key_id(&credentials.key)), 5, Duration::from_secs(1), 1));
+            }
+        }
+        limits
+    }
+
+    fn build_request(&self, builder: RequestBuilder, request_body: &Option<B>, _: u8) -> Result<Request, Self::BuildError> {
+        signer::blocking(self.build(builder, request_body, Signing::Blocking))
+    }
+
+    fn build_request_async(&self, builder: RequestBuilder, request_body: &Option<B>, _: u8) -> impl Future<Output = Result<Request, Self::BuildError>> {
+        self.build(builder, request_body, Signing::Async)
+    }
+
+    /// Retries requests rejected because of `ACCESS-NONCE` after requesting a resynchronization of the [ServerClock],
+    /// which the nonces are taken from.
+    fn retry_decision(&self, method: &Method, status: StatusCode, headers: &HeaderMap, response_body: &Bytes) -> RetryDecision {
+        // errors look like {"success": false, "error": "Nonce must be incremented"}
+        if let Ok(CoincheckResponseError { success: Some(false), error: Some(error) }) = json::from_slice(response_body) {
+            if error.to_ascii_lowercase().contains("nonce") {
+                // the request is signed again with a new nonce when retrying
+                log::debug!("Nonce rejected by the server: {}", error);
+                self.options.server_clock.observe_date_header(headers);
+                self.options.server_clock.request_resync();
+                return RetryDecision::Retry;
+            }
+        }
+        default_retry_decision(method, status, headers)
+    }
+
+    fn handle_response(&self, status: StatusCode, headers: HeaderMap, response_body: Bytes) -> Result<Self::Successful, Self::Unsuccessful> {
+        let parse_error = |status, body| CoincheckHandlerError::ParseError { status, body };
         if status.is_success() {
-            serde_json::from_slice(&response_body).map_err(|error| {
-                log::debug!("Failed to parse response due to an error: {}", error);
-                CoincheckHandlerError::ParseError
-            })
-        } else {
-            let error = match serde_json::from_slice(&response_body) {
-                Ok(parsed_error) => {
-                    if status == 429 {
-                        CoincheckHandlerError::RequestLimitExceeded(parsed_error)
-                    } else {
-                        CoincheckHandlerError::ApiError(parsed_error)
-                    }
-                },
-                Err(error) => {
-                    log::debug!("Failed to parse error response due to an error: {}", error);
-                    CoincheckHandlerError::ParseError
-                }
-            };
-            Err(error)
+            // some errors are returned with HTTP 200 and "success": false
+            if let Ok(CoincheckResponseSuccess { success: Some(false) }) = json::from_slice(&response_body) {
+                return match json::from_slice::<serde_json::Value>(&response_body) {
+                    Ok(body) => Err(CoincheckHandlerError::Failure {
+                        error: match &body["error"] {
+                            serde_json::Value::String(error) => error.clone(),
+                            serde_json::Value::Null => String::new(),
+                            error => error.to_string(),
+                        },
+                        body,
+                    }),
+                    Err(_) => Err(parse_error(status, response_body)),
+                };
+            }
         }
+        util::handle_json_response(status, &headers, response_body, parse_error, |status, _, parsed| {
+            parsed.map(|body| if status == 429 {
+                CoincheckHandlerError::RequestLimitExceeded(body)
+            } else {
+                CoincheckHandlerError::ApiError { status, body }
+            })
+        })
     }
 }
 
+/// The part of a response used to detect errors.
+#[derive(Deserialize)]
+struct CoincheckResponseSuccess {
+    success: Option<bool>,
+}
+
+/// The part of a response used to detect errors which are retried.
+#[derive(Deserialize)]
+struct CoincheckResponseError {
+    success: Option<bool>,
+    error: Option<String>,
+}
+
 impl WebSocketHandler for CoincheckWebSocketHandler {
     fn websocket_config(&self) -> WebSocketConfig {
         let mut config = self.options.websocket_config.clone();
@@ -200,7 +312,7 @@ impl WebSocketHandler for CoincheckWebSocketHandler {
     fn handle_message(&mut self, message: WebSocketMessage) -> Vec<WebSocketMessage> {
         match message {
             WebSocketMessage::Text(message) => {
-                match serde_json::from_str(&message) {
+                match json::from_string(message) {
                     Ok(message) => (self.message_handler)(message),
                     Err(_) => log::debug!("Invalid JSON message received"),
                 };
@@ -210,99 +322,39 @@ impl WebSocketHandler for CoincheckWebSocketHandler {
         }
         vec![]
     }
-}
 
-impl CoincheckHttpUrl {
-    /// The base URL that this variant represents.
-    #[inline(always)]
-    fn as_str(&self) -> &'static str {
-        match self {
-            Self::Default => "https://coincheck.com",
-            Self::None => "",
-        }
+    /// Identifies trade messages by the pairs and ids of the trades.
+    ///
+    /// Order book messages are not identified because `last_update_at` only has a resolution of seconds.
+    fn dedup_key(&self, message: &WebSocketMessage) -> Option<DedupKey> {
+        let WebSocketMessage::Text(message) = message else {
+            return None;
+        };
+        // [[timestamp, id, pair, rate, amount, order_type, taker_order_id, maker_order_id], ...]
+        let trades: Vec<Vec<&str>> = serde_json::from_str(message).ok()?;
+        let trades = trades.iter()
+            .map(|trade| Some((*trade.get(2)?, *trade.get(1)?)))
+            .collect::<Option<Vec<_>>>()?;
+        Some(DedupKey::new(trades))
     }
 }
 
-impl CoincheckWebSocketUrl {
-    /// The base URL that this variant represents.
-    #[inline(always)]
-    fn as_str(&self) -> &'static str {
-        match self {
-            Self::Default => "wss://ws-api.coincheck.com/",
-            Self::None => "",
-        }
+impl ServerTimeOption for CoincheckOption {
+    fn server_time_url(options: &Self::Options) -> String {
+        let prefix = match options.http_url {
+            CoincheckHttpUrl::None => CoincheckHttpUrl::Default.as_str(),
+            url => url.as_str(),
+        };
+        // Coincheck has no endpoint for the server time, so the Date header of a lightweight endpoint is used
+        format!("{prefix}/api/ticker")
     }
-}
-
-impl HandlerOptions for CoincheckOptions {
-    type OptionItem = CoincheckOption;
-
-    fn update(&mut self, option: Self::OptionItem) {
-        match option {
-            CoincheckOption::Default => (),
-            CoincheckOption::Key(v) => self.key = Some(v),
-            CoincheckOption::Secret(v) => self.secret = Some(v),
-            CoincheckOption::HttpUrl(v) => self.http_url = v,
-            CoincheckOption::HttpAuth(v) => self.http_auth = v,
-            CoincheckOption::RequestConfig(v) => self.request_config = v,
-            CoincheckOption::WebSocketUrl(v) => self.websocket_url = v,
-            CoincheckOption::WebSocketChannels(v) => self.websocket_channels = v,
-            CoincheckOption::WebSocketConfig(v) => self.websocket_config = v,
-        }
-    }
-}
-
-impl Default for CoincheckOptions {
-    fn default() -> Self {
-        let mut websocket_config = WebSocketConfig::new();
-        websocket_config.ignore_duplicate_during_reconnection = true;
-        Self {
-            key: None,
-            secret: None,
-            http_url: CoincheckHttpUrl::Default,
-            http_auth: false,
-            request_config: RequestConfig::default(),
-            websocket_url: CoincheckWebSocketUrl::Default,
-            websocket_channels: vec![],
-            websocket_config,
-        }
-    }
-}
-
-impl<'a, R, B> HttpOption<'a, R, B> for CoincheckOption
-where
-    R: DeserializeOwned + 'a,
-    B: Serialize,
-{
-    type RequestHandler = CoincheckRequestHandler<'a, R>;
-
-    #[inline(always)]
-    fn request_handler(options: Self::Options) -> Self::RequestHandler {
-        CoincheckRequestHandler::<'a, R> {
-            options,
-            _phantom: PhantomData,
-        }
-    }
-}
-
-impl<H: FnMut(serde_json::Value) + Send + 'static> WebSocketOption<H> for CoincheckOption {
-    type WebSocketHandler = CoincheckWebSocketHandler;
 
     #[inline(always)]
-    fn websocket_handler(handler: H, options: Self::Options) -> Self::WebSocketHandler {
-        CoincheckWebSocketHandler {
-            message_handler: Box::new(handler),
-            options,
-        }
+    fn parse_server_time(headers: &HeaderMap, response_body: &[u8]) -> Option<SystemTime> {
+        crate::clock::parse_date_header(headers, response_body)
     }
-}
-
-impl HandlerOption for CoincheckOption {
-    type Options = CoincheckOptions;
-}
 
-impl Default for CoincheckOption {
-    fn default() -> Self {
-        Self::Default
+    fn server_clock(options: &Self::Options) -> &ServerClock {
+        &options.server_clock
     }
 }
@@ -0,0 +1,189 @@
+//! Macros that generate the parts of an exchange module which are the same for every exchange.
+//!
+//! With these, an exchange module only has to describe its options and base urls, and implement
+//! [RequestHandler][generic_api_client::http::RequestHandler] and [WebSocketHandler][generic_api_client::websocket::WebSocketHandler]
+//! (mostly the authentication). See `coincheck.rs` for the smallest example.
+
+/// Defines the `*Option` enum and the `*Options` struct of an exchange, and implements
+/// [HandlerOption][crate::traits::HandlerOption], [HandlerOptions][crate::traits::HandlerOptions],
+/// [HttpOption][crate::traits::HttpOption] and [WebSocketOption][crate::traits::WebSocketOption] for them.
+///
+/// ```ignore
+/// exchange_options! {
+///     option: ExampleOption,
+///     options: ExampleOptions,
+///     request_handler: ExampleRequestHandler,
+///     // the type passed to the message handler, and the initial values of the other fields of the handler
+///     websocket_handler: ExampleWebSocketHandler(serde_json::Value) { auth_id: None },
+///     variants: {
+///         /// API key
+///         Key(String) => key: Option<String> = None, |v| Some(v);
+///         // variants without the type of a field update an existing field
+///         /// PEM encoded private key
+///         PrivateKeyPem(String) => signer, |v| Some(signer::signer_from_pem_or_invalid(v));
+///     }
+/// }
+/// ```
+///
+/// The `Default` and `Account` variants are always added. Variants which declare the type of their field also declare
+/// the field, in the order of the variants. The request handler must have the fields `options` and `_phantom`,
+/// and the WebSocket handler must have the fields `message_handler` and `options`.
+macro_rules! exchange_options {
+    (
+        option: $Option:ident,
+        options: $Options:ident,
+        request_handler: $RequestHandler:ident,
+        websocket_handler: $WebSocketHandler:ident($Message:ty) { $($handler_field:ident: $handler_init:expr),* $(,)? },
+        variants: {
+            $(
+                $(#[$meta:meta])*
+                $Variant:ident($Type:ty) => $field:ident $(: $FieldType:ty = $default:expr)?, |$v:ident| $update:expr;
+            )*
+        } $(,)?
+    ) => {
+        /// Options that can be set when creating handlers
+        #[derive(Default)]
+        pub enum $Option {
+            /// [Default] variant, does nothing
+            #[default]
+            Default,
+            /// Name of an account profile registered with [Client::add_account()][crate::Client::add_account()].
+            /// Handlers are created from the options of that profile instead of the default options of the [Client][crate::Client].
+            ///
+            /// This only has an effect when it is passed to the methods of [Client][crate::Client]. If the account is not registered,
+            /// requests fail with [BuildError::UnknownAccount][crate::error::BuildError::UnknownAccount] and WebSocket connections fail to start.
+            Account(String),
+            $(
+                $(#[$meta])*
+                $Variant($Type),
+            )*
+        }
+
+        #[doc = concat!("A `struct` that represents a set of [", stringify!($Option), "] s.")]
+        #[derive(Clone, Debug)]
+        pub struct $Options {
+            $($(
+                #[doc = concat!("see [", stringify!($Option), "::", stringify!($Variant), "]")]
+                pub $field: $FieldType,
+            )?)*
+            /// The account profile selected with [Account](#variant.Account) if it is not registered.
+            /// Requests fail to be built if this is set.
+            pub unknown_account: Option<String>,
+        }
+
+        impl $crate::traits::HandlerOptions for $Options {
+            type OptionItem = $Option;
+
+            fn update(&mut self, option: Self::OptionItem) {
+                match option {
+                    $Option::Default => (),
+                    $Option::Account(_) => (), // handled by Client
+                    $($Option::$Variant($v) => self.$field = $update,)*
+                }
+            }
+
+            #[inline(always)]
+            fn set_unknown_account(&mut self, name: String) {
+                self.unknown_account = Some(name);
+            }
+        }
+
+        impl Default for $Options {
+            fn default() -> Self {
+                Self {
+                    $($($field: $default,)?)*
+                    unknown_account: None,
+                }
+            }
+        }
+
+        impl $crate::traits::HandlerOption for $Option {
+            type Options = $Options;
+
+            #[inline(always)]
+            fn account_name(&self) -> Option<&str> {
+                match self {
+                    Self::Account(name) => Some(name),
+                    _ => None,
+                }
+            }
+        }
+
+        impl<'a, R, B> $crate::traits::HttpOption<'a, R, B> for $Option
+        where
+            R: $crate::response::ResponseBody + 'a,
+            B: serde::Serialize,
+        {
+            type RequestHandler = $RequestHandler<'a, R>;
+
+            #[inline(always)]
+            fn request_handler(options: Self::Options) -> Self::RequestHandler {
+                $RequestHandler::<'a, R> {
+                    options,
+                    _phantom: std::marker::PhantomData,
+                }
+            }
+        }
+
+        impl<H: FnMut($Message) + Send + 'static> $crate::traits::WebSocketOption<H> for $Option {
+            type WebSocketHandler = $WebSocketHandler;
+
+            #[inline(always)]
+            fn websocket_handler(handler: H, options: Self::Options) -> Self::WebSocketHandler {
+                $WebSocketHandler {
+                    message_handler: Box::new(handler),
+                    $($handler_field: $handler_init,)*
+                    options,
+                }
+            }
+        }
+
+        impl $crate::traits::WebSocketStreamOption for $Option {
+            type Message = $Message;
+        }
+    };
+}
+
+/// Defines an `enum` of base urls with an `as_str()` method that returns the url of each variant.
+///
+/// ```ignore
+/// base_urls! {
+///     /// A `enum` that represents the base url of the Example HTTP API.
+///     pub enum ExampleHttpUrl {
+///         /// `https://api.example.com`
+///         Default => "https://api.example.com",
+///         /// The url will not be modified by [ExampleRequestHandler]
+///         None => "",
+///     }
+/// }
+/// ```
+macro_rules! base_urls {
+    (
+        $(#[$meta:meta])*
+        pub enum $Url:ident {
+            $(
+                $(#[$variant_meta:meta])*
+                $Variant:ident => $url:literal,
+            )*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Eq, PartialEq, Copy, Clone)]
+        pub enum $Url {
+            $(
+                $(#[$variant_meta])*
+                $Variant,
+            )*
+        }
+
+        impl $Url {
+            /// The base URL that this variant represents.
+            #[inline(always)]
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $(Self::$Variant => $url,)*
+                }
+            }
+        }
+    };
+}
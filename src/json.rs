@@ -0,0 +1,73 @@
+//! JSON parsing of responses and WebSocket messages.
+//!
+//! With the `simd-json` feature, [simd-json](https://docs.rs/simd-json) is used instead of `serde_json`.
+//! Errors are converted to [serde_json::Error] so that the error types don't depend on the feature.
+
+use generic_api_client::http::Bytes;
+use serde::de::{Deserialize, DeserializeOwned};
+
+/// Parses `slice` as JSON.
+///
+/// simd-json parses in place, so `slice` is copied when the `simd-json` feature is enabled.
+#[cfg(any(feature = "binance", feature = "bitflyer", feature = "bybit", feature = "coincheck"))]
+#[cfg(not(feature = "simd-json"))]
+#[inline(always)]
+pub(crate) fn from_slice<T: DeserializeOwned>(slice: &[u8]) -> Result<T, serde_json::Error> {
+    serde_json::from_slice(slice)
+}
+
+#[cfg(any(feature = "binance", feature = "bitflyer", feature = "bybit", feature = "coincheck"))]
+#[cfg(feature = "simd-json")]
+#[inline(always)]
+pub(crate) fn from_slice<T: DeserializeOwned>(slice: &[u8]) -> Result<T, serde_json::Error> {
+    from_vec(slice.to_vec())
+}
+
+/// Parses `bytes` as JSON. If it can't be parsed, `bytes` is returned with the error.
+///
+/// simd-json parses in place, so `bytes` is copied when the `simd-json` feature is enabled and the returned `bytes` are
+/// always the untouched body.
+#[cfg(not(feature = "simd-json"))]
+#[inline(always)]
+pub(crate) fn from_bytes<T: DeserializeOwned>(bytes: Bytes) -> Result<T, (serde_json::Error, Bytes)> {
+    serde_json::from_slice(&bytes).map_err(|error| (error, bytes))
+}
+
+#[cfg(feature = "simd-json")]
+#[inline(always)]
+pub(crate) fn from_bytes<T: DeserializeOwned>(bytes: Bytes) -> Result<T, (serde_json::Error, Bytes)> {
+    from_vec(bytes.to_vec()).map_err(|error| (error, bytes))
+}
+
+/// Parses `slice` as JSON into a value which may borrow from it.
+///
+/// simd-json needs a mutable buffer to borrow from, so this uses `serde_json` even if the `simd-json` feature is enabled.
+#[inline(always)]
+pub(crate) fn from_borrowed_slice<'a, T: Deserialize<'a>>(slice: &'a [u8]) -> Result<T, serde_json::Error> {
+    serde_json::from_slice(slice)
+}
+
+/// Parses `string` as JSON, reusing its buffer when the `simd-json` feature is enabled.
+#[cfg(any(feature = "binance", feature = "bitflyer", feature = "bybit", feature = "coincheck"))]
+#[cfg(not(feature = "simd-json"))]
+#[inline(always)]
+pub(crate) fn from_string<T: DeserializeOwned>(string: String) -> Result<T, serde_json::Error> {
+    serde_json::from_str(&string)
+}
+
+#[cfg(any(feature = "binance", feature = "bitflyer", feature = "bybit", feature = "coincheck"))]
+#[cfg(feature = "simd-json")]
+#[inline(always)]
+pub(crate) fn from_string<T: DeserializeOwned>(string: String) -> Result<T, serde_json::Error> {
+    from_vec(string.into_bytes())
+}
+
+#[cfg(feature = "simd-json")]
+fn from_vec<T: DeserializeOwned>(mut buffer: Vec<u8>) -> Result<T, serde_json::Error> {
+    simd_json::serde::from_slice(&mut buffer).map_err(convert_error)
+}
+
+#[cfg(feature = "simd-json")]
+fn convert_error(error: simd_json::Error) -> serde_json::Error {
+    <serde_json::Error as serde::de::Error>::custom(error)
+}
@@ -1,14 +1,29 @@
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
+use std::{collections::HashMap, time::Duration};
 use generic_api_client::{http::{self, *}, websocket::*};
 use serde::Serialize;
+use tokio::task::JoinHandle;
 use traits::*;
+use clock::{ClockSyncError, ServerTimeHandler};
+use options_map::OptionsMap;
 
 pub use generic_api_client;
 pub use exchanges::*;
 
 mod exchanges;
 pub mod traits;
+pub mod clock;
+pub mod nonce;
+pub mod secret;
+pub mod credentials;
+pub mod error;
+pub mod response;
+pub mod stream;
+pub mod signer;
+mod util;
+mod json;
+mod options_map;
 
 // very long type, make it a macro
 macro_rules! request_return_type {
@@ -23,17 +38,29 @@ macro_rules! request_return_type {
     };
 }
 
-#[derive(Default, Debug, Clone)]
+#[derive(Debug, Clone)]
 pub struct Client {
     client: http::Client,
-    #[cfg(feature = "binance")]
-    binance: binance::BinanceOptions,
-    #[cfg(feature = "bitflyer")]
-    bitflyer: bitflyer::BitFlyerOptions,
-    #[cfg(feature = "bybit")]
-    bybit: bybit::BybitOptions,
-    #[cfg(feature = "coincheck")]
-    coincheck: coincheck::CoincheckOptions,
+    options: OptionsMap,
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        #[allow(unused_mut)]
+        let mut options = OptionsMap::default();
+        #[cfg(feature = "binance")]
+        options.register(binance::BinanceOptions::default());
+        #[cfg(feature = "bitflyer")]
+        options.register(bitflyer::BitFlyerOptions::default());
+        #[cfg(feature = "bybit")]
+        options.register(bybit::BybitOptions::default());
+        #[cfg(feature = "coincheck")]
+        options.register(coincheck::CoincheckOptions::default());
+        Self {
+            client: http::Client::default(),
+            options,
+        }
+    }
 }
 
 impl Client {
@@ -42,6 +69,34 @@ impl Client {
         Self::default()
     }
 
+    /// Returns the [RateLimiter][generic_api_client::rate_limit::RateLimiter] shared by this [Client] and its clones.
+    #[inline(always)]
+    pub fn rate_limiter(&self) -> &generic_api_client::rate_limit::RateLimiter {
+        self.client.rate_limiter()
+    }
+
+    /// Returns the usage of Binance's rate limits for the given base url.
+    ///
+    /// The `ORDERS` limits are per account, so they are only returned when `key` is set.
+    /// Limits are only tracked after a request was sent with [BinanceOption::RateLimit][binance::BinanceOption::RateLimit] enabled.
+    #[cfg(feature = "binance")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "binance")))]
+    pub fn binance_usage(&self, url: binance::BinanceHttpUrl, key: Option<&str>) -> binance::BinanceUsage {
+        binance::usage(self.rate_limiter(), url, key)
+    }
+
+    /// Registers the default options of a user-defined exchange, so that its [HandlerOption]s can be used with the methods
+    /// of this [Client] in the same way as the exchanges in this crate.
+    ///
+    /// If options of the same type are already registered, their default options are replaced.
+    #[inline(always)]
+    pub fn register_options<O>(&mut self, options: O)
+    where
+        O: HandlerOptions + Send + Sync + 'static,
+    {
+        self.options.register(options);
+    }
+
     /// Update the default options for this [Client]
     #[inline(always)]
     pub fn update_default_option<O>(&mut self, option: O)
@@ -52,17 +107,80 @@ impl Client {
         self.default_options_mut().update(option);
     }
 
+    /// Registers an account profile named `name`, which starts from the current default options updated with `options`.
+    ///
+    /// The profile is selected by passing the `Account` option of the exchange (such as `BinanceOption::Account("hedge".to_owned())`)
+    /// to the methods of this [Client]. Profiles share the HTTP connection pool, the rate limiter and (unless it is replaced)
+    /// the [ServerClock][clock::ServerClock] of this [Client], but have their own credentials, urls and other options.
+    /// An existing profile with the same name is replaced.
+    pub fn add_account<O>(&mut self, name: impl Into<String>, options: impl IntoIterator<Item=O>)
+    where
+        O: HandlerOption,
+        Self: GetOptions<O::Options>,
+    {
+        let mut account_options = self.default_options().clone();
+        for option in options {
+            account_options.update(option);
+        }
+        self.accounts_mut().insert(name.into(), account_options);
+    }
+
+    /// Update the options of the account profile `name`.
+    ///
+    /// # Panics
+    /// Panics if the account was not registered with [add_account()][Self::add_account()].
+    pub fn update_account_option<O>(&mut self, name: &str, option: O)
+    where
+        O: HandlerOption,
+        Self: GetOptions<O::Options>,
+    {
+        self.accounts_mut().get_mut(name)
+            .unwrap_or_else(|| panic!("account {:?} is not registered", name))
+            .update(option);
+    }
+
+    /// Removes the account profile `name` and returns its options.
+    #[inline(always)]
+    pub fn remove_account<O>(&mut self, name: &str) -> Option<O::Options>
+    where
+        O: HandlerOption,
+        Self: GetOptions<O::Options>,
+    {
+        self.accounts_mut().remove(name)
+    }
+
+    /// Returns the options of the account profile selected by `options` (or the default options) updated with `options`.
+    /// If the account profile is not registered, its name is returned as the error.
     #[inline]
-    fn merged_options<O>(&self, options: impl IntoIterator<Item=O>) -> O::Options
+    fn merged_options<O>(&self, options: impl IntoIterator<Item=O>) -> Result<O::Options, String>
     where
         O: HandlerOption,
         Self:GetOptions<O::Options>,
     {
-        let mut default_options = self.default_options().clone();
+        let options: Vec<O> = options.into_iter().collect();
+        let mut merged_options = match options.iter().rev().find_map(O::account_name) {
+            Some(name) => self.accounts().get(name).ok_or_else(|| name.to_owned())?.clone(),
+            None => self.default_options().clone(),
+        };
         for option in options {
-            default_options.update(option);
+            merged_options.update(option);
         }
-        default_options
+        Ok(merged_options)
+    }
+
+    /// [merged_options()][Self::merged_options()] for HTTP requests. If the account profile is not registered,
+    /// the default options marked with [HandlerOptions::set_unknown_account()] are returned so that the request fails.
+    #[inline]
+    fn http_options<O>(&self, options: impl IntoIterator<Item=O>) -> O::Options
+    where
+        O: HandlerOption,
+        Self:GetOptions<O::Options>,
+    {
+        self.merged_options(options).unwrap_or_else(|name| {
+            let mut options = self.default_options().clone();
+            options.set_unknown_account(name);
+            options
+        })
     }
 
     /// see [http::Client::request()]
@@ -75,7 +193,26 @@ impl Client {
         Self: GetOptions<O::Options>,
         Q: Serialize + ?Sized,
     {
-        self.client.request(method, url, query, body, &O::request_handler(self.merged_options(options))).await
+        self.client.request(method, url, query, body, &O::request_handler(self.http_options(options))).await
+    }
+
+    /// see [http::Client::request_with_metadata()]
+    ///
+    /// The parsed body is returned with the status, the headers, the timestamps and the number of attempts of the response.
+    #[inline(always)]
+    pub async fn request_with_metadata<'a, R, O, Q, B>(
+        &self, method: Method, url: &str, query: Option<&Q>, body: Option<B>, options: impl IntoIterator<Item=O>,
+    ) -> Result<
+        Response<<O::RequestHandler as RequestHandler<B>>::Successful>,
+        RequestError<<O::RequestHandler as RequestHandler<B>>::BuildError, <O::RequestHandler as RequestHandler<B>>::Unsuccessful>,
+    >
+    where
+        O: HttpOption<'a, R, B>,
+        O::RequestHandler: RequestHandler<B>,
+        Self: GetOptions<O::Options>,
+        Q: Serialize + ?Sized,
+    {
+        self.client.request_with_metadata(method, url, query, body, &O::request_handler(self.http_options(options))).await
     }
 
     /// see [http::Client::get()]
@@ -87,7 +224,7 @@ impl Client {
         Self: GetOptions<O::Options>,
         Q: Serialize + ?Sized,
     {
-        self.client.get(url, query, &O::request_handler(self.merged_options(options))).await
+        self.client.get(url, query, &O::request_handler(self.http_options(options))).await
     }
 
     /// see [http::Client::get_no_query()]
@@ -98,7 +235,7 @@ impl Client {
         O::RequestHandler: RequestHandler<()>,
         Self: GetOptions<O::Options>,
     {
-        self.client.get_no_query(url, &O::request_handler(self.merged_options(options))).await
+        self.client.get_no_query(url, &O::request_handler(self.http_options(options))).await
     }
 
     /// see [http::Client::post()]
@@ -110,7 +247,7 @@ impl Client {
         O::RequestHandler: RequestHandler<B>,
         Self: GetOptions<O::Options>,
     {
-        self.client.post(url, body, &O::request_handler(self.merged_options(options))).await
+        self.client.post(url, body, &O::request_handler(self.http_options(options))).await
     }
 
     /// see [http::Client::post_no_body()]
@@ -122,7 +259,7 @@ impl Client {
         O::RequestHandler: RequestHandler<()>,
         Self: GetOptions<O::Options>,
     {
-        self.client.post_no_body(url, &O::request_handler(self.merged_options(options))).await
+        self.client.post_no_body(url, &O::request_handler(self.http_options(options))).await
     }
 
     /// see [http::Client::put()]
@@ -134,7 +271,7 @@ impl Client {
         O::RequestHandler: RequestHandler<B>,
         Self: GetOptions<O::Options>,
     {
-        self.client.put(url, body, &O::request_handler(self.merged_options(options))).await
+        self.client.put(url, body, &O::request_handler(self.http_options(options))).await
     }
 
     /// see [http::Client::put_no_body()]
@@ -146,7 +283,7 @@ impl Client {
         O::RequestHandler: RequestHandler<()>,
         Self: GetOptions<O::Options>,
     {
-        self.client.put_no_body(url, &O::request_handler(self.merged_options(options))).await
+        self.client.put_no_body(url, &O::request_handler(self.http_options(options))).await
     }
 
     /// see [http::Client::delete()]
@@ -158,7 +295,7 @@ impl Client {
         Self: GetOptions<O::Options>,
         Q: Serialize + ?Sized,
     {
-        self.client.delete(url, query, &O::request_handler(self.merged_options(options))).await
+        self.client.delete(url, query, &O::request_handler(self.http_options(options))).await
     }
 
     /// see [http::Client::delete_no_query()]
@@ -169,7 +306,49 @@ impl Client {
         O::RequestHandler: RequestHandler<()>,
         Self: GetOptions<O::Options>,
     {
-        self.client.delete_no_query(url, &O::request_handler(self.merged_options(options))).await
+        self.client.delete_no_query(url, &O::request_handler(self.http_options(options))).await
+    }
+
+    /// Samples the server time of an exchange once, and updates the [ServerClock][clock::ServerClock] in the default options.
+    ///
+    /// The exchange is determined by the type parameter `O`, for example `client.sync_clock::<BinanceOption>()`.
+    pub async fn sync_clock<O>(&self) -> Result<(), ClockSyncError>
+    where
+        O: ServerTimeOption,
+        Self: GetOptions<O::Options>,
+    {
+        let options = self.default_options();
+        let url = O::server_time_url(options);
+        let clock = O::server_clock(options).clone();
+        let handler = ServerTimeHandler { parse: O::parse_server_time };
+
+        let sent = clock.local_now();
+        let server_time = self.client.get_no_query(&url, &handler).await?;
+        clock.add_sample(sent, clock.local_now(), server_time);
+        Ok(())
+    }
+
+    /// Spawns a task which calls [sync_clock()][Self::sync_clock()] every `interval`, and whenever a handler requests
+    /// a resynchronization because the server rejected a timestamp.
+    ///
+    /// Aborting the returned [JoinHandle] stops the task.
+    pub fn spawn_clock_sync<O>(&self, interval: Duration) -> JoinHandle<()>
+    where
+        O: ServerTimeOption + 'static,
+        Self: GetOptions<O::Options>,
+    {
+        let client = self.clone();
+        let clock = O::server_clock(self.default_options()).clone();
+        tokio::spawn(async move {
+            loop {
+                if let Err(error) = client.sync_clock::<O>().await {
+                    log::debug!("Failed to synchronize server clock: {:?}", error);
+                }
+                if tokio::time::timeout(interval, clock.resync_requested()).await.is_ok() {
+                    log::debug!("Server clock resynchronization requested");
+                }
+            }
+        })
     }
 
     #[inline(always)]
@@ -179,67 +358,71 @@ impl Client {
         O::WebSocketHandler: WebSocketHandler,
         Self: GetOptions<O::Options>,
     {
-        WebSocketConnection::new(url, O::websocket_handler(handler, self.merged_options(options))).await
+        let options = self.merged_options(options).map_err(Self::unknown_account)?;
+        WebSocketConnection::new(url, O::websocket_handler(handler, options)).await
     }
-}
-
-pub trait GetOptions<O: HandlerOptions> {
-    fn default_options(&self) -> &O;
-    fn default_options_mut(&mut self) -> &mut O;
-}
 
-#[cfg(feature = "binance")]
-#[cfg_attr(docsrs, doc(cfg(feature = "binance")))]
-impl GetOptions<binance::BinanceOptions> for Client {
-    #[inline(always)]
-    fn default_options(&self) -> &binance::BinanceOptions {
-        &self.binance
+    /// The error of a WebSocket connection which selected the account profile `name`, which is not registered.
+    fn unknown_account(name: String) -> TungsteniteError {
+        TungsteniteError::Io(std::io::Error::new(std::io::ErrorKind::NotFound, error::BuildError::UnknownAccount(name)))
     }
 
-    #[inline(always)]
-    fn default_options_mut(&mut self) -> &mut binance::BinanceOptions {
-        &mut self.binance
+    /// Starts a WebSocket connection like [websocket()][Self::websocket()], but returns the received messages
+    /// as a [MessageStream][stream::MessageStream] instead of passing them to a callback.
+    ///
+    /// Messages are buffered until they are taken from the stream. See [StreamConfig][stream::StreamConfig] for
+    /// the size of the buffer and what happens when it is full.
+    pub async fn websocket_stream<O>(&self, url: &str, config: stream::StreamConfig, options: impl IntoIterator<Item=O>)
+        -> Result<(WebSocketConnection<stream::StreamHandler<O::WebSocketHandler, O::Message>>, stream::MessageStream<O::Message>), TungsteniteError>
+    where
+        O: WebSocketStreamOption + WebSocketOption<Box<dyn FnMut(O::Message) + Send>>,
+        O::WebSocketHandler: WebSocketHandler,
+        Self: GetOptions<O::Options>,
+    {
+        let options = self.merged_options(options).map_err(Self::unknown_account)?;
+        let (sender, stream) = stream::channel(config);
+        let handler: Box<dyn FnMut(O::Message) + Send> = Box::new(move |message| sender.send(message));
+        let connection = WebSocketConnection::new(url, stream.handler(O::websocket_handler(handler, options))).await?;
+        stream.set_reconnect_state(connection.reconnect_state());
+        Ok((connection, stream))
     }
 }
 
-#[cfg(feature = "bitflyer")]
-#[cfg_attr(docsrs, doc(cfg(feature = "bitflyer")))]
-impl GetOptions<bitflyer::BitFlyerOptions> for Client {
-    #[inline(always)]
-    fn default_options(&self) -> &bitflyer::BitFlyerOptions {
-        &self.bitflyer
-    }
-
-    #[inline(always)]
-    fn default_options_mut(&mut self) -> &mut bitflyer::BitFlyerOptions {
-        &mut self.bitflyer
-    }
+/// A `trait` that shows [Client] holds options of type `O`.
+///
+/// This is implemented for all [HandlerOptions], so the options of user-defined exchanges can be used with [Client]
+/// after they are registered with [Client::register_options()] or [Client::update_default_option()].
+/// The options of the exchanges in this crate are registered when the [Client] is created.
+pub trait GetOptions<O: HandlerOptions> {
+    /// The [Default] options of `O` if it is not registered.
+    fn default_options(&self) -> &O;
+    /// Registers `O` with its [Default] options if it is not registered.
+    fn default_options_mut(&mut self) -> &mut O;
+    /// Account profiles registered with [Client::add_account()], by name.
+    /// Empty if `O` is not registered.
+    fn accounts(&self) -> &HashMap<String, O>;
+    /// Registers `O` with its [Default] options if it is not registered.
+    fn accounts_mut(&mut self) -> &mut HashMap<String, O>;
 }
 
-#[cfg(feature = "bybit")]
-#[cfg_attr(docsrs, doc(cfg(feature = "bybit")))]
-impl GetOptions<bybit::BybitOptions> for Client {
+impl<O: HandlerOptions + Send + Sync + 'static> GetOptions<O> for Client {
     #[inline(always)]
-    fn default_options(&self) -> &bybit::BybitOptions {
-        &self.bybit
+    fn default_options(&self) -> &O {
+        &self.options.get_or_default::<O>().default
     }
 
     #[inline(always)]
-    fn default_options_mut(&mut self) -> &mut bybit::BybitOptions {
-        &mut self.bybit
+    fn default_options_mut(&mut self) -> &mut O {
+        &mut self.options.get_or_insert_mut::<O>().default
     }
-}
 
-#[cfg(feature = "coincheck")]
-#[cfg_attr(docsrs, doc(cfg(feature = "coincheck")))]
-impl GetOptions<coincheck::CoincheckOptions> for Client {
     #[inline(always)]
-    fn default_options(&self) -> &coincheck::CoincheckOptions {
-        &self.coincheck
+    fn accounts(&self) -> &HashMap<String, O> {
+        &self.options.get_or_default::<O>().accounts
     }
 
     #[inline(always)]
-    fn default_options_mut(&mut self) -> &mut coincheck::CoincheckOptions {
-        &mut self.coincheck
+    fn accounts_mut(&mut self) -> &mut HashMap<String, O> {
+        &mut self.options.get_or_insert_mut::<O>().accounts
     }
 }
@@ -0,0 +1,158 @@
+//! A module for generating nonces used in authentication.
+//!
+//! Handlers take nonces from a [NonceSource] in their options, so a deterministic source such as [FixedNonce]
+//! can be used to reproduce signatures in tests.
+//! Exchanges which require increasing integer nonces use [MonotonicNonce].
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+};
+use fs2::FileExt;
+use parking_lot::Mutex as SyncMutex;
+use crate::util;
+
+/// A `trait` that represents a source of random nonces.
+pub trait NonceSource: Send + Sync + Debug {
+    /// Returns a new nonce.
+    fn nonce(&self) -> String;
+}
+
+/// A [NonceSource] which returns 16 random alphanumeric characters.
+#[cfg(feature = "bitflyer")]
+#[cfg_attr(docsrs, doc(cfg(feature = "bitflyer")))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomNonce;
+
+#[cfg(feature = "bitflyer")]
+impl NonceSource for RandomNonce {
+    fn nonce(&self) -> String {
+        use rand::{Rng, distributions::Alphanumeric};
+
+        rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(16)
+            .map(char::from)
+            .collect()
+    }
+}
+
+/// A [NonceSource] which always returns the same nonce.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixedNonce(pub String);
+
+impl NonceSource for FixedNonce {
+    #[inline(always)]
+    fn nonce(&self) -> String {
+        self.0.clone()
+    }
+}
+
+/// A generator of strictly increasing integer nonces, kept separately for each API key.
+///
+/// Nonces are based on the time in milliseconds, but if the time has not advanced since the last nonce for the key,
+/// the last nonce plus one is returned instead. This `struct` uses an [Arc] internally, so clones share the nonces.
+/// [MonotonicNonce::shared()] returns an instance shared by the whole process, which is used by default.
+///
+/// If a file is set with [MonotonicNonce::with_file()], the last nonce of each key is also recorded in that file
+/// under an exclusive lock, so that multiple processes using the same key (or a restarted process) never reuse a nonce.
+/// The file is read and written synchronously, so with a file, [next()][Self::next()] blocks the calling thread
+/// until it gets the lock, including while another process holds it.
+#[derive(Debug, Clone, Default)]
+pub struct MonotonicNonce {
+    inner: Arc<MonotonicNonceInner>,
+}
+
+#[derive(Debug, Default)]
+struct MonotonicNonceInner {
+    /// key id -> the last nonce
+    last: SyncMutex<HashMap<String, u64>>,
+    /// file that stores the high-water marks
+    file: Option<PathBuf>,
+}
+
+impl MonotonicNonce {
+    /// Creates a new `MonotonicNonce` which does not share nonces with other instances.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new `MonotonicNonce` which records the last nonce of each key in the file at `path`.
+    ///
+    /// The file is created if it does not exist. Keys are not written to the file; only hashes of them are.
+    pub fn with_file(path: impl Into<PathBuf>) -> Self {
+        Self {
+            inner: Arc::new(MonotonicNonceInner {
+                last: SyncMutex::default(),
+                file: Some(path.into()),
+            }),
+        }
+    }
+
+    /// Returns the instance shared by the whole process.
+    pub fn shared() -> Self {
+        static SHARED: OnceLock<MonotonicNonce> = OnceLock::new();
+        SHARED.get_or_init(Self::new).clone()
+    }
+
+    /// Returns a nonce for `key` which is greater than all the nonces returned for `key` before,
+    /// and greater than or equal to `time` (usually the current time in milliseconds).
+    ///
+    /// This only returns an error if reading or writing the file set with [with_file()][Self::with_file()] failed.
+    pub fn next(&self, key: &str, time: u64) -> io::Result<u64> {
+        let key = util::key_id(key);
+        let nonce = {
+            let mut last = self.inner.last.lock();
+            let entry = last.entry(key.clone()).or_default();
+            *entry = time.max(*entry + 1);
+            *entry
+        };
+        let Some(path) = &self.inner.file else {
+            return Ok(nonce);
+        };
+
+        // The file lock serializes the nonces of all threads and processes, so the mutex isn't held while waiting for it.
+        // Every nonce returned from here is above the previous high-water mark in the file, so they never repeat.
+        let nonce = Self::update_file(path, &key, nonce)?;
+        let mut last = self.inner.last.lock();
+        let entry = last.entry(key).or_default();
+        *entry = (*entry).max(nonce);
+        Ok(nonce)
+    }
+
+    /// Raises `nonce` above the high-water mark for `key` in the file, and records it.
+    fn update_file(path: &Path, key: &str, mut nonce: u64) -> io::Result<u64> {
+        let mut file = File::options().read(true).write(true).create(true).truncate(false).open(path)?;
+        file.lock_exclusive()?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+
+        // each line is "{key id} {last nonce}"
+        let mut marks: Vec<(&str, u64)> = contents.lines()
+            .filter_map(|line| line.split_once(' '))
+            .filter_map(|(key, mark)| Some((key, mark.trim().parse().ok()?)))
+            .collect();
+        match marks.iter_mut().find(|(mark_key, _)| *mark_key == key) {
+            Some((_, mark)) => {
+                nonce = nonce.max(*mark + 1);
+                *mark = nonce;
+            },
+            None => marks.push((key, nonce)),
+        }
+
+        let mut new_contents = String::new();
+        for (key, mark) in marks {
+            new_contents.push_str(&format!("{key} {mark}\n"));
+        }
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(new_contents.as_bytes())?;
+        file.sync_data()?;
+        // the lock is released when the file is closed
+        Ok(nonce)
+    }
+}
@@ -0,0 +1,89 @@
+//! Storage for the options of each exchange in a [Client][crate::Client], keyed by the type of the options.
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt::{self, Debug},
+    sync::OnceLock,
+};
+use parking_lot::Mutex;
+use crate::traits::HandlerOptions;
+
+/// The default options and the account profiles of an exchange.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Entry<O> {
+    pub(crate) default: O,
+    pub(crate) accounts: HashMap<String, O>,
+}
+
+/// An [Entry] whose type was erased.
+trait AnyEntry: Any + Send + Sync + Debug {
+    fn clone_box(&self) -> Box<dyn AnyEntry>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<O: HandlerOptions + Send + Sync + 'static> AnyEntry for Entry<O> {
+    fn clone_box(&self) -> Box<dyn AnyEntry> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct OptionsMap(HashMap<TypeId, Box<dyn AnyEntry>>);
+
+impl OptionsMap {
+    /// Sets the default options of `O`, keeping its account profiles if it is already registered.
+    pub(crate) fn register<O: HandlerOptions + Send + Sync + 'static>(&mut self, options: O) {
+        self.get_or_insert_mut::<O>().default = options;
+    }
+
+    pub(crate) fn get<O: HandlerOptions + Send + Sync + 'static>(&self) -> Option<&Entry<O>> {
+        self.0.get(&TypeId::of::<O>()).and_then(|entry| entry.as_any().downcast_ref())
+    }
+
+    /// Returns the entry of `O`, or an entry with the [Default] options of `O` if it is not registered.
+    pub(crate) fn get_or_default<O: HandlerOptions + Send + Sync + 'static>(&self) -> &Entry<O> {
+        self.get::<O>().unwrap_or_else(|| default_entry::<O>())
+    }
+
+    /// Returns the entry of `O`, registering it with its [Default] options if necessary.
+    pub(crate) fn get_or_insert_mut<O: HandlerOptions + Send + Sync + 'static>(&mut self) -> &mut Entry<O> {
+        self.0.entry(TypeId::of::<O>())
+            .or_insert_with(|| Box::<Entry<O>>::default())
+            .as_any_mut()
+            .downcast_mut()
+            .expect("the entry of a TypeId must have that type")
+    }
+}
+
+/// Returns an entry with the [Default] options of `O`, which is shared by every [OptionsMap] where `O` is not registered.
+///
+/// The entry of each type is created once and lives for the rest of the program.
+fn default_entry<O: HandlerOptions + Send + Sync + 'static>() -> &'static Entry<O> {
+    static DEFAULTS: OnceLock<Mutex<HashMap<TypeId, &'static (dyn Any + Send + Sync)>>> = OnceLock::new();
+    let entry = *DEFAULTS.get_or_init(Default::default).lock()
+        .entry(TypeId::of::<O>())
+        .or_insert_with(|| Box::leak(Box::<Entry<O>>::default()));
+    entry.downcast_ref().expect("the entry of a TypeId must have that type")
+}
+
+impl Clone for OptionsMap {
+    fn clone(&self) -> Self {
+        Self(self.0.iter().map(|(&type_id, entry)| (type_id, entry.clone_box())).collect())
+    }
+}
+
+impl Debug for OptionsMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.0.values()).finish()
+    }
+}
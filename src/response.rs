@@ -0,0 +1,86 @@
+//! A module for the types that successful responses are returned as.
+//!
+//! Responses are usually deserialized into an owned type `R`. [ResponseBuffer] instead keeps the body as it is,
+//! so that types which borrow from it (such as `&str` fields) can be deserialized without allocating.
+//!
+//! ```no_run
+//! # async fn example() {
+//! use crypto_botters::{binance::BinanceOption, response::ResponseBuffer, Client};
+//!
+//! #[derive(serde::Deserialize)]
+//! struct Depth<'a> {
+//!     #[serde(borrow)]
+//!     bids: Vec<[&'a str; 2]>,
+//!     #[serde(borrow)]
+//!     asks: Vec<[&'a str; 2]>,
+//! }
+//!
+//! let client = Client::new();
+//! let buffer: ResponseBuffer = client.get("/api/v3/depth", Some(&[("symbol", "BTCUSDT")]), [BinanceOption::Default]).await.unwrap();
+//! let depth: Depth = buffer.deserialize().unwrap();
+//! # }
+//! ```
+
+use std::fmt::{self, Debug};
+use generic_api_client::http::Bytes;
+use serde::{de::DeserializeOwned, Deserialize};
+
+/// A `trait` for types that the body of a successful response can be converted to.
+///
+/// This is implemented for all [DeserializeOwned] types, which are parsed as JSON, and for [ResponseBuffer].
+pub trait ResponseBody: Sized {
+    /// Converts the body of a successful response. If it can't be converted, the body is returned with the error.
+    ///
+    /// The body is taken by value so that it can be kept, for example by [ResponseBuffer], or returned without being copied.
+    fn from_body(body: Bytes) -> Result<Self, (serde_json::Error, Bytes)>;
+}
+
+impl<T: DeserializeOwned> ResponseBody for T {
+    #[inline(always)]
+    fn from_body(body: Bytes) -> Result<Self, (serde_json::Error, Bytes)> {
+        crate::json::from_bytes(body)
+    }
+}
+
+/// The body of a successful response, kept without being deserialized.
+///
+/// Cloning this does not copy the body.
+#[derive(Clone)]
+pub struct ResponseBuffer(Bytes);
+
+impl ResponseBuffer {
+    /// Deserializes a value which may borrow from this buffer.
+    ///
+    /// Note that `&str` can only be borrowed if the string doesn't contain escape sequences. Use [Cow][std::borrow::Cow]
+    /// with `#[serde(borrow)]` for strings which may contain them.
+    #[inline(always)]
+    pub fn deserialize<'a, T: Deserialize<'a>>(&'a self) -> Result<T, serde_json::Error> {
+        crate::json::from_borrowed_slice(&self.0)
+    }
+
+    /// The body of the response.
+    #[inline(always)]
+    pub fn bytes(&self) -> &Bytes {
+        &self.0
+    }
+
+    /// Returns the body of the response.
+    #[inline(always)]
+    pub fn into_bytes(self) -> Bytes {
+        self.0
+    }
+}
+
+impl ResponseBody for ResponseBuffer {
+    /// Keeps `body` without checking whether it is valid JSON.
+    #[inline(always)]
+    fn from_body(body: Bytes) -> Result<Self, (serde_json::Error, Bytes)> {
+        Ok(Self(body))
+    }
+}
+
+impl Debug for ResponseBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ResponseBuffer").field(&String::from_utf8_lossy(&self.0)).finish()
+    }
+}
@@ -0,0 +1,51 @@
+//! A module for handling API secrets.
+
+use std::fmt::{self, Debug, Display};
+use zeroize::Zeroizing;
+
+/// A `struct` that holds an API secret.
+///
+/// The secret is never printed by [Debug] or [Display], and its memory is zeroed when it is dropped.
+/// The value can only be read by the signing code of this crate.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(Zeroizing<String>);
+
+impl Secret {
+    /// Wraps `secret` without copying it.
+    pub fn new(secret: impl Into<String>) -> Self {
+        Self(Zeroizing::new(secret.into()))
+    }
+
+    /// Returns the secret. Only use this when signing.
+    #[cfg(any(feature = "binance", feature = "bitflyer", feature = "bybit", feature = "coincheck"))]
+    #[inline(always)]
+    pub(crate) fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Secret {
+    #[inline(always)]
+    fn from(secret: String) -> Self {
+        Self::new(secret)
+    }
+}
+
+impl From<&str> for Secret {
+    #[inline(always)]
+    fn from(secret: &str) -> Self {
+        Self::new(secret)
+    }
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret([REDACTED])")
+    }
+}
+
+impl Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
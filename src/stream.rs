@@ -0,0 +1,332 @@
+//! A module for receiving WebSocket messages as a [Stream] instead of through a callback.
+//!
+//! Messages are buffered until they are polled from the [MessageStream]. The buffer is bounded, and
+//! [OverflowPolicy] decides what happens when it is full.
+//!
+//! ```no_run
+//! # async fn example() {
+//! use futures_util::StreamExt;
+//! use crypto_botters::{binance::{BinanceOption, BinanceWebSocketUrl}, stream::StreamConfig, Client};
+//!
+//! let client = Client::new();
+//! let (connection, mut messages) = client.websocket_stream(
+//!     "/ws/btcusdt@trade",
+//!     StreamConfig::default(),
+//!     [BinanceOption::WebSocketUrl(BinanceWebSocketUrl::Spot443)],
+//! ).await.expect("failed to connect websocket");
+//! while let Some(message) = messages.next().await {
+//!     println!("{}", message);
+//! }
+//! # }
+//! ```
+
+use std::{
+    collections::VecDeque,
+    fmt::{self, Debug},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, OnceLock, atomic::{AtomicU64, Ordering}},
+    task::{Context, Poll, Waker},
+};
+use futures_util::Stream;
+use generic_api_client::websocket::{DedupKey, ReconnectState, WebSocketConfig, WebSocketHandler, WebSocketMessage};
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+
+/// What a [MessageStream] does with a new message when its buffer is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OverflowPolicy {
+    /// The oldest message in the buffer is discarded.
+    DropOldest,
+    /// The new message is discarded.
+    DropNewest,
+    /// The connection waits until a message is taken from the buffer.
+    ///
+    /// Messages which arrive in the meantime are held by the connection, up to the size of the buffer. Messages beyond that
+    /// are discarded. The connection waits asynchronously, so this works on any runtime.
+    Block,
+    /// The buffer and the new message are discarded and the connection is reconnected.
+    ///
+    /// This is useful for messages which are only meaningful in sequence, such as order book updates,
+    /// because exchanges send a fresh snapshot when the connection subscribes again.
+    Reconnect,
+}
+
+/// Configuration of a [MessageStream].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct StreamConfig {
+    /// The maximum number of messages kept in the buffer, at least 1. [Default]s to 1024.
+    pub buffer: usize,
+    /// What happens when the buffer is full. [Default]s to [OverflowPolicy::DropOldest].
+    pub overflow: OverflowPolicy,
+}
+
+impl StreamConfig {
+    /// Constructs a new `StreamConfig` with the given buffer size and overflow policy.
+    pub fn new(buffer: usize, overflow: OverflowPolicy) -> Self {
+        Self { buffer, overflow }
+    }
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self::new(1024, OverflowPolicy::DropOldest)
+    }
+}
+
+struct Shared<M> {
+    config: StreamConfig,
+    state: Mutex<State<M>>,
+    /// Notified when the messages held by [OverflowPolicy::Block] are moved to the buffer or the stream is dropped.
+    space: Notify,
+    dropped: AtomicU64,
+    reconnect_state: OnceLock<ReconnectState>,
+}
+
+struct State<M> {
+    buffer: VecDeque<M>,
+    /// Messages which didn't fit in the buffer with [OverflowPolicy::Block], at most as many as fit in the buffer.
+    /// They are moved to the buffer as messages are taken from it.
+    held: VecDeque<M>,
+    waker: Option<Waker>,
+    /// The sender has been dropped, which means the connection was closed.
+    sender_closed: bool,
+    /// The [MessageStream] has been dropped.
+    receiver_closed: bool,
+}
+
+/// A [Stream] of messages received by a WebSocket connection, returned by [Client::websocket_stream()][crate::Client::websocket_stream()].
+///
+/// The stream ends when the connection is dropped and all buffered messages have been taken.
+pub struct MessageStream<M> {
+    shared: Arc<Shared<M>>,
+}
+
+impl<M> MessageStream<M> {
+    /// The number of messages which were discarded because the buffer was full.
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+
+    /// The number of messages in the buffer.
+    pub fn len(&self) -> usize {
+        self.shared.state.lock().buffer.len()
+    }
+
+    /// Returns `true` if there are no messages in the buffer.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Wraps `handler`, the handler of the exchange which sends the messages of this stream.
+    pub(crate) fn handler<H>(&self, handler: H) -> StreamHandler<H, M> {
+        StreamHandler { handler, shared: Arc::clone(&self.shared) }
+    }
+
+    /// Sets the [ReconnectState] of the connection, which is used by [OverflowPolicy::Reconnect].
+    pub(crate) fn set_reconnect_state(&self, reconnect_state: ReconnectState) {
+        self.shared.reconnect_state.set(reconnect_state).ok();
+    }
+}
+
+impl<M> Stream for MessageStream<M> {
+    type Item = M;
+
+    fn poll_next(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Option<M>> {
+        let mut state = self.shared.state.lock();
+        if let Some(message) = state.buffer.pop_front() {
+            if let Some(held) = state.held.pop_front() {
+                state.buffer.push_back(held);
+                if state.held.is_empty() {
+                    // the old and the new connection may both be waiting during a reconnection
+                    self.shared.space.notify_waiters();
+                }
+            }
+            Poll::Ready(Some(message))
+        } else if state.sender_closed {
+            Poll::Ready(None)
+        } else {
+            state.waker = Some(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl<M> Drop for MessageStream<M> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock();
+        state.receiver_closed = true;
+        state.buffer.clear();
+        state.held.clear();
+        drop(state);
+        // connections waiting for space should stop waiting
+        self.shared.space.notify_waiters();
+    }
+}
+
+impl<M> Debug for MessageStream<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MessageStream")
+            .field("config", &self.shared.config)
+            .field("len", &self.len())
+            .field("dropped", &self.dropped())
+            .finish()
+    }
+}
+
+/// The sending half of a [MessageStream], which is called by the WebSocket handler.
+pub(crate) struct MessageSender<M> {
+    shared: Arc<Shared<M>>,
+}
+
+impl<M> MessageSender<M> {
+    pub(crate) fn send(&self, message: M) {
+        let mut state = self.shared.state.lock();
+        if state.receiver_closed {
+            return;
+        }
+        if state.buffer.len() >= self.shared.config.buffer {
+            match self.shared.config.overflow {
+                OverflowPolicy::DropOldest => {
+                    state.buffer.pop_front();
+                    self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+                },
+                OverflowPolicy::DropNewest => {
+                    self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+                    return;
+                },
+                OverflowPolicy::Block => {
+                    if state.held.len() >= self.shared.config.buffer {
+                        self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+                        return;
+                    }
+                    // the connection waits in StreamHandler::ready() until this is moved to the buffer
+                    state.held.push_back(message);
+                    return;
+                },
+                OverflowPolicy::Reconnect => {
+                    let discarded = state.buffer.len() as u64 + 1;
+                    state.buffer.clear();
+                    self.shared.dropped.fetch_add(discarded, Ordering::Relaxed);
+                    match self.shared.reconnect_state.get() {
+                        Some(reconnect_state) => if reconnect_state.request_reconnect() {
+                            log::info!("Reconnecting WebSocket because the message buffer is full");
+                        },
+                        None => log::debug!("Message buffer is full before the connection was established"),
+                    }
+                    return;
+                },
+            }
+        }
+        state.buffer.push_back(message);
+        let waker = state.waker.take();
+        drop(state);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<M> Drop for MessageSender<M> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock();
+        state.sender_closed = true;
+        let waker = state.waker.take();
+        drop(state);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<M> Shared<M> {
+    /// Waits until the messages held by [OverflowPolicy::Block] are moved to the buffer, or the stream is dropped.
+    ///
+    /// [Notify::notify_waiters()] wakes every waiting connection, so each of them checks the state again.
+    async fn held_moved(&self) {
+        loop {
+            let notified = self.space.notified();
+            {
+                let state = self.state.lock();
+                if state.held.is_empty() || state.receiver_closed {
+                    return;
+                }
+            }
+            notified.await;
+        }
+    }
+}
+
+/// The [WebSocketHandler] of a connection started by [Client::websocket_stream()][crate::Client::websocket_stream()].
+///
+/// It wraps the handler of the exchange, and makes the connection wait for space in the buffer with [OverflowPolicy::Block].
+pub struct StreamHandler<H, M> {
+    handler: H,
+    shared: Arc<Shared<M>>,
+}
+
+impl<H, M> WebSocketHandler for StreamHandler<H, M>
+where
+    H: WebSocketHandler,
+    M: Send + 'static,
+{
+    #[inline(always)]
+    fn websocket_config(&self) -> WebSocketConfig {
+        self.handler.websocket_config()
+    }
+
+    #[inline(always)]
+    fn handle_start(&mut self) -> Vec<WebSocketMessage> {
+        self.handler.handle_start()
+    }
+
+    #[inline(always)]
+    fn handle_start_async(&mut self) -> impl Future<Output = Vec<WebSocketMessage>> + Send + 'static {
+        self.handler.handle_start_async()
+    }
+
+    #[inline(always)]
+    fn handle_message(&mut self, message: WebSocketMessage) -> Vec<WebSocketMessage> {
+        self.handler.handle_message(message)
+    }
+
+    fn ready(&self) -> impl Future<Output = ()> + Send + 'static {
+        let shared = (self.shared.config.overflow == OverflowPolicy::Block).then(|| Arc::clone(&self.shared));
+        async move {
+            if let Some(shared) = shared {
+                shared.held_moved().await;
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn dedup_key(&self, message: &WebSocketMessage) -> Option<DedupKey> {
+        self.handler.dedup_key(message)
+    }
+
+    #[inline(always)]
+    fn handle_close(&mut self, reconnect: bool) {
+        self.handler.handle_close(reconnect)
+    }
+}
+
+/// Creates a [MessageSender] and the [MessageStream] that receives the messages sent by it.
+pub(crate) fn channel<M>(mut config: StreamConfig) -> (MessageSender<M>, MessageStream<M>) {
+    config.buffer = config.buffer.max(1);
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            buffer: VecDeque::with_capacity(config.buffer.min(1024)),
+            held: VecDeque::new(),
+            waker: None,
+            sender_closed: false,
+            receiver_closed: false,
+        }),
+        config,
+        space: Notify::new(),
+        dropped: AtomicU64::new(0),
+        reconnect_state: OnceLock::new(),
+    });
+    (MessageSender { shared: Arc::clone(&shared) }, MessageStream { shared })
+}
@@ -1,9 +1,18 @@
-use std::fmt::Debug;
-use generic_api_client::{http, websocket};
+use std::{fmt::Debug, time::SystemTime};
+use generic_api_client::{http::{self, HeaderMap}, websocket};
+use crate::clock::ServerClock;
 
 /// A `trait` that represents an option which can be set when creating handlers
 pub trait HandlerOption: Default {
     type Options: HandlerOptions<OptionItem=Self>;
+
+    /// Returns the name of the account profile this option selects, if it is an `Account` option.
+    ///
+    /// See [Client::add_account()][crate::Client::add_account()].
+    #[inline(always)]
+    fn account_name(&self) -> Option<&str> {
+        None
+    }
 }
 
 /// Set of [HandlerOption] s
@@ -12,6 +21,13 @@ pub trait HandlerOptions: Default + Clone + Debug {
     type OptionItem: HandlerOption<Options=Self>;
 
     fn update(&mut self, option: Self::OptionItem);
+
+    /// Marks these options as selecting the account profile `name`, which is not registered in the [Client][crate::Client].
+    ///
+    /// [Client][crate::Client] calls this on its default options instead of failing to create a handler. Handlers
+    /// created with the marked options must fail to build requests, so that requests are not sent with the credentials
+    /// of another account.
+    fn set_unknown_account(&mut self, name: String);
 }
 
 /// A `trait` that shows the implementing type is able to create [http::RequestHandler]s
@@ -27,3 +43,22 @@ pub trait WebSocketOption<H>: HandlerOption {
 
     fn websocket_handler(handler: H, options: Self::Options) -> Self::WebSocketHandler;
 }
+
+/// A `trait` that shows the [websocket::WebSocketHandler]s created by the implementing type pass messages of type
+/// [Message][Self::Message] to their handler, so that the messages can be received as a [Stream][futures_util::Stream]
+/// with [Client::websocket_stream()][crate::Client::websocket_stream()].
+pub trait WebSocketStreamOption: HandlerOption {
+    type Message: Send + 'static;
+}
+
+/// A `trait` that shows the server time of the exchange can be sampled to synchronize a [ServerClock].
+pub trait ServerTimeOption: HandlerOption {
+    /// The url of an endpoint whose response contains the server time.
+    fn server_time_url(options: &Self::Options) -> String;
+
+    /// Extracts the server time from a response of [server_time_url()][Self::server_time_url()].
+    fn parse_server_time(headers: &HeaderMap, response_body: &[u8]) -> Option<SystemTime>;
+
+    /// The [ServerClock] used by handlers created with `options`.
+    fn server_clock(options: &Self::Options) -> &ServerClock;
+}
@@ -0,0 +1,41 @@
+use sha2::{Digest, Sha256};
+#[cfg(any(feature = "binance", feature = "bitflyer", feature = "bybit", feature = "coincheck"))]
+use generic_api_client::http::{Bytes, HeaderMap, StatusCode};
+
+/// Returns a short identifier of an API key which can be used in names of rate limit buckets etc.
+/// without exposing the key itself.
+pub(crate) fn key_id(key: &str) -> String {
+    hex::encode(&Sha256::digest(key.as_bytes())[..8])
+}
+
+/// Parses a successful response as JSON. Otherwise, `classify` converts the status and the error response,
+/// which is `None` if it could not be parsed as `P`, to the error of the handler.
+///
+/// If the response could not be parsed, or `classify` returns `None`, `parse_error` is called with the status and the body.
+#[cfg(any(feature = "binance", feature = "bitflyer", feature = "bybit", feature = "coincheck"))]
+pub(crate) fn handle_json_response<R, P, E>(
+    status: StatusCode,
+    headers: &HeaderMap,
+    response_body: Bytes,
+    parse_error: impl FnOnce(StatusCode, Bytes) -> E,
+    classify: impl FnOnce(StatusCode, &HeaderMap, Option<P>) -> Option<E>,
+) -> Result<R, E>
+where
+    R: crate::response::ResponseBody,
+    P: serde::de::DeserializeOwned,
+{
+    if status.is_success() {
+        R::from_body(response_body).map_err(|(error, response_body)| {
+            log::debug!("Failed to parse response due to an error: {}", error);
+            parse_error(status, response_body)
+        })
+    } else {
+        let parsed = crate::json::from_slice(&response_body).map_err(|error| {
+            log::debug!("Failed to parse error response due to an error: {}", error);
+        }).ok();
+        match classify(status, headers, parsed) {
+            Some(error) => Err(error),
+            None => Err(parse_error(status, response_body)),
+        }
+    }
+}
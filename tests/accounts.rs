@@ -0,0 +1,82 @@
+mod common;
+
+use std::iter;
+use crypto_botters::{
+    binance::{BinanceAuth, BinanceOption},
+    error::BuildError,
+    generic_api_client::{http::{RequestConfig, RequestError}, websocket::TungsteniteError},
+    Client,
+};
+use common::StubServer;
+
+/// Starts an HTTP server which responds `{}` to every request.
+fn start_server() -> StubServer {
+    StubServer::start(iter::repeat(common::ok("{}")))
+}
+
+/// The `X-MBX-APIKEY` header of the next request received by `server`.
+fn key(server: &StubServer) -> Option<String> {
+    let headers = server.requests.recv().unwrap();
+    headers.get("x-mbx-apikey").map(|key| key.to_str().unwrap().to_owned())
+}
+
+fn client(url: &str) -> Client {
+    let mut config = RequestConfig::default();
+    config.url_prefix = url.to_owned();
+
+    let mut client = Client::new();
+    client.update_default_option(BinanceOption::RequestConfig(config));
+    client.update_default_option(BinanceOption::HttpAuth(BinanceAuth::Key));
+    client.update_default_option(BinanceOption::Key("main key".to_owned()));
+    client.add_account("hedge", [BinanceOption::Key("hedge key".to_owned())]);
+    client
+}
+
+#[tokio::test]
+async fn accounts_have_separate_options() {
+    let server = start_server();
+    let mut client = client(&server.url);
+
+    let _: serde_json::Value = client.get_no_query("/api/v3/account", [BinanceOption::Default]).await.unwrap();
+    assert_eq!(key(&server).as_deref(), Some("main key"));
+
+    let _: serde_json::Value = client.get_no_query("/api/v3/account", [BinanceOption::Account("hedge".to_owned())]).await.unwrap();
+    assert_eq!(key(&server).as_deref(), Some("hedge key"));
+
+    // options passed with the account override the profile, regardless of their order
+    let options = [BinanceOption::Key("override".to_owned()), BinanceOption::Account("hedge".to_owned())];
+    let _: serde_json::Value = client.get_no_query("/api/v3/account", options).await.unwrap();
+    assert_eq!(key(&server).as_deref(), Some("override"));
+
+    // updating the profile doesn't affect the default options
+    client.update_account_option("hedge", BinanceOption::HttpAuth(BinanceAuth::None));
+    let _: serde_json::Value = client.get_no_query("/api/v3/account", [BinanceOption::Account("hedge".to_owned())]).await.unwrap();
+    assert_eq!(key(&server), None);
+    let _: serde_json::Value = client.get_no_query("/api/v3/account", [BinanceOption::Default]).await.unwrap();
+    assert_eq!(key(&server).as_deref(), Some("main key"));
+
+    assert!(client.remove_account::<BinanceOption>("hedge").is_some());
+    assert!(client.remove_account::<BinanceOption>("hedge").is_none());
+}
+
+#[tokio::test]
+async fn unknown_account_fails() {
+    let server = start_server();
+    let client = client(&server.url);
+
+    let result: Result<serde_json::Value, _> = client.get_no_query("/api/v3/account", [BinanceOption::Account("unknown".to_owned())]).await;
+    let Err(RequestError::BuildRequestError(error)) = result else {
+        panic!("unexpected result: {:?}", result);
+    };
+    assert!(matches!(&error, BuildError::UnknownAccount(name) if name == "unknown"), "{:?}", error);
+    assert_eq!(error.to_string(), "account \"unknown\" is not registered");
+    // the request is not sent with the default credentials
+    assert!(server.requests.try_recv().is_err());
+
+    let result = client.websocket("/ws/btcusdt@trade", |_| {}, [BinanceOption::Account("unknown".to_owned())]).await;
+    let Err(TungsteniteError::Io(error)) = result else {
+        panic!("unexpected result: {:?}", result.map(|_| ()));
+    };
+    assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    assert_eq!(error.to_string(), "account \"unknown\" is not registered");
+}
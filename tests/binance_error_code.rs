@@ -0,0 +1,72 @@
+use crypto_botters::{
+    binance::{BinanceErrorCode, BinanceHandlerError, BinanceOption},
+    error::ExchangeError,
+    generic_api_client::http::{Bytes, HeaderMap, RequestHandler, StatusCode},
+    traits::*,
+};
+
+fn api_error(status: StatusCode, body: &'static str) -> BinanceHandlerError {
+    let handler = <BinanceOption as HttpOption<serde_json::Value, ()>>::request_handler(Default::default());
+    RequestHandler::<()>::handle_response(&handler, status, HeaderMap::new(), Bytes::from_static(body.as_bytes())).unwrap_err()
+}
+
+#[test]
+fn codes_round_trip() {
+    for code in [-1000, -1003, -1021, -1022, -2010, -2011, -2013, -2015, -2019, -4003, -4061, -4131, -4164, -4999, -9000] {
+        assert_eq!(BinanceErrorCode::from(code).code(), code);
+    }
+    assert_eq!(BinanceErrorCode::from(-1021), BinanceErrorCode::InvalidTimestamp);
+    assert_eq!(BinanceErrorCode::from(-4164), BinanceErrorCode::MinNotional);
+    assert_eq!(BinanceErrorCode::from(-4131), BinanceErrorCode::MarketOrderRejected);
+    assert_eq!(BinanceErrorCode::from(-4999), BinanceErrorCode::Futures(-4999));
+    assert_eq!(BinanceErrorCode::from(-9000), BinanceErrorCode::Other(-9000));
+}
+
+#[test]
+fn classification() {
+    assert!(BinanceErrorCode::TooManyRequests.is_rate_limited());
+    assert!(BinanceErrorCode::TooManyRequests.is_retryable());
+    assert!(BinanceErrorCode::InvalidTimestamp.is_retryable());
+    assert!(!BinanceErrorCode::Timeout.is_retryable());
+    assert!(BinanceErrorCode::InvalidSignature.is_auth_error());
+    assert!(BinanceErrorCode::RejectedApiKey.is_auth_error());
+    assert!(BinanceErrorCode::NewOrderRejected.is_order_rejected());
+    assert!(BinanceErrorCode::NoSuchOrder.is_order_rejected());
+    assert!(BinanceErrorCode::MinNotional.is_order_rejected());
+    assert!(!BinanceErrorCode::MinNotional.is_retryable());
+    assert!(BinanceErrorCode::MarketOrderRejected.is_order_rejected());
+    assert!(BinanceErrorCode::MarketOrderRejected.is_retryable());
+    assert!(BinanceErrorCode::StopOrderTriggering.is_retryable());
+    assert!(!BinanceErrorCode::NoNeedToChangeMarginType.is_order_rejected());
+    assert!(!BinanceErrorCode::NoNeedToChangeMarginType.is_retryable());
+    assert!(BinanceErrorCode::Futures(-4999).is_order_rejected());
+    assert!(!BinanceErrorCode::InvalidSignature.is_order_rejected());
+}
+
+#[test]
+fn api_error_has_error_code() {
+    let error = api_error(StatusCode::BAD_REQUEST, r#"{"code":-2010,"msg":"Account has insufficient balance for requested action."}"#);
+    match &error {
+        BinanceHandlerError::ApiError(api_error) => {
+            assert_eq!(api_error.error_code(), BinanceErrorCode::NewOrderRejected);
+            assert!(api_error.error_code().is_order_rejected());
+        },
+        _ => panic!("unexpected error {:?}", error),
+    }
+    assert!(!error.is_retryable());
+
+    let error = api_error(StatusCode::UNAUTHORIZED, r#"{"code":-1022,"msg":"Signature for this request is not valid."}"#);
+    assert!(matches!(&error, BinanceHandlerError::ApiError(api_error) if api_error.error_code() == BinanceErrorCode::InvalidSignature), "{:?}", error);
+    assert!(error.is_auth_error());
+}
+
+#[test]
+fn futures_api_error() {
+    let error = api_error(StatusCode::BAD_REQUEST, r#"{"code":-4164,"msg":"Order's notional must be no smaller than 5.0 (unless you choose reduce only)"}"#);
+    assert!(matches!(&error, BinanceHandlerError::ApiError(api_error) if api_error.error_code() == BinanceErrorCode::MinNotional), "{:?}", error);
+    assert!(!error.is_retryable());
+
+    let error = api_error(StatusCode::BAD_REQUEST, r#"{"code":-4131,"msg":"The counterparty's best price does not meet the PERCENT_PRICE filter limit."}"#);
+    assert!(matches!(&error, BinanceHandlerError::ApiError(api_error) if api_error.error_code() == BinanceErrorCode::MarketOrderRejected), "{:?}", error);
+    assert!(error.is_retryable());
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use crypto_botters::{
+    binance::{BinanceHttpUrl, BinanceOption},
+    generic_api_client::{http::{HeaderMap, RequestHandler, StatusCode}, rate_limit::RateLimitUsage},
+    Client,
+};
+
+#[test]
+fn usage_is_synchronized_with_headers() {
+    let client = Client::new();
+    let options = [BinanceOption::Key("key".to_owned()), BinanceOption::Secret("secret".to_owned())];
+    let handler = common::handler::<_, serde_json::Value, ()>(options);
+    assert!(client.binance_usage(BinanceHttpUrl::Spot, Some("key")).weight.is_none());
+
+    // buckets are created when a request is sent
+    let request = reqwest::Client::new().post("https://api.binance.com/api/v3/order?symbol=BTCUSDT").build().unwrap();
+    let limits = RequestHandler::<()>::rate_limits(&handler, &request);
+    client.rate_limiter().try_acquire(&limits).unwrap();
+    let usage = client.binance_usage(BinanceHttpUrl::Spot, Some("key"));
+    assert_eq!(usage.weight.unwrap().capacity, 6000);
+    assert_eq!(usage.orders.iter().map(|(interval, _)| *interval).collect::<Vec<_>>(), ["10s", "1d"]);
+
+    let mut headers = HeaderMap::new();
+    headers.insert("X-MBX-USED-WEIGHT-1M", "1234".parse().unwrap());
+    headers.insert("X-MBX-ORDER-COUNT-10S", "7".parse().unwrap());
+    headers.insert("X-MBX-ORDER-COUNT-1D", "42".parse().unwrap());
+    headers.insert("X-MBX-USED-WEIGHT-1M-INVALID", "not a number".parse().unwrap());
+    let url = request.url().clone();
+    let reported = RequestHandler::<()>::reported_rate_limit_usage(&handler, &url, StatusCode::OK, &headers);
+    assert_eq!(reported.len(), 3, "{:?}", reported);
+    for (key, used) in reported {
+        client.rate_limiter().set_used(&key, used);
+    }
+
+    let usage = client.binance_usage(BinanceHttpUrl::Spot, Some("key"));
+    let used = |usage: Option<RateLimitUsage>| usage.unwrap().used.round() as u32;
+    assert_eq!(used(usage.weight), 1234);
+    assert_eq!(used(usage.orders.iter().find(|(interval, _)| *interval == "10s").map(|(_, usage)| *usage)), 7);
+    assert_eq!(used(usage.orders.iter().find(|(interval, _)| *interval == "1d").map(|(_, usage)| *usage)), 42);
+    // order counts are per API key
+    assert!(client.binance_usage(BinanceHttpUrl::Spot, Some("other")).orders.is_empty());
+    // the weight is shared by the hosts of the same API
+    assert_eq!(used(client.binance_usage(BinanceHttpUrl::Spot1, None).weight), 1234);
+    assert!(client.binance_usage(BinanceHttpUrl::FuturesUsdM, None).weight.is_none());
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use std::{collections::HashMap, error::Error, io, sync::Arc};
+use crypto_botters::{
+    binance::{BinanceAuth, BinanceOption},
+    bybit::{BybitHttpAuth, BybitOption},
+    coincheck::CoincheckOption,
+    error::BuildError,
+    signer::Signer,
+};
+
+#[test]
+fn missing_credentials() {
+    let error = common::build::<_, serde_json::Value, _>([BinanceOption::HttpAuth(BinanceAuth::Key)], "https://api.binance.com/api/v3/userDataStream", None::<()>).unwrap_err();
+    assert!(matches!(error, BuildError::MissingCredential("API key")), "{:?}", error);
+    assert_eq!(error.to_string(), "API key not set");
+
+    let options = [BinanceOption::HttpAuth(BinanceAuth::Sign), BinanceOption::Key("key".to_owned())];
+    let error = common::build::<_, serde_json::Value, _>(options, "https://api.binance.com/api/v3/account", None::<()>).unwrap_err();
+    assert!(matches!(error, BuildError::MissingCredential("API secret")), "{:?}", error);
+}
+
+#[test]
+fn serialization_failure_keeps_source() {
+    // nested maps can't be encoded as application/x-www-form-urlencoded
+    let body = HashMap::from([("outer", HashMap::from([("inner", 1)]))]);
+    let error = common::build::<_, serde_json::Value, _>([BinanceOption::Default], "https://api.binance.com/api/v3/order", Some(body)).unwrap_err();
+    assert!(matches!(error, BuildError::Serialize { content_type: "application/x-www-form-urlencoded", .. }), "{:?}", error);
+    assert!(error.source().is_some());
+}
+
+#[test]
+fn invalid_header_value_does_not_leak_key() {
+    let options = [
+        CoincheckOption::HttpAuth(true),
+        CoincheckOption::Key("secret\nkey".to_owned()),
+        CoincheckOption::Secret("secret".to_owned()),
+    ];
+    let error = common::build::<_, serde_json::Value, _>(options, "https://coincheck.com/api/accounts/balance", None::<()>).unwrap_err();
+    assert!(matches!(error, BuildError::InvalidHeaderValue { header: "ACCESS-KEY", .. }), "{:?}", error);
+    assert!(!format!("{} {:?}", error, error).contains("secret"));
+}
+
+/// A [Signer] whose daemon is unreachable.
+#[derive(Debug)]
+struct FailingSigner;
+
+impl Signer for FailingSigner {
+    fn sign(&self, _: &[u8]) -> io::Result<Vec<u8>> {
+        Err(io::Error::new(io::ErrorKind::ConnectionRefused, "connection refused"))
+    }
+}
+
+#[test]
+fn signer_failure() {
+    let options = [
+        BinanceOption::HttpAuth(BinanceAuth::Sign),
+        BinanceOption::Key("key".to_owned()),
+        BinanceOption::Signer(Arc::new(FailingSigner)),
+    ];
+    let error = common::build::<_, serde_json::Value, _>(options, "https://api.binance.com/api/v3/account", None::<()>).unwrap_err();
+    assert!(matches!(error, BuildError::Sign(_)), "{:?}", error);
+    assert!(error.to_string().starts_with("failed to sign: "), "{}", error);
+}
+
+#[test]
+fn invalid_key() {
+    let options = [
+        BinanceOption::HttpAuth(BinanceAuth::Sign),
+        BinanceOption::Key("key".to_owned()),
+        BinanceOption::PrivateKeyPem("not a key".to_owned()),
+    ];
+    let error = common::build::<_, serde_json::Value, _>(options, "https://api.binance.com/api/v3/account", None::<()>).unwrap_err();
+    assert!(matches!(error, BuildError::InvalidKey(_)), "{:?}", error);
+    assert!(error.to_string().starts_with("invalid private key: "), "{}", error);
+    assert!(error.source().is_some());
+}
+
+#[test]
+fn unsupported_key() {
+    let options = [
+        BybitOption::HttpAuth(BybitHttpAuth::BelowV3),
+        BybitOption::Key("key".to_owned()),
+        BybitOption::PrivateKeyPem(include_str!("keys/rsa.pem").to_owned()),
+    ];
+    let error = common::build::<_, serde_json::Value, _>(options, "https://api.bybit.com/v2/private/order/create", None::<()>).unwrap_err();
+    assert!(matches!(error, BuildError::UnsupportedKey(_)), "{:?}", error);
+}
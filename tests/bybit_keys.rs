@@ -0,0 +1,68 @@
+//! Tests for Bybit authentication with RSA keys.
+//!
+//! The key in `tests/keys` was generated with OpenSSL, and the expected signatures were computed with `openssl dgst`.
+
+use serde_json::json;
+use crypto_botters::{
+    bybit::{BybitHttpAuth, BybitOption, BybitOptions},
+    clock::{FixedClock, ServerClock},
+    error::BuildError,
+    generic_api_client::{http::RequestHandler, websocket::{WebSocketHandler, WebSocketMessage}},
+    traits::*,
+};
+
+const HTTP_SIGNATURE: &str = "hcdqCxLUk9U/ZpDpEvaZmCy59IfDnpMNYXUW/xc81eIa99zWxdgPI2mcV0EcOhbc17A6EIVrVMvPcR+QZpBk3lBPebxgNCrIKVGayDlEf/OOvGNUTTteLVa0vu7brn1p559unqckGxuZ/9UWqP1iilsU722fn8etKGD3oFIEBrffUDCAecS6ewH2D6RjTiJ8nmgBRHUpcj8WuwRGe/7DUWgtb081o+ZNya8dkGXJXaCuKjCreysZu5tIzAA/6ywUpO0gol79YSal09n8nCwCTr4zdyUqsf72NrsKdZk/5rJJux3zMTjJq0muKR0EsSQ7sl+60Xk9gLFSUTdPVF7iBQ==";
+const WEBSOCKET_SIGNATURE: &str = "hMrcb5u2DYiobmiTetNgTlq67p0LtejIkzg4rcKwfYtWGNNkRmC2+kerY80nNa9NNvvs+SsnHerwdfEl6ubyLXPshkoCuOVTGGPUquylpX6zIuKn/yssx5gZpOzmqv9G0qpACO6lh3TL0HX3zDud30XO1fhn93ca07cGWK0yIWCGYlW7xokqPCpWn6SVq80qq0qYBiclYq2s/cQnHsUaTkTMCavYf0Xr/BaJuRfoWzBo1iI71EQYBl9BXcXNiZI4CDOzIhhg/+dmABLp3oNeIt5raVq7w4QVnjfhB7nV70SL989oQax3GRGEa8q8JlN7hEHeApDzzexdcoQDxZDzkw==";
+
+fn options(auth: BybitHttpAuth) -> BybitOptions {
+    let mut options = BybitOptions::default();
+    for option in [
+        BybitOption::Key("XXXXXXXXXX".to_owned()),
+        BybitOption::PrivateKeyPem(include_str!("keys/rsa.pem").to_owned()),
+        BybitOption::ServerClock(ServerClock::with_clock(FixedClock::from_millis(1658384314791))),
+        BybitOption::HttpAuth(auth),
+        BybitOption::RecvWindow(5000),
+        BybitOption::WebSocketAuth(true),
+    ] {
+        options.update(option);
+    }
+    options
+}
+
+#[test]
+fn http_v3() {
+    let handler = <BybitOption as HttpOption<serde_json::Value, ()>>::request_handler(options(BybitHttpAuth::V3AndAbove));
+    let builder = reqwest::Client::new().get("https://api.bybit.com/v5/order/realtime?category=option&symbol=BTC-29JUL22-25000-C");
+    let request = handler.build_request(builder, &None::<()>, 1).unwrap();
+    assert_eq!(request.headers()["X-BAPI-SIGN"], HTTP_SIGNATURE);
+    assert_eq!(request.headers()["X-BAPI-TIMESTAMP"], "1658384314791");
+    // the sign type is only sent for HMAC signatures of the USDC contract API
+    assert!(request.headers().get("X-BAPI-SIGN-TYPE").is_none());
+}
+
+#[test]
+fn http_below_v3_is_rejected() {
+    let handler = <BybitOption as HttpOption<serde_json::Value, ()>>::request_handler(options(BybitHttpAuth::BelowV3));
+    let builder = reqwest::Client::new().get("https://api.bybit.com/v2/private/position/list?symbol=BTCUSD");
+    let error = handler.build_request(builder, &None::<()>, 1).unwrap_err();
+    assert!(matches!(error, BuildError::UnsupportedKey(_)), "{:?}", error);
+}
+
+#[test]
+fn http_usdc_contract_v1_is_rejected() {
+    let handler = <BybitOption as HttpOption<serde_json::Value, ()>>::request_handler(options(BybitHttpAuth::UsdcContractV1));
+    let builder = reqwest::Client::new().post("https://api.bybit.com/option/usdc/openapi/private/v1/query-position");
+    let error = handler.build_request(builder, &None::<()>, 1).unwrap_err();
+    assert!(matches!(error, BuildError::UnsupportedKey(_)), "{:?}", error);
+}
+
+#[test]
+fn websocket() {
+    let mut handler = BybitOption::websocket_handler(|_: serde_json::Value| {}, options(BybitHttpAuth::None));
+    let messages = handler.handle_start();
+    let [WebSocketMessage::Text(message)] = messages.as_slice() else {
+        panic!("unexpected messages: {:?}", messages);
+    };
+    let message: serde_json::Value = serde_json::from_str(message).unwrap();
+    assert_eq!(message, json!({ "op": "auth", "args": ["XXXXXXXXXX", 1658384315791u64, WEBSOCKET_SIGNATURE] }));
+}
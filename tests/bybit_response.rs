@@ -0,0 +1,94 @@
+mod common;
+
+use serde::Deserialize;
+use crypto_botters::{
+    bybit::{BybitErrorCode, BybitHandlerError, BybitOption},
+    error::ExchangeError,
+    generic_api_client::http::{Bytes, HeaderMap, Method, RequestHandler, RetryDecision, StatusCode},
+};
+
+#[derive(Deserialize, Debug, PartialEq)]
+struct ServerTime {
+    #[serde(rename = "timeSecond")]
+    time_second: String,
+}
+
+#[test]
+fn non_zero_ret_code_is_error() {
+    let body = r#"{"retCode":110007,"retMsg":"ab not enough for new order","result":{},"retExtInfo":{},"time":1700000000000}"#;
+    let error = common::handle::<_, serde_json::Value>([BybitOption::Default], 200, HeaderMap::new(), body).unwrap_err();
+    match &error {
+        BybitHandlerError::RetCode { code, ret_code, ret_msg, body } => {
+            assert_eq!(*code, BybitErrorCode::InsufficientBalance);
+            assert_eq!(*ret_code, 110007);
+            assert_eq!(ret_msg, "ab not enough for new order");
+            assert_eq!(body["time"], 1700000000000_i64);
+        },
+        _ => panic!("unexpected error {:?}", error),
+    }
+    assert_eq!(error.exchange_code(), Some(110007));
+    assert!(!error.is_retryable());
+}
+
+#[test]
+fn legacy_ret_code_is_error() {
+    let body = r#"{"ret_code":10006,"ret_msg":"too many visits!","result":null}"#;
+    let error = common::handle::<_, serde_json::Value>([BybitOption::Default], 200, HeaderMap::new(), body).unwrap_err();
+    assert!(matches!(error, BybitHandlerError::RetCode { code: BybitErrorCode::TooManyVisits, .. }), "{:?}", error);
+    assert!(error.is_rate_limited());
+
+    let body = r#"{"retCode":10004,"retMsg":"error sign!"}"#;
+    let error = common::handle::<_, serde_json::Value>([BybitOption::Default], 200, HeaderMap::new(), body).unwrap_err();
+    assert!(matches!(error, BybitHandlerError::RetCode { code: BybitErrorCode::InvalidSignature, .. }), "{:?}", error);
+    assert!(error.is_auth_error());
+
+    let body = r#"{"retCode":12345,"retMsg":"something new"}"#;
+    let error = common::handle::<_, serde_json::Value>([BybitOption::Default], 200, HeaderMap::new(), body).unwrap_err();
+    assert!(matches!(error, BybitHandlerError::RetCode { code: BybitErrorCode::Other(12345), .. }), "{:?}", error);
+}
+
+#[test]
+fn zero_ret_code_is_success() {
+    let body = r#"{"retCode":0,"retMsg":"OK","result":{"timeSecond":"1700000000"},"retExtInfo":{},"time":1700000000000}"#;
+    let response = common::handle::<_, serde_json::Value>([BybitOption::Default], 200, HeaderMap::new(), body).unwrap();
+    assert_eq!(response["result"]["timeSecond"], "1700000000");
+
+    // responses without a code are passed through
+    let response = common::handle::<_, serde_json::Value>([BybitOption::Default], 200, HeaderMap::new(), r#"{"timeSecond":"1700000000"}"#).unwrap();
+    assert_eq!(response["timeSecond"], "1700000000");
+}
+
+#[test]
+fn unwrap_result() {
+    let body = r#"{"retCode":0,"retMsg":"OK","result":{"timeSecond":"1700000000"},"retExtInfo":{},"time":1700000000000}"#;
+    let time = common::handle::<_, ServerTime>([BybitOption::UnwrapResult(true)], 200, HeaderMap::new(), body).unwrap();
+    assert_eq!(time, ServerTime { time_second: "1700000000".to_owned() });
+
+    let body = r#"{"retCode":10001,"retMsg":"params error","result":{}}"#;
+    let error = common::handle::<_, ServerTime>([BybitOption::UnwrapResult(true)], 200, HeaderMap::new(), body).unwrap_err();
+    assert!(matches!(error, BybitHandlerError::RetCode { code: BybitErrorCode::InvalidRequest, .. }), "{:?}", error);
+}
+
+#[test]
+fn retry_decision_does_not_affect_handle_response() {
+    let handler = common::handler::<_, ServerTime, ()>([BybitOption::UnwrapResult(true)]);
+    let body = Bytes::from_static(br#"{"retCode":0,"retMsg":"OK","result":{"timeSecond":"1700000000"}}"#);
+    let decision = RequestHandler::<()>::retry_decision(&handler, &Method::GET, StatusCode::OK, &HeaderMap::new(), &body);
+    assert_eq!(decision, RetryDecision::DoNotRetry);
+    let time = RequestHandler::<()>::handle_response(&handler, StatusCode::OK, HeaderMap::new(), body).unwrap();
+    assert_eq!(time, ServerTime { time_second: "1700000000".to_owned() });
+
+    // the response passed to handle_response() is used even if retry_decision() saw another one
+    let body = Bytes::from_static(br#"{"retCode":0,"retMsg":"OK","result":{"timeSecond":"1"}}"#);
+    let other = Bytes::from_static(br#"{"retCode":10001,"retMsg":"params error","result":{}}"#);
+    RequestHandler::<()>::retry_decision(&handler, &Method::GET, StatusCode::OK, &HeaderMap::new(), &other);
+    let time = RequestHandler::<()>::handle_response(&handler, StatusCode::OK, HeaderMap::new(), body).unwrap();
+    assert_eq!(time, ServerTime { time_second: "1".to_owned() });
+
+    let decision = RequestHandler::<()>::retry_decision(&handler, &Method::GET, StatusCode::OK, &HeaderMap::new(), &Bytes::from_static(br#"{"retCode":10002,"retMsg":"invalid timestamp"}"#));
+    assert_eq!(decision, RetryDecision::Retry);
+
+    let body = Bytes::from_static(br#"{"retCode":0,"retMsg":"OK","result":null}"#);
+    let handler = common::handler::<_, Option<ServerTime>, ()>([BybitOption::UnwrapResult(true)]);
+    assert_eq!(RequestHandler::<()>::handle_response(&handler, StatusCode::OK, HeaderMap::new(), body).unwrap(), None);
+}
@@ -0,0 +1,193 @@
+mod common;
+
+use std::time::{Duration, SystemTime};
+use crypto_botters::{
+    binance::BinanceOption,
+    bitflyer::BitFlyerOption,
+    clock::{FixedClock, ServerClock},
+    coincheck::CoincheckOption,
+    generic_api_client::http::{header::{self, HeaderMap, HeaderValue}, Bytes, Method, RequestError, RequestHandler, RetryDecision, StatusCode},
+    traits::*,
+    Client,
+};
+use common::StubServer;
+
+/// Options of an exchange whose server time is served by a local server.
+#[derive(Default)]
+enum StubOption {
+    #[default]
+    Default,
+    Url(String),
+    ServerClock(ServerClock),
+}
+
+#[derive(Default, Clone, Debug)]
+struct StubOptions {
+    url: String,
+    server_clock: ServerClock,
+}
+
+impl HandlerOptions for StubOptions {
+    type OptionItem = StubOption;
+
+    fn update(&mut self, option: Self::OptionItem) {
+        match option {
+            StubOption::Default => (),
+            StubOption::Url(v) => self.url = v,
+            StubOption::ServerClock(v) => self.server_clock = v,
+        }
+    }
+
+    fn set_unknown_account(&mut self, _: String) {
+        // StubOption doesn't select account profiles
+    }
+}
+
+impl HandlerOption for StubOption {
+    type Options = StubOptions;
+}
+
+impl ServerTimeOption for StubOption {
+    fn server_time_url(options: &Self::Options) -> String {
+        format!("{}/time", options.url)
+    }
+
+    fn parse_server_time(_: &HeaderMap, response_body: &[u8]) -> Option<SystemTime> {
+        let millis = std::str::from_utf8(response_body).ok()?.parse().ok()?;
+        Some(SystemTime::UNIX_EPOCH + Duration::from_millis(millis))
+    }
+
+    fn server_clock(options: &Self::Options) -> &ServerClock {
+        &options.server_clock
+    }
+}
+
+/// Starts an HTTP server which responds with each of the server times in `bodies` in order, then with status 500.
+fn start_server(bodies: &[&str]) -> StubServer {
+    StubServer::start(bodies.iter().map(|body| common::ok(body)).collect::<Vec<_>>())
+}
+
+fn client(url: String, clock: &ServerClock) -> Client {
+    let mut client = Client::new();
+    client.update_default_option(StubOption::Url(url));
+    client.update_default_option(StubOption::ServerClock(clock.clone()));
+    client
+}
+
+#[tokio::test]
+async fn sync_clock_sets_offset() {
+    let server = start_server(&["1005000", "1007000"]);
+    // the local clock doesn't advance, so the round trip time is the minimum
+    let clock = ServerClock::with_clock(FixedClock::from_millis(1_000_000));
+    let client = client(server.url.clone(), &clock);
+    assert_eq!(clock.rtt(), None);
+
+    client.sync_clock::<StubOption>().await.unwrap();
+    assert_eq!(clock.offset_micros(), 5_000_000);
+    assert_eq!(clock.now_millis(), 1_005_000);
+    assert!(clock.rtt().is_some());
+
+    // later samples are averaged
+    client.sync_clock::<StubOption>().await.unwrap();
+    assert_eq!(clock.offset_micros(), 6_000_000);
+
+    // failures don't change the offset
+    let error = client.sync_clock::<StubOption>().await.unwrap_err();
+    assert!(matches!(error, RequestError::ResponseHandleError("unsuccessful response")), "{:?}", error);
+    assert_eq!(clock.offset_micros(), 6_000_000);
+    assert_eq!(server.requests.try_iter().count(), 3);
+}
+
+#[tokio::test]
+async fn stale_offset_is_replaced() {
+    let server = start_server(&["1005000", "999000"]);
+    let clock = ServerClock::with_clock(FixedClock::from_millis(1_000_000));
+    let client = client(server.url.clone(), &clock);
+    client.sync_clock::<StubOption>().await.unwrap();
+
+    // the server rejected a timestamp, so the next sample is used as it is instead of being averaged
+    clock.request_resync();
+    client.sync_clock::<StubOption>().await.unwrap();
+    assert_eq!(clock.offset_micros(), -1_000_000);
+}
+
+/// Headers with a `Date` header of `seconds` after the epoch.
+fn date(seconds: u64) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let date = httpdate::fmt_http_date(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds));
+    headers.insert(header::DATE, HeaderValue::from_str(&date).unwrap());
+    headers
+}
+
+#[test]
+fn date_header_corrects_large_errors() {
+    let clock = ServerClock::with_clock(FixedClock::from_millis(1_000_000_000));
+
+    // within a second of the Date header
+    clock.observe_date_header(&date(1_000_000));
+    assert_eq!(clock.offset_micros(), 0);
+
+    clock.observe_date_header(&date(1_000_010));
+    assert_eq!(clock.offset_micros(), 10_500_000);
+}
+
+#[tokio::test]
+async fn spawn_clock_sync_resamples_on_request() {
+    let server = start_server(&["1005000", "1006000"]);
+    let clock = ServerClock::with_clock(FixedClock::from_millis(1_000_000));
+    let client = client(server.url.clone(), &clock);
+
+    let task = client.spawn_clock_sync::<StubOption>(Duration::from_secs(3600));
+    server.next_request().await;
+    clock.request_resync();
+    server.next_request().await;
+    task.abort();
+
+    // wait for the response to be applied
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while clock.offset_micros() != 6_000_000 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }).await.unwrap();
+}
+
+#[test]
+fn binance_server_time() {
+    let time = BinanceOption::parse_server_time(&HeaderMap::new(), br#"{"serverTime":1499827319559}"#).unwrap();
+    assert_eq!(time, SystemTime::UNIX_EPOCH + Duration::from_millis(1499827319559));
+    assert_eq!(BinanceOption::parse_server_time(&HeaderMap::new(), b"{}"), None);
+}
+
+#[test]
+fn bitflyer_timestamp_error_is_retried() {
+    let clock = ServerClock::with_clock(FixedClock::from_millis(1_000_000_000));
+    let handler = common::handler::<_, serde_json::Value, ()>([BitFlyerOption::ServerClock(clock.clone())]);
+    let retry_decision = |status: u16, body: &'static str| RequestHandler::<()>::retry_decision(
+        &handler, &Method::POST, StatusCode::from_u16(status).unwrap(), &date(1_000_010), &Bytes::from_static(body.as_bytes()),
+    );
+
+    let decision = retry_decision(400, r#"{"status":-500,"error_message":"Key not found","data":null}"#);
+    assert_eq!(decision, RetryDecision::DoNotRetry);
+    assert_eq!(clock.offset_micros(), 0);
+
+    let decision = retry_decision(400, r#"{"status":-501,"error_message":"ACCESS-TIMESTAMP is out of range","data":null}"#);
+    assert_eq!(decision, RetryDecision::Retry);
+    assert_eq!(clock.offset_micros(), 10_500_000);
+}
+
+#[test]
+fn coincheck_nonce_error_is_retried() {
+    let clock = ServerClock::with_clock(FixedClock::from_millis(1_000_000_000));
+    let handler = common::handler::<_, serde_json::Value, ()>([CoincheckOption::ServerClock(clock.clone())]);
+    let retry_decision = |status: u16, body: &'static str| RequestHandler::<()>::retry_decision(
+        &handler, &Method::POST, StatusCode::from_u16(status).unwrap(), &date(1_000_010), &Bytes::from_static(body.as_bytes()),
+    );
+
+    let decision = retry_decision(200, r#"{"success":false,"error":"invalid authentication"}"#);
+    assert_eq!(decision, RetryDecision::DoNotRetry);
+    assert_eq!(clock.offset_micros(), 0);
+
+    let decision = retry_decision(401, r#"{"success":false,"error":"Nonce must be incremented"}"#);
+    assert_eq!(decision, RetryDecision::Retry);
+    assert_eq!(clock.offset_micros(), 10_500_000);
+}
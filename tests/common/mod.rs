@@ -0,0 +1,136 @@
+//! Helpers shared by the integration tests which call request handlers directly.
+
+#![allow(dead_code)]
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    sync::{mpsc::{self, Receiver}, Arc, Mutex},
+    time::Duration,
+};
+use crypto_botters::{
+    generic_api_client::http::{header::{HeaderName, HeaderValue}, Bytes, HeaderMap, Request, RequestHandler, StatusCode},
+    traits::*,
+};
+
+/// The successful and unsuccessful results of the request handler of `O`.
+pub type Handled<'a, O, R> = Result<
+    <<O as HttpOption<'a, R, ()>>::RequestHandler as RequestHandler<()>>::Successful,
+    <<O as HttpOption<'a, R, ()>>::RequestHandler as RequestHandler<()>>::Unsuccessful,
+>;
+
+/// The [Default] options of `O` updated with `options`.
+pub fn options<O: HandlerOption>(options: impl IntoIterator<Item=O>) -> O::Options {
+    let mut default = O::Options::default();
+    for option in options {
+        default.update(option);
+    }
+    default
+}
+
+/// The request handler of `O` with the [Default] options updated with `options`.
+pub fn handler<'a, O, R, B>(options: impl IntoIterator<Item=O>) -> O::RequestHandler
+where
+    O: HttpOption<'a, R, B>,
+{
+    O::request_handler(self::options(options))
+}
+
+/// Passes a response to the request handler of `O`.
+pub fn handle<'a, O, R>(options: impl IntoIterator<Item=O>, status: u16, headers: HeaderMap, body: &'static str) -> Handled<'a, O, R>
+where
+    O: HttpOption<'a, R, ()>,
+{
+    let status = StatusCode::from_u16(status).unwrap();
+    handler::<O, R, ()>(options).handle_response(status, headers, Bytes::from_static(body.as_bytes()))
+}
+
+/// Builds a `POST` request to `url` with the request handler of `O`.
+pub fn build<'a, O, R, B>(
+    options: impl IntoIterator<Item=O>,
+    url: &str,
+    body: Option<B>,
+) -> Result<Request, <O::RequestHandler as RequestHandler<B>>::BuildError>
+where
+    O: HttpOption<'a, R, B>,
+{
+    let builder = reqwest::Client::new().post(url);
+    handler::<O, R, B>(options).build_request(builder, &body, 1)
+}
+
+/// The response of a [StubServer] when it has run out of responses.
+pub const SERVER_ERROR: &str = "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\n\r\n";
+
+/// A `200 OK` response with `body`.
+pub fn ok(body: &str) -> String {
+    format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", body.len(), body)
+}
+
+/// An HTTP server which answers requests with canned responses.
+pub struct StubServer {
+    /// The url of the server, such as `http://127.0.0.1:8080`.
+    pub url: String,
+    /// Receives the headers of each request, before the request is answered.
+    pub requests: Receiver<HeaderMap>,
+}
+
+impl StubServer {
+    /// Starts a server which answers requests with `responses` in order, and with [SERVER_ERROR] when they run out.
+    ///
+    /// Use [std::iter::repeat()] to give the same response to every request.
+    pub fn start<I>(responses: I) -> Self
+    where
+        I: IntoIterator<Item = String>,
+        I::IntoIter: Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let responses = Arc::new(Mutex::new(responses.into_iter()));
+        let (sender, requests) = mpsc::channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let responses = Arc::clone(&responses);
+                let sender = sender.clone();
+                std::thread::spawn(move || {
+                    let mut stream = stream.unwrap();
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    loop {
+                        let mut headers = HeaderMap::new();
+                        loop {
+                            let mut line = String::new();
+                            if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                                return;
+                            }
+                            let line = line.trim_end();
+                            if line.is_empty() {
+                                break;
+                            }
+                            if let Some((name, value)) = line.split_once(": ") {
+                                headers.append(HeaderName::from_bytes(name.as_bytes()).unwrap(), HeaderValue::from_str(value).unwrap());
+                            }
+                        }
+                        // the test may have finished with this server
+                        sender.send(headers).ok();
+                        let response = responses.lock().unwrap().next().unwrap_or_else(|| SERVER_ERROR.to_owned());
+                        if stream.write_all(response.as_bytes()).is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        Self { url, requests }
+    }
+
+    /// Waits for the next request without blocking the runtime, and returns its headers.
+    pub async fn next_request(&self) -> HeaderMap {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Ok(headers) = self.requests.try_recv() {
+                    return headers;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.expect("timed out waiting for a request")
+    }
+}
@@ -0,0 +1,91 @@
+use std::{fs::File, sync::Arc, time::{Duration, SystemTime}};
+use serde_json::json;
+use crypto_botters::{
+    binance::{BinanceAuth, BinanceOption, BinanceOptions},
+    bybit::BybitOption,
+    credentials::{CredentialProvider, Credentials, EnvCredentials, FileCredentials, StaticCredentials},
+    generic_api_client::{http::RequestHandler, websocket::{WebSocketHandler, WebSocketMessage}},
+    traits::*,
+};
+
+fn write_credentials(path: &std::path::Path, contents: serde_json::Value, modified: SystemTime) {
+    std::fs::write(path, contents.to_string()).unwrap();
+    File::options().write(true).open(path).unwrap().set_modified(modified).unwrap();
+}
+
+fn binance_key(provider: Arc<dyn CredentialProvider>) -> String {
+    let mut options = BinanceOptions::default();
+    options.update(BinanceOption::Key("ignored".to_owned()));
+    options.update(BinanceOption::Credentials(provider));
+    options.update(BinanceOption::HttpAuth(BinanceAuth::Key));
+    let handler = <BinanceOption as HttpOption<serde_json::Value, ()>>::request_handler(options);
+    let builder = reqwest::Client::new().post("https://api.binance.com/api/v3/userDataStream");
+    let request = handler.build_request(builder, &None::<()>, 1).unwrap();
+    request.headers()["X-MBX-APIKEY"].to_str().unwrap().to_owned()
+}
+
+#[test]
+fn static_credentials() {
+    let provider = Arc::new(StaticCredentials(Credentials::new("static key", "secret")));
+    assert_eq!(binance_key(provider), "static key");
+}
+
+#[test]
+fn env_credentials() {
+    std::env::set_var("CRYPTO_BOTTERS_TEST_KEY", "env key");
+    let provider = Arc::new(EnvCredentials::new("CRYPTO_BOTTERS_TEST_KEY", "CRYPTO_BOTTERS_TEST_SECRET"));
+    assert_eq!(binance_key(provider.clone()), "env key");
+
+    std::env::set_var("CRYPTO_BOTTERS_TEST_KEY", "rotated env key");
+    assert_eq!(binance_key(provider.clone()), "rotated env key");
+
+    std::env::remove_var("CRYPTO_BOTTERS_TEST_KEY");
+    assert!(provider.credentials().is_err());
+}
+
+#[test]
+fn file_credentials_are_reloaded() {
+    let path = std::env::temp_dir().join(format!("crypto-botters-credentials-test-{}.json", std::process::id()));
+    let start = SystemTime::now();
+    write_credentials(&path, json!({ "key": "file key", "secret": "file secret" }), start);
+
+    let provider: Arc<dyn CredentialProvider> = Arc::new(FileCredentials::new(&path));
+    assert_eq!(binance_key(Arc::clone(&provider)), "file key");
+
+    write_credentials(&path, json!({ "key": "rotated file key", "secret": "rotated file secret" }), start + Duration::from_secs(1));
+    assert_eq!(binance_key(Arc::clone(&provider)), "rotated file key");
+
+    // a broken file keeps the last credentials
+    std::fs::write(&path, "{").unwrap();
+    File::options().write(true).open(&path).unwrap().set_modified(start + Duration::from_secs(2)).unwrap();
+    assert_eq!(binance_key(Arc::clone(&provider)), "rotated file key");
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn websocket_reauthentication_uses_rotated_credentials() {
+    let path = std::env::temp_dir().join(format!("crypto-botters-credentials-ws-test-{}.json", std::process::id()));
+    let start = SystemTime::now();
+    write_credentials(&path, json!({ "key": "first", "secret": "secret" }), start);
+
+    let mut options = <BybitOption as HandlerOption>::Options::default();
+    options.update(BybitOption::Credentials(Arc::new(FileCredentials::new(&path))));
+    options.update(BybitOption::WebSocketAuth(true));
+    let mut handler = BybitOption::websocket_handler(|_: serde_json::Value| {}, options);
+
+    let auth_key = |messages: Vec<WebSocketMessage>| {
+        let [WebSocketMessage::Text(message)] = messages.as_slice() else {
+            panic!("unexpected messages: {:?}", messages);
+        };
+        let message: serde_json::Value = serde_json::from_str(message).unwrap();
+        message["args"][0].as_str().unwrap().to_owned()
+    };
+    assert_eq!(auth_key(handler.handle_start()), "first");
+
+    write_credentials(&path, json!({ "key": "second", "secret": "secret" }), start + Duration::from_secs(1));
+    // handle_start() is called again when the connection is reconnected
+    assert_eq!(auth_key(handler.handle_start()), "second");
+
+    std::fs::remove_file(&path).unwrap();
+}
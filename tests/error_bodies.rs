@@ -0,0 +1,76 @@
+mod common;
+
+use crypto_botters::{
+    bitflyer::{BitFlyerErrorCode, BitFlyerHandlerError, BitFlyerOption},
+    coincheck::{CoincheckHandlerError, CoincheckOption},
+    error::ExchangeError,
+    generic_api_client::http::{HeaderMap, StatusCode},
+};
+
+#[test]
+fn bitflyer_negative_status() {
+    let body = r#"{"status":-205,"error_message":"Margin amount is insufficient for this order.","data":null}"#;
+    let error = common::handle::<_, serde_json::Value>([BitFlyerOption::Default], 200, HeaderMap::new(), body).unwrap_err();
+    match &error {
+        BitFlyerHandlerError::ErrorStatus { code, status, error_message, .. } => {
+            assert_eq!(*code, BitFlyerErrorCode::InsufficientMargin);
+            assert_eq!(*status, -205);
+            assert_eq!(error_message, "Margin amount is insufficient for this order.");
+        },
+        _ => panic!("unexpected error {:?}", error),
+    }
+    assert_eq!(error.exchange_code(), Some(-205));
+    assert!(!error.is_retryable());
+
+    let error = common::handle::<_, serde_json::Value>([BitFlyerOption::Default], 200, HeaderMap::new(), r#"{"status":-208,"error_message":"Order is not accepted. Please try again later."}"#).unwrap_err();
+    assert!(matches!(error, BitFlyerHandlerError::ErrorStatus { code: BitFlyerErrorCode::OrderNotAccepted, .. }), "{:?}", error);
+    assert!(error.is_retryable());
+
+    let error = common::handle::<_, serde_json::Value>([BitFlyerOption::Default], 200, HeaderMap::new(), r#"{"status":-500,"error_message":"Key not found","data":null}"#).unwrap_err();
+    assert!(error.is_auth_error());
+
+    let error = common::handle::<_, serde_json::Value>([BitFlyerOption::Default], 200, HeaderMap::new(), r#"{"status":-999,"error_message":"unknown","data":null}"#).unwrap_err();
+    assert!(matches!(error, BitFlyerHandlerError::ErrorStatus { code: BitFlyerErrorCode::Other(-999), .. }), "{:?}", error);
+}
+
+#[test]
+fn bitflyer_success() {
+    // the health endpoint has a string status
+    let response = common::handle::<_, serde_json::Value>([BitFlyerOption::Default], 200, HeaderMap::new(), r#"{"status":"NORMAL"}"#).unwrap();
+    assert_eq!(response["status"], "NORMAL");
+
+    let response = common::handle::<_, serde_json::Value>([BitFlyerOption::Default], 200, HeaderMap::new(), r#"{"status":0,"data":null}"#).unwrap();
+    assert_eq!(response["status"], 0);
+
+    let response = common::handle::<_, serde_json::Value>([BitFlyerOption::Default], 200, HeaderMap::new(), r#"[{"product_code":"BTC_JPY","market_type":"Spot"}]"#).unwrap();
+    assert_eq!(response[0]["product_code"], "BTC_JPY");
+
+    let response = common::handle::<_, serde_json::Value>([BitFlyerOption::Default], 200, HeaderMap::new(), "[]").unwrap();
+    assert_eq!(response, serde_json::json!([]));
+}
+
+#[test]
+fn coincheck_success_false() {
+    let error = common::handle::<_, serde_json::Value>([CoincheckOption::Default], 200, HeaderMap::new(), r#"{"success":false,"error":"Amount is insufficient"}"#).unwrap_err();
+    match &error {
+        CoincheckHandlerError::Failure { error, body } => {
+            assert_eq!(error, "Amount is insufficient");
+            assert_eq!(body["success"], false);
+        },
+        _ => panic!("unexpected error {:?}", error),
+    }
+    assert_eq!(error.status(), Some(StatusCode::OK));
+
+    let error = common::handle::<_, serde_json::Value>([CoincheckOption::Default], 200, HeaderMap::new(), r#"{"success":false,"error":{"amount":["is too small"]}}"#).unwrap_err();
+    assert!(matches!(&error, CoincheckHandlerError::Failure { error, .. } if error == r#"{"amount":["is too small"]}"#), "{:?}", error);
+}
+
+#[test]
+fn coincheck_success() {
+    let response = common::handle::<_, serde_json::Value>([CoincheckOption::Default], 200, HeaderMap::new(), r#"{"success":true,"jpy":"0.8401","btc":"7.75052654"}"#).unwrap();
+    assert_eq!(response["jpy"], "0.8401");
+
+    // public APIs don't have "success"
+    let response = common::handle::<_, serde_json::Value>([CoincheckOption::Default], 200, HeaderMap::new(), r#"{"last":27390,"bid":26900}"#).unwrap();
+    assert_eq!(response["last"], 27390);
+}
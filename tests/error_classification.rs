@@ -0,0 +1,115 @@
+mod common;
+
+use std::time::Duration;
+use crypto_botters::{
+    binance::{BinanceHandlerError, BinanceOption},
+    bitflyer::BitFlyerOption,
+    bybit::{BybitHandlerError, BybitOption},
+    coincheck::CoincheckOption,
+    error::{BuildError, ExchangeError},
+    generic_api_client::http::{header::HeaderValue, Bytes, HeaderMap, RequestError, RequestHandler, StatusCode},
+};
+
+#[test]
+fn binance() {
+    let error = common::handle::<_, serde_json::Value>([BinanceOption::Default], 400, HeaderMap::new(), r#"{"code":-2015,"msg":"Invalid API-key, IP, or permissions for action."}"#).unwrap_err();
+    assert!(error.is_auth_error());
+    assert!(!error.is_retryable());
+    assert_eq!(error.exchange_code(), Some(-2015));
+
+    let error = common::handle::<_, serde_json::Value>([BinanceOption::Default], 400, HeaderMap::new(), r#"{"code":-1021,"msg":"Timestamp for this request is outside of the recvWindow."}"#).unwrap_err();
+    assert!(!error.is_auth_error());
+    assert!(error.is_retryable());
+
+    let mut headers = HeaderMap::new();
+    headers.insert("Retry-After", HeaderValue::from_static("30"));
+    let error = common::handle::<_, serde_json::Value>([BinanceOption::Default], 429, headers, r#"{"code":-1003,"msg":"Too many requests."}"#).unwrap_err();
+    assert!(matches!(error, BinanceHandlerError::RateLimitError { retry_after: Some(30) }), "{:?}", error);
+    assert!(error.is_rate_limited());
+    assert!(error.is_retryable());
+    assert_eq!(error.retry_after(), Some(Duration::from_secs(30)));
+
+    // IP bans use the HTTP-date form
+    let mut headers = HeaderMap::new();
+    let date = httpdate::fmt_http_date(std::time::SystemTime::now() + Duration::from_secs(7200));
+    headers.insert("Retry-After", HeaderValue::from_str(&date).unwrap());
+    let error = common::handle::<_, serde_json::Value>([BinanceOption::Default], 418, headers, r#"{"code":-1003,"msg":"Way too many requests; IP banned."}"#).unwrap_err();
+    let retry_after = error.retry_after().unwrap();
+    assert!(retry_after > Duration::from_secs(7190) && retry_after <= Duration::from_secs(7200), "{:?}", retry_after);
+}
+
+#[test]
+fn bybit() {
+    let error = common::handle::<_, serde_json::Value>([BybitOption::Default], 401, HeaderMap::new(), r#"{"retCode":10003,"retMsg":"API key is invalid."}"#).unwrap_err();
+    assert!(error.is_auth_error());
+    assert_eq!(error.exchange_code(), Some(10003));
+
+    let error = common::handle::<_, serde_json::Value>([BybitOption::Default], 429, HeaderMap::new(), r#"{"ret_code":10006,"ret_msg":"Too many visits!"}"#).unwrap_err();
+    assert!(error.is_rate_limited());
+    assert!(error.is_retryable());
+    assert_eq!(error.exchange_code(), Some(10006));
+
+    let error = common::handle::<_, serde_json::Value>([BybitOption::Default], 403, HeaderMap::new(), r#"{"retCode":403,"retMsg":"Forbidden"}"#).unwrap_err();
+    assert!(matches!(error, BybitHandlerError::IpBan(_)), "{:?}", error);
+    assert!(error.is_rate_limited());
+    assert_eq!(error.status(), Some(StatusCode::FORBIDDEN));
+}
+
+#[test]
+fn bitflyer() {
+    let error = common::handle::<_, serde_json::Value>([BitFlyerOption::Default], 401, HeaderMap::new(), r#"{"status":-500,"error_message":"Key not found","data":null}"#).unwrap_err();
+    assert!(error.is_auth_error());
+    assert!(!error.is_retryable());
+    assert_eq!(error.exchange_code(), Some(-500));
+    assert_eq!(error.status(), Some(StatusCode::UNAUTHORIZED));
+}
+
+#[test]
+fn coincheck() {
+    let error = common::handle::<_, serde_json::Value>([CoincheckOption::Default], 429, HeaderMap::new(), r#"{"success":false,"error":"too many requests"}"#).unwrap_err();
+    assert!(error.is_rate_limited());
+    assert!(error.is_retryable());
+    assert_eq!(error.exchange_code(), None);
+
+    let error = common::handle::<_, serde_json::Value>([CoincheckOption::Default], 401, HeaderMap::new(), r#"{"success":false,"error":"invalid authentication"}"#).unwrap_err();
+    assert!(error.is_auth_error());
+}
+
+#[test]
+fn parse_error_keeps_status_and_body() {
+    let body = "<html><body>502 Bad Gateway</body></html>";
+    let error = common::handle::<_, serde_json::Value>([CoincheckOption::Default], 502, HeaderMap::new(), body).unwrap_err();
+    assert!(error.is_retryable());
+    assert_eq!(error.status(), Some(StatusCode::BAD_GATEWAY));
+    assert_eq!(error.raw_body(), Some(body.as_bytes()));
+
+    let error = common::handle::<_, serde_json::Value>([BinanceOption::Default], 200, HeaderMap::new(), "not json").unwrap_err();
+    assert!(matches!(error, BinanceHandlerError::ParseError { .. }), "{:?}", error);
+    assert!(!error.is_retryable());
+    assert_eq!(error.status(), Some(StatusCode::OK));
+    assert_eq!(error.raw_body(), Some(&b"not json"[..]));
+
+    // an owned buffer which isn't shared could be parsed in place, but the body of the error must be left untouched
+    let body = r#"{"symbol":"BTC\u0055SDT","escaped":"a\"b\\c","price":}"#;
+    let handler = common::handler::<_, serde_json::Value, ()>([BinanceOption::Default]);
+    let error = RequestHandler::<()>::handle_response(&handler, StatusCode::OK, HeaderMap::new(), Bytes::from(body.as_bytes().to_vec())).unwrap_err();
+    assert!(matches!(error, BinanceHandlerError::ParseError { .. }), "{:?}", error);
+    assert_eq!(error.raw_body(), Some(body.as_bytes()));
+}
+
+#[test]
+fn request_error() {
+    let error: RequestError<BuildError, BinanceHandlerError> = RequestError::RateLimitExceeded { wait: Duration::from_secs(5) };
+    assert!(error.is_rate_limited());
+    assert!(error.is_retryable());
+    assert_eq!(error.retry_after(), Some(Duration::from_secs(5)));
+
+    let error: RequestError<BuildError, BinanceHandlerError> = RequestError::BuildRequestError(BuildError::MissingCredential("API key"));
+    assert!(!error.is_retryable());
+    assert!(!error.is_auth_error());
+
+    let handler_error = common::handle::<_, serde_json::Value>([BybitOption::Default], 401, HeaderMap::new(), r#"{"retCode":10004,"retMsg":"error sign!"}"#).unwrap_err();
+    let error: RequestError<BuildError, BybitHandlerError> = RequestError::ResponseHandleError(handler_error);
+    assert!(error.is_auth_error());
+    assert_eq!(error.exchange_code(), Some(10004));
+}
@@ -0,0 +1,166 @@
+mod common;
+
+use std::iter;
+use crypto_botters::{
+    generic_api_client::http::{header::HeaderMap, *},
+    traits::*,
+    Client, GetOptions,
+};
+use common::StubServer;
+
+/// Options of an exchange which isn't built into crypto-botters.
+#[derive(Default)]
+enum EchoOption {
+    #[default]
+    Default,
+    Account(String),
+    Url(String),
+    Name(String),
+}
+
+#[derive(Default, Clone, Debug)]
+struct EchoOptions {
+    url: String,
+    name: Option<String>,
+    unknown_account: Option<String>,
+}
+
+impl HandlerOptions for EchoOptions {
+    type OptionItem = EchoOption;
+
+    fn update(&mut self, option: Self::OptionItem) {
+        match option {
+            EchoOption::Default | EchoOption::Account(_) => (),
+            EchoOption::Url(v) => self.url = v,
+            EchoOption::Name(v) => self.name = Some(v),
+        }
+    }
+
+    fn set_unknown_account(&mut self, name: String) {
+        self.unknown_account = Some(name);
+    }
+}
+
+impl HandlerOption for EchoOption {
+    type Options = EchoOptions;
+
+    fn account_name(&self) -> Option<&str> {
+        match self {
+            Self::Account(name) => Some(name),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> HttpOption<'a, String, ()> for EchoOption {
+    type RequestHandler = EchoRequestHandler;
+
+    fn request_handler(options: Self::Options) -> Self::RequestHandler {
+        EchoRequestHandler { options }
+    }
+}
+
+struct EchoRequestHandler {
+    options: EchoOptions,
+}
+
+impl RequestHandler<()> for EchoRequestHandler {
+    type Successful = String;
+    type Unsuccessful = StatusCode;
+    type BuildError = String;
+
+    fn request_config(&self) -> RequestConfig {
+        let mut config = RequestConfig::default();
+        config.url_prefix = self.options.url.clone();
+        config
+    }
+
+    fn build_request(&self, mut builder: RequestBuilder, _: &Option<()>, _: u8) -> Result<Request, Self::BuildError> {
+        if let Some(name) = &self.options.unknown_account {
+            return Err(format!("account {name:?} is not registered"));
+        }
+        if let Some(name) = &self.options.name {
+            builder = builder.header("X-Name", name);
+        }
+        builder.build().or(Err("failed to build request".to_owned()))
+    }
+
+    fn handle_response(&self, status: StatusCode, _: HeaderMap, response_body: Bytes) -> Result<Self::Successful, Self::Unsuccessful> {
+        if status.is_success() {
+            Ok(String::from_utf8_lossy(&response_body).into_owned())
+        } else {
+            Err(status)
+        }
+    }
+}
+
+/// Starts an HTTP server which responds `ok` to every request.
+fn start_server() -> StubServer {
+    StubServer::start(iter::repeat(common::ok("ok")))
+}
+
+/// The `X-Name` header of the next request received by `server`.
+fn name(server: &StubServer) -> Option<String> {
+    let headers = server.requests.recv().unwrap();
+    headers.get("x-name").map(|name| name.to_str().unwrap().to_owned())
+}
+
+#[tokio::test]
+async fn user_defined_exchange() {
+    let server = start_server();
+    let mut client = Client::new();
+    client.register_options(EchoOptions::default());
+    client.update_default_option(EchoOption::Url(server.url.clone()));
+    client.update_default_option(EchoOption::Name("default".to_owned()));
+    client.add_account("sub", [EchoOption::Name("sub account".to_owned())]);
+
+    let response = client.get_no_query("/", [EchoOption::Default]).await.unwrap();
+    assert_eq!(response, "ok");
+    assert_eq!(name(&server).as_deref(), Some("default"));
+    client.get_no_query("/", [EchoOption::Name("per request".to_owned())]).await.unwrap();
+    assert_eq!(name(&server).as_deref(), Some("per request"));
+    client.get_no_query("/", [EchoOption::Account("sub".to_owned())]).await.unwrap();
+    assert_eq!(name(&server).as_deref(), Some("sub account"));
+
+    // clones have their own options, like the built-in exchanges
+    let mut clone = client.clone();
+    clone.update_default_option(EchoOption::Name("clone".to_owned()));
+    clone.get_no_query("/", [EchoOption::Default]).await.unwrap();
+    assert_eq!(name(&server).as_deref(), Some("clone"));
+    client.get_no_query("/", [EchoOption::Default]).await.unwrap();
+    assert_eq!(name(&server).as_deref(), Some("default"));
+}
+
+#[tokio::test]
+async fn unregistered_exchange_uses_default_options() {
+    let server = start_server();
+    let client = Client::new();
+    let options: &EchoOptions = client.default_options();
+    assert_eq!(options.url, "");
+    assert_eq!(options.name, None);
+    assert!(GetOptions::<EchoOptions>::accounts(&client).is_empty());
+
+    client.get_no_query("/", [EchoOption::Url(server.url.clone())]).await.unwrap();
+    assert_eq!(name(&server), None);
+    client.get_no_query("/", [EchoOption::Url(server.url.clone()), EchoOption::Name("per request".to_owned())]).await.unwrap();
+    assert_eq!(name(&server).as_deref(), Some("per request"));
+}
+
+#[tokio::test]
+async fn unknown_account_fails() {
+    let server = start_server();
+    let mut client = Client::new();
+    client.update_default_option(EchoOption::Url(server.url.clone()));
+    client.update_default_option(EchoOption::Name("default".to_owned()));
+
+    let error = client.get_no_query("/", [EchoOption::Account("missing".to_owned())]).await.unwrap_err();
+    assert!(matches!(&error, RequestError::BuildRequestError(message) if message == "account \"missing\" is not registered"), "{:?}", error);
+    // the request is not sent with the default options
+    assert!(server.requests.try_recv().is_err());
+}
+
+#[test]
+fn client_is_send_and_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Client>();
+}
@@ -0,0 +1,75 @@
+use std::{collections::HashSet, thread};
+use crypto_botters::nonce::MonotonicNonce;
+
+#[test]
+fn monotonic_nonce_is_strictly_increasing() {
+    let nonce = MonotonicNonce::new();
+    assert_eq!(nonce.next("key", 1000).unwrap(), 1000);
+    assert_eq!(nonce.next("key", 1000).unwrap(), 1001);
+    assert_eq!(nonce.next("key", 999).unwrap(), 1002);
+    assert_eq!(nonce.next("key", 2000).unwrap(), 2000);
+    // other keys are independent
+    assert_eq!(nonce.next("other key", 1000).unwrap(), 1000);
+}
+
+#[test]
+fn monotonic_nonce_is_unique_across_clones() {
+    let nonce = MonotonicNonce::new();
+    let threads: Vec<_> = (0..8).map(|_| {
+        let nonce = nonce.clone();
+        thread::spawn(move || (0..1000).map(|_| nonce.next("key", 1000).unwrap()).collect::<Vec<_>>())
+    }).collect();
+
+    let mut all = HashSet::new();
+    for thread in threads {
+        let nonces = thread.join().unwrap();
+        assert!(nonces.windows(2).all(|pair| pair[0] < pair[1]));
+        all.extend(nonces);
+    }
+    assert_eq!(all.len(), 8000);
+}
+
+#[test]
+fn monotonic_nonce_file_is_shared() {
+    let path = std::env::temp_dir().join(format!("crypto-botters-nonce-test-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    // two instances behave like two processes sharing the file
+    let first = MonotonicNonce::with_file(&path);
+    let second = MonotonicNonce::with_file(&path);
+    assert_eq!(first.next("key", 1000).unwrap(), 1000);
+    assert_eq!(second.next("key", 1000).unwrap(), 1001);
+    assert_eq!(first.next("key", 1000).unwrap(), 1002);
+    assert_eq!(second.next("other key", 1000).unwrap(), 1000);
+
+    // a restarted process continues from the high-water mark
+    let restarted = MonotonicNonce::with_file(&path);
+    assert_eq!(restarted.next("key", 500).unwrap(), 1003);
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    assert!(!contents.contains("key "), "API keys must not be written to the file");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn monotonic_nonce_file_is_unique_across_threads() {
+    let path = std::env::temp_dir().join(format!("crypto-botters-nonce-thread-test-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let first = MonotonicNonce::with_file(&path);
+    let second = MonotonicNonce::with_file(&path);
+    let threads: Vec<_> = (0..8).map(|i| {
+        let nonce = if i % 2 == 0 { first.clone() } else { second.clone() };
+        thread::spawn(move || (0..50).map(|_| nonce.next("key", 1000).unwrap()).collect::<Vec<_>>())
+    }).collect();
+
+    let mut all = HashSet::new();
+    for thread in threads {
+        let nonces = thread.join().unwrap();
+        assert!(nonces.windows(2).all(|pair| pair[0] < pair[1]));
+        all.extend(nonces);
+    }
+    assert_eq!(all.len(), 400);
+    assert!(second.next("key", 1000).unwrap() > *all.iter().max().unwrap());
+    std::fs::remove_file(&path).unwrap();
+}
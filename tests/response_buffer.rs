@@ -0,0 +1,86 @@
+mod common;
+
+use std::{borrow::Cow, collections::HashMap};
+use serde::Deserialize;
+use crypto_botters::{
+    binance::BinanceOption,
+    bybit::{BybitHandlerError, BybitOption},
+    generic_api_client::http::{Bytes, HeaderMap},
+    response::{ResponseBody, ResponseBuffer},
+};
+
+#[derive(Deserialize)]
+struct Depth<'a> {
+    #[serde(rename = "lastUpdateId")]
+    last_update_id: u64,
+    #[serde(borrow)]
+    bids: Vec<[&'a str; 2]>,
+    #[serde(borrow)]
+    asks: Vec<[&'a str; 2]>,
+}
+
+#[test]
+fn borrowed_depth() {
+    let body = r#"{"lastUpdateId":1027024,"bids":[["4.00000000","431.00000000"]],"asks":[["4.00000200","12.00000000"]]}"#;
+    let buffer = common::handle::<_, ResponseBuffer>([BinanceOption::Default], 200, HeaderMap::new(), body).unwrap();
+    assert_eq!(buffer.bytes().as_ref(), body.as_bytes());
+    // the buffer is not copied
+    assert_eq!(buffer.bytes().as_ptr(), body.as_ptr());
+
+    let depth: Depth = buffer.deserialize().unwrap();
+    assert_eq!(depth.last_update_id, 1027024);
+    assert_eq!(depth.bids, [["4.00000000", "431.00000000"]]);
+    assert_eq!(depth.asks, [["4.00000200", "12.00000000"]]);
+    let price = depth.bids[0][0];
+    assert!(body.as_bytes().as_ptr_range().contains(&price.as_ptr()));
+}
+
+#[test]
+fn escaped_strings_need_cow() {
+    let buffer = common::handle::<_, ResponseBuffer>([BinanceOption::Default], 200, HeaderMap::new(), r#"{"msg":"a\"b"}"#).unwrap();
+
+    #[derive(Deserialize)]
+    struct Borrowed<'a> {
+        #[allow(dead_code)]
+        msg: &'a str,
+    }
+    assert!(buffer.deserialize::<Borrowed>().is_err());
+
+    #[derive(Deserialize)]
+    struct Message<'a> {
+        #[serde(borrow)]
+        msg: Cow<'a, str>,
+    }
+    assert_eq!(buffer.deserialize::<Message>().unwrap().msg, "a\"b");
+}
+
+#[test]
+fn bybit_unwrap_result() {
+    let body = r#"{"retCode":0,"retMsg":"OK","result":{"s":"BTCUSDT","b":[["65485.47","47.081829"]]},"time":1}"#;
+    let buffer = common::handle::<_, ResponseBuffer>([BybitOption::UnwrapResult(true)], 200, HeaderMap::new(), body).unwrap();
+    assert_eq!(buffer.bytes().as_ref(), br#"{"s":"BTCUSDT","b":[["65485.47","47.081829"]]}"#);
+
+    #[derive(Deserialize)]
+    struct OrderBook<'a> {
+        s: &'a str,
+        b: Vec<[&'a str; 2]>,
+    }
+    let order_book: OrderBook = buffer.deserialize().unwrap();
+    assert_eq!(order_book.s, "BTCUSDT");
+    assert_eq!(order_book.b, [["65485.47", "47.081829"]]);
+
+    // errors are detected before the body is kept
+    let error = common::handle::<_, ResponseBuffer>([BybitOption::Default], 200, HeaderMap::new(), r#"{"retCode":10001,"retMsg":"params error"}"#).unwrap_err();
+    assert!(matches!(error, BybitHandlerError::RetCode { ret_code: 10001, .. }), "{:?}", error);
+}
+
+#[test]
+fn owned_body_is_returned_on_error() {
+    let body = Bytes::from(br#"{"symbol":"BTCUSDT","price":"4.00000000"}"#.to_vec());
+    let price = <HashMap<String, String>>::from_body(body).unwrap();
+    assert_eq!(price["price"], "4.00000000");
+
+    let body = Bytes::from(br#"{"symbol":"BTCUSDT"}"#.to_vec());
+    let (_, returned) = <Vec<u64>>::from_body(body).unwrap_err();
+    assert_eq!(returned.as_ref(), br#"{"symbol":"BTCUSDT"}"#);
+}
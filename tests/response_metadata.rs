@@ -0,0 +1,39 @@
+mod common;
+
+use std::time::{Duration, SystemTime};
+use crypto_botters::{
+    bybit::{BybitHttpUrl, BybitOption},
+    generic_api_client::http::{Method, RequestConfig, StatusCode},
+    Client,
+};
+use common::StubServer;
+
+#[tokio::test]
+async fn metadata_of_last_attempt() {
+    let server = StubServer::start([
+        "HTTP/1.1 429 Too Many Requests\r\nContent-Length: 2\r\n\r\n{}".to_owned(),
+        "HTTP/1.1 200 OK\r\nTraceid: abc123\r\nX-Bapi-Limit-Status: 49\r\nContent-Length: 55\r\n\r\n{\"retCode\":0,\"retMsg\":\"OK\",\"result\":{\"timeSecond\":\"1\"}}".to_owned(),
+    ]);
+    let mut config = RequestConfig::default();
+    config.url_prefix = server.url;
+    config.max_try = 2;
+    config.retry_cooldown = Duration::ZERO;
+
+    let mut client = Client::new();
+    client.update_default_option(BybitOption::HttpUrl(BybitHttpUrl::None));
+    client.update_default_option(BybitOption::RequestConfig(config));
+
+    let started = SystemTime::now();
+    let response = client.request_with_metadata::<serde_json::Value, _, (), ()>(
+        Method::GET, "/v5/market/time", None, None, [BybitOption::UnwrapResult(true)],
+    ).await.unwrap();
+    assert_eq!(response.body["timeSecond"], "1");
+
+    let metadata = response.metadata;
+    assert_eq!(metadata.status, StatusCode::OK);
+    assert_eq!(metadata.attempt_count, 2);
+    assert_eq!(metadata.header("Traceid"), Some("abc123"));
+    assert_eq!(metadata.header("X-Bapi-Limit-Status"), Some("49"));
+    assert!(metadata.sent_at >= started);
+    assert_eq!(metadata.received_at, metadata.sent_at + metadata.latency);
+}
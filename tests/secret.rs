@@ -0,0 +1,32 @@
+use crypto_botters::{
+    binance::BinanceOption,
+    bitflyer::BitFlyerOption,
+    bybit::BybitOption,
+    coincheck::CoincheckOption,
+    secret::Secret,
+    Client,
+};
+
+const SECRET: &str = "this-is-a-very-secret-value";
+
+#[test]
+fn secret_is_redacted() {
+    let secret = Secret::new(SECRET);
+    assert!(!format!("{:?}", secret).contains(SECRET));
+    assert!(!format!("{}", secret).contains(SECRET));
+}
+
+#[test]
+fn client_debug_does_not_leak_secrets() {
+    let mut client = Client::new();
+    client.update_default_option(BinanceOption::Secret(SECRET.to_owned()));
+    client.update_default_option(BitFlyerOption::Secret(SECRET.to_owned()));
+    client.update_default_option(BybitOption::Secret(SECRET.to_owned()));
+    client.update_default_option(CoincheckOption::Secret(SECRET.to_owned()));
+
+    let debug = format!("{:?}", client);
+    assert!(debug.contains("REDACTED"));
+    assert!(!debug.contains(SECRET));
+    let debug = format!("{:#?}", client);
+    assert!(!debug.contains(SECRET));
+}
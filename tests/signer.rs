@@ -0,0 +1,199 @@
+#![cfg(unix)]
+
+use std::{
+    collections::HashMap,
+    io,
+    os::unix::net::UnixListener,
+    sync::{Arc, atomic::{AtomicUsize, Ordering}},
+    time::{Duration, Instant},
+};
+use crypto_botters::{
+    bybit::BybitOption,
+    clock::{FixedClock, ServerClock},
+    coincheck::CoincheckOption,
+    error::BuildError,
+    generic_api_client::{http::RequestHandler, websocket::{WebSocketHandler, WebSocketMessage}},
+    nonce::MonotonicNonce,
+    signer::{serve_unix_socket, HmacSigner, Signer, UnixSocketSigner},
+    traits::*,
+};
+
+/// Starts a signing daemon with a key named `"trading"` on a new socket, and returns the path of the socket.
+fn start_daemon(name: &str) -> std::path::PathBuf {
+    start_daemon_with(name, Arc::new(HmacSigner::new("ccsecret")))
+}
+
+fn start_daemon_with(name: &str, signer: Arc<dyn Signer>) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("crypto-botters-{name}-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+
+    let mut signers: HashMap<String, Arc<dyn Signer>> = HashMap::new();
+    signers.insert("trading".to_owned(), signer);
+    std::thread::spawn(move || serve_unix_socket(listener, signers));
+    path
+}
+
+/// A [Signer] which takes [SLOW_SIGNING] to sign each message.
+#[derive(Debug)]
+struct SlowSigner(HmacSigner);
+
+const SLOW_SIGNING: Duration = Duration::from_millis(200);
+
+impl Signer for SlowSigner {
+    fn sign(&self, message: &[u8]) -> io::Result<Vec<u8>> {
+        std::thread::sleep(SLOW_SIGNING);
+        self.0.sign(message)
+    }
+}
+
+fn coincheck_handler(path: &std::path::Path) -> impl RequestHandler<(), BuildError = BuildError> {
+    let mut options = <CoincheckOption as HandlerOption>::Options::default();
+    for option in [
+        CoincheckOption::Key("cckey".to_owned()),
+        CoincheckOption::Signer(Arc::new(UnixSocketSigner::new(path, "trading"))),
+        CoincheckOption::ServerClock(ServerClock::with_clock(FixedClock::from_millis(1700000000123))),
+        CoincheckOption::Nonce(MonotonicNonce::new()),
+        CoincheckOption::HttpAuth(true),
+    ] {
+        options.update(option);
+    }
+    <CoincheckOption as HttpOption<serde_json::Value, ()>>::request_handler(options)
+}
+
+#[test]
+fn unix_socket_signer_matches_hmac() {
+    let path = start_daemon("signer-matches");
+    let remote = UnixSocketSigner::new(&path, "trading");
+    let local = HmacSigner::new("ccsecret");
+
+    for message in [&b"message"[..], b"", &[0xff; 100_000]] {
+        assert_eq!(remote.sign(message).unwrap(), local.sign(message).unwrap());
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn unix_socket_signer_unknown_key() {
+    let path = start_daemon("signer-unknown");
+    let remote = UnixSocketSigner::new(&path, "unknown");
+
+    let error = remote.sign(b"message").unwrap_err();
+    assert!(error.to_string().contains("unknown key"), "{}", error);
+    // the connection is still usable after an error
+    let error = remote.sign(b"message").unwrap_err();
+    assert!(error.to_string().contains("unknown key"), "{}", error);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn request_signed_by_daemon() {
+    let path = start_daemon("signer-request");
+
+    let handler = coincheck_handler(&path);
+    let builder = reqwest::Client::new().get("https://coincheck.com/api/accounts/balance");
+    let request = handler.build_request(builder, &None::<()>, 1).ok().unwrap();
+
+    // same as coincheck_get() in signing.rs, which uses the secret directly
+    let local = HmacSigner::new("ccsecret").sign(b"1700000000123https://coincheck.com/api/accounts/balance").unwrap();
+    assert_eq!(request.headers()["ACCESS-SIGNATURE"], hex::encode(local));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn unix_socket_signer_async_matches_hmac() {
+    let path = start_daemon("signer-async");
+    let remote = UnixSocketSigner::new(&path, "trading");
+    let local = HmacSigner::new("ccsecret");
+
+    for message in [&b"message"[..], b"", &[0xff; 100_000]] {
+        assert_eq!(remote.sign_async(message).await.unwrap(), local.sign(message).unwrap());
+    }
+    // the blocking and the non-blocking connections can be used together
+    assert_eq!(remote.sign(b"message").unwrap(), local.sign(b"message").unwrap());
+
+    let remote = UnixSocketSigner::new(&path, "unknown");
+    let error = remote.sign_async(b"message").await.unwrap_err();
+    assert!(error.to_string().contains("unknown key"), "{}", error);
+    std::fs::remove_file(&path).unwrap();
+}
+
+// the current-thread runtime would stop if signing blocked the thread
+#[tokio::test]
+async fn concurrent_requests_with_slow_daemon() {
+    let path = start_daemon_with("signer-slow", Arc::new(SlowSigner(HmacSigner::new("ccsecret"))));
+    let handler = coincheck_handler(&path);
+
+    let ticks = Arc::new(AtomicUsize::new(0));
+    let ticker = tokio::spawn({
+        let ticks = Arc::clone(&ticks);
+        async move {
+            loop {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                ticks.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    });
+
+    let started = Instant::now();
+    let requests = (0..8).map(|_| {
+        let builder = reqwest::Client::new().get("https://coincheck.com/api/accounts/balance");
+        handler.build_request_async(builder, &None, 1)
+    });
+    let requests = futures_util::future::join_all(requests).await;
+    let elapsed = started.elapsed();
+    ticker.abort();
+
+    let mut nonces = Vec::new();
+    for request in requests {
+        let request = request.unwrap();
+        nonces.push(request.headers()["ACCESS-NONCE"].to_str().unwrap().to_owned());
+        assert!(request.headers().contains_key("ACCESS-SIGNATURE"));
+    }
+    nonces.sort();
+    nonces.dedup();
+    assert_eq!(nonces.len(), 8);
+    // the requests are signed in parallel on separate connections
+    assert!(elapsed < SLOW_SIGNING * 4, "{:?}", elapsed);
+    // and the other tasks keep running while waiting for the daemon
+    assert!(ticks.load(Ordering::Relaxed) >= 5, "{}", ticks.load(Ordering::Relaxed));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn websocket_auth_with_slow_daemon() {
+    let path = start_daemon_with("signer-websocket", Arc::new(SlowSigner(HmacSigner::new("bysecret"))));
+    let mut options = <BybitOption as HandlerOption>::Options::default();
+    for option in [
+        BybitOption::Key("bykey".to_owned()),
+        BybitOption::Signer(Arc::new(UnixSocketSigner::new(&path, "trading"))),
+        BybitOption::ServerClock(ServerClock::with_clock(FixedClock::from_millis(1700000000123))),
+        BybitOption::WebSocketAuth(true),
+    ] {
+        options.update(option);
+    }
+    let mut handler = BybitOption::websocket_handler(|_: serde_json::Value| {}, options);
+
+    let ticks = Arc::new(AtomicUsize::new(0));
+    let ticker = tokio::spawn({
+        let ticks = Arc::clone(&ticks);
+        async move {
+            loop {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                ticks.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    });
+    let messages = handler.handle_start_async().await;
+    ticker.abort();
+
+    let [WebSocketMessage::Text(message)] = messages.as_slice() else {
+        panic!("unexpected messages: {:?}", messages);
+    };
+    let message: serde_json::Value = serde_json::from_str(message).unwrap();
+    let signature = hex::encode(HmacSigner::new("bysecret").sign(b"GET/realtime1700000001123").unwrap());
+    assert_eq!(message, serde_json::json!({ "op": "auth", "args": ["bykey", 1700000001123u64, signature] }));
+    // the other tasks keep running while waiting for the daemon
+    assert!(ticks.load(Ordering::Relaxed) >= 5, "{}", ticks.load(Ordering::Relaxed));
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,315 @@
+//! Golden tests for request signing.
+//!
+//! The clock and the nonce source are fixed, so the signed requests are deterministic.
+//! The Binance vectors are the examples in the official documentation. The other exchanges don't publish signatures,
+//! so their expected signatures are computed here from the pre-hash strings described in each exchange's documentation.
+
+use std::sync::Arc;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use serde_json::json;
+use crypto_botters::{
+    binance::{BinanceAuth, BinanceOption, BinanceOptions},
+    bitflyer::{BitFlyerOption, BitFlyerOptions},
+    bybit::{BybitHttpAuth, BybitOption, BybitOptions},
+    coincheck::{CoincheckOption, CoincheckOptions},
+    clock::{FixedClock, ServerClock},
+    generic_api_client::{http::{Request, RequestHandler}, websocket::{WebSocketHandler, WebSocketMessage}},
+    nonce::{FixedNonce, MonotonicNonce},
+    traits::*,
+};
+
+fn options<O: HandlerOption>(options: impl IntoIterator<Item=O>) -> O::Options {
+    let mut default = O::Options::default();
+    for option in options {
+        default.update(option);
+    }
+    default
+}
+
+fn build<'a, O, B>(options: O::Options, method: reqwest::Method, url: &str, body: Option<B>) -> Request
+where
+    O: HttpOption<'a, serde_json::Value, B>,
+{
+    let handler = O::request_handler(options);
+    let builder = reqwest::Client::new().request(method, url);
+    handler.build_request(builder, &body, 1).ok().expect("failed to build request")
+}
+
+/// Hex-encoded HMAC-SHA256 of `pre_hash`.
+fn hmac_sha256(secret: &str, pre_hash: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(pre_hash.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+fn header<'r>(request: &'r Request, name: &str) -> &'r str {
+    request.headers()[name].to_str().unwrap()
+}
+
+fn body(request: &Request) -> &str {
+    std::str::from_utf8(request.body().and_then(|body| body.as_bytes()).unwrap_or_default()).unwrap()
+}
+
+const BINANCE_KEY: &str = "vmPUZE6mv9SD5VNHk4HlWFsOr6aKE2zvsw0MuIgwCIPy6utIco14y7Ju91duEh8A";
+const BINANCE_SECRET: &str = "NhqPtmdSJYdKjVHjA7PZj4Mge3R5YNiP1e3UZjInClVN65XAbvqqM6A7H5fATj0j";
+
+fn binance_options(auth: BinanceAuth) -> BinanceOptions {
+    options([
+        BinanceOption::Key(BINANCE_KEY.to_owned()),
+        BinanceOption::Secret(BINANCE_SECRET.to_owned()),
+        BinanceOption::ServerClock(ServerClock::with_clock(FixedClock::from_millis(1499827319559))),
+        BinanceOption::HttpAuth(auth),
+    ])
+}
+
+#[test]
+fn binance_sign_query() {
+    let request = build::<BinanceOption, ()>(
+        binance_options(BinanceAuth::Sign),
+        reqwest::Method::POST,
+        "https://api.binance.com/api/v3/order?symbol=LTCBTC&side=BUY&type=LIMIT&timeInForce=GTC&quantity=1&price=0.1&recvWindow=5000",
+        None,
+    );
+    assert_eq!(
+        request.url().as_str(),
+        "https://api.binance.com/api/v3/order?symbol=LTCBTC&side=BUY&type=LIMIT&timeInForce=GTC&quantity=1&price=0.1&recvWindow=5000\
+        &timestamp=1499827319559&signature=c8db56825ae71d6d79447849e617115f4a920fa2acdcab2b053c4b2838bd6b71",
+    );
+    assert_eq!(header(&request, "X-MBX-APIKEY"), BINANCE_KEY);
+    assert_eq!(body(&request), "");
+}
+
+#[test]
+fn binance_sign_query_and_body() {
+    let request = build::<BinanceOption, _>(
+        binance_options(BinanceAuth::Sign),
+        reqwest::Method::POST,
+        "https://api.binance.com/api/v3/order?quantity=1&price=0.1&recvWindow=5000",
+        Some([("symbol", "LTCBTC"), ("side", "BUY"), ("type", "LIMIT"), ("timeInForce", "GTC")]),
+    );
+    assert_eq!(
+        request.url().as_str(),
+        "https://api.binance.com/api/v3/order?quantity=1&price=0.1&recvWindow=5000\
+        &timestamp=1499827319559&signature=0d07a7457c47d2d2e76b36e1be129e46ae01139496aa6903178d7f0cb7738ee0",
+    );
+    assert_eq!(header(&request, "X-MBX-APIKEY"), BINANCE_KEY);
+    assert_eq!(header(&request, "Content-Type"), "application/x-www-form-urlencoded");
+    assert_eq!(body(&request), "symbol=LTCBTC&side=BUY&type=LIMIT&timeInForce=GTC");
+}
+
+#[test]
+fn binance_key() {
+    let request = build::<BinanceOption, ()>(
+        binance_options(BinanceAuth::Key),
+        reqwest::Method::POST,
+        "https://api.binance.com/api/v3/userDataStream",
+        None,
+    );
+    assert_eq!(request.url().as_str(), "https://api.binance.com/api/v3/userDataStream");
+    assert_eq!(header(&request, "X-MBX-APIKEY"), BINANCE_KEY);
+}
+
+const BYBIT_SECRET: &str = "YYYYYYYYYYYYYYYYYYYY";
+
+fn bybit_options(auth: BybitHttpAuth) -> BybitOptions {
+    options([
+        BybitOption::Key("XXXXXXXXXX".to_owned()),
+        BybitOption::Secret(BYBIT_SECRET.to_owned()),
+        BybitOption::ServerClock(ServerClock::with_clock(FixedClock::from_millis(1658384314791))),
+        BybitOption::HttpAuth(auth),
+        BybitOption::RecvWindow(5000),
+    ])
+}
+
+#[test]
+fn bybit_v3_get() {
+    let request = build::<BybitOption, ()>(
+        bybit_options(BybitHttpAuth::V3AndAbove),
+        reqwest::Method::GET,
+        "https://api.bybit.com/v5/order/realtime?category=option&symbol=BTC-29JUL22-25000-C",
+        None,
+    );
+    assert_eq!(request.url().as_str(), "https://api.bybit.com/v5/order/realtime?category=option&symbol=BTC-29JUL22-25000-C");
+    assert_eq!(header(&request, "X-BAPI-API-KEY"), "XXXXXXXXXX");
+    assert_eq!(header(&request, "X-BAPI-TIMESTAMP"), "1658384314791");
+    assert_eq!(header(&request, "X-BAPI-RECV-WINDOW"), "5000");
+    assert_eq!(header(&request, "X-BAPI-SIGN"), hmac_sha256(BYBIT_SECRET, "1658384314791XXXXXXXXXX5000category=option&symbol=BTC-29JUL22-25000-C"));
+    assert!(request.headers().get("X-BAPI-SIGN-TYPE").is_none());
+}
+
+#[test]
+fn bybit_v3_post() {
+    let request = build::<BybitOption, _>(
+        bybit_options(BybitHttpAuth::V3AndAbove),
+        reqwest::Method::POST,
+        "https://api.bybit.com/v5/order/create",
+        Some(json!({ "category": "spot", "symbol": "BTCUSDT", "side": "Buy", "qty": "1" })),
+    );
+    assert_eq!(header(&request, "X-BAPI-SIGN"), hmac_sha256(
+        BYBIT_SECRET,
+        r#"1658384314791XXXXXXXXXX5000{"category":"spot","qty":"1","side":"Buy","symbol":"BTCUSDT"}"#,
+    ));
+    assert_eq!(header(&request, "Content-Type"), "application/json");
+    assert_eq!(body(&request), r#"{"category":"spot","qty":"1","side":"Buy","symbol":"BTCUSDT"}"#);
+}
+
+#[test]
+fn bybit_usdc_contract_v1() {
+    let mut options = bybit_options(BybitHttpAuth::UsdcContractV1);
+    options.recv_window = None;
+    let request = build::<BybitOption, ()>(options, reqwest::Method::POST, "https://api.bybit.com/option/usdc/openapi/private/v1/query-position", None);
+    assert_eq!(header(&request, "X-BAPI-SIGN"), hmac_sha256(BYBIT_SECRET, "1658384314791XXXXXXXXXX{}"));
+    assert_eq!(header(&request, "X-BAPI-SIGN-TYPE"), "2");
+    assert_eq!(header(&request, "X-BAPI-TIMESTAMP"), "1658384314791");
+    assert!(request.headers().get("X-BAPI-RECV-WINDOW").is_none());
+    assert_eq!(body(&request), "{}");
+}
+
+/// The parameters of [bybit_below_v3_get()], sorted by name as the documentation requires.
+const BELOW_V3_PARAMS: &str = "api_key=XXXXXXXXXX&recv_window=5000&symbol=BTCUSD&timestamp=1658384314791";
+
+#[test]
+fn bybit_below_v3_get() {
+    let request = build::<BybitOption, ()>(
+        bybit_options(BybitHttpAuth::BelowV3),
+        reqwest::Method::GET,
+        "https://api.bybit.com/v2/private/position/list?symbol=BTCUSD",
+        None,
+    );
+    assert_eq!(
+        request.url().as_str(),
+        format!(
+            "https://api.bybit.com/v2/private/position/list?{}&sign={}",
+            BELOW_V3_PARAMS,
+            hmac_sha256(BYBIT_SECRET, BELOW_V3_PARAMS),
+        ),
+    );
+}
+
+#[test]
+fn bybit_below_v3_post() {
+    let request = build::<BybitOption, _>(
+        bybit_options(BybitHttpAuth::BelowV3),
+        reqwest::Method::POST,
+        "https://api.bybit.com/v2/private/order/create",
+        Some([("symbol", "BTCUSD"), ("side", "Buy"), ("qty", "1")]),
+    );
+    let body: serde_json::Value = serde_json::from_str(body(&request)).unwrap();
+    assert_eq!(body, json!({
+        "api_key": "XXXXXXXXXX",
+        "qty": "1",
+        "recv_window": "5000",
+        "side": "Buy",
+        "symbol": "BTCUSD",
+        "timestamp": "1658384314791",
+        "sign": hmac_sha256(BYBIT_SECRET, "api_key=XXXXXXXXXX&qty=1&recv_window=5000&side=Buy&symbol=BTCUSD&timestamp=1658384314791"),
+    }));
+    assert_eq!(header(&request, "Content-Type"), "application/json");
+}
+
+#[test]
+fn bybit_spot_v1_post() {
+    let request = build::<BybitOption, _>(
+        bybit_options(BybitHttpAuth::SpotV1),
+        reqwest::Method::POST,
+        "https://api.bybit.com/spot/v1/order",
+        Some([("symbol", "BTCUSDT"), ("side", "Buy"), ("qty", "1")]),
+    );
+    let params = "api_key=XXXXXXXXXX&qty=1&recvWindow=5000&side=Buy&symbol=BTCUSDT&timestamp=1658384314791";
+    assert_eq!(body(&request), format!("{}&sign={}", params, hmac_sha256(BYBIT_SECRET, params)));
+    assert_eq!(header(&request, "Content-Type"), "application/x-www-form-urlencoded");
+}
+
+fn bitflyer_options() -> BitFlyerOptions {
+    options([
+        BitFlyerOption::Key("bfkey".to_owned()),
+        BitFlyerOption::Secret("bfsecret".to_owned()),
+        BitFlyerOption::ServerClock(ServerClock::with_clock(FixedClock::from_millis(1700000000123))),
+        BitFlyerOption::NonceSource(Arc::new(FixedNonce("abcdefghijklmnop".to_owned()))),
+        BitFlyerOption::HttpAuth(true),
+        BitFlyerOption::WebSocketAuth(true),
+    ])
+}
+
+#[test]
+fn bitflyer_get() {
+    let request = build::<BitFlyerOption, ()>(
+        bitflyer_options(),
+        reqwest::Method::GET,
+        "https://api.bitflyer.com/v1/me/getchildorders?product_code=BTC_JPY",
+        None,
+    );
+    assert_eq!(header(&request, "ACCESS-KEY"), "bfkey");
+    assert_eq!(header(&request, "ACCESS-TIMESTAMP"), "1700000000123");
+    assert_eq!(header(&request, "ACCESS-SIGN"), hmac_sha256("bfsecret", "1700000000123GET/v1/me/getchildorders?product_code=BTC_JPY"));
+}
+
+#[test]
+fn bitflyer_post() {
+    let request = build::<BitFlyerOption, _>(
+        bitflyer_options(),
+        reqwest::Method::POST,
+        "https://api.bitflyer.com/v1/me/sendchildorder",
+        Some(json!({ "product_code": "BTC_JPY", "side": "BUY", "size": 0.01 })),
+    );
+    assert_eq!(header(&request, "ACCESS-SIGN"), hmac_sha256(
+        "bfsecret",
+        r#"1700000000123POST/v1/me/sendchildorder{"product_code":"BTC_JPY","side":"BUY","size":0.01}"#,
+    ));
+    assert_eq!(header(&request, "Content-Type"), "application/json");
+    assert_eq!(body(&request), r#"{"product_code":"BTC_JPY","side":"BUY","size":0.01}"#);
+}
+
+#[test]
+fn bitflyer_websocket_auth() {
+    let mut handler = BitFlyerOption::websocket_handler(|_| {}, bitflyer_options());
+    let messages = handler.handle_start();
+    let [WebSocketMessage::Text(message)] = messages.as_slice() else {
+        panic!("unexpected messages: {:?}", messages);
+    };
+    let message: serde_json::Value = serde_json::from_str(message).unwrap();
+    assert_eq!(message, json!({
+        "method": "auth",
+        "params": {
+            "api_key": "bfkey",
+            "timestamp": 1700000000123u64,
+            "nonce": "abcdefghijklmnop",
+            "signature": hmac_sha256("bfsecret", "1700000000123abcdefghijklmnop"),
+        },
+        "id": "_auth1700000000123000000",
+    }));
+}
+
+fn coincheck_options() -> CoincheckOptions {
+    options([
+        CoincheckOption::Key("cckey".to_owned()),
+        CoincheckOption::Secret("ccsecret".to_owned()),
+        CoincheckOption::ServerClock(ServerClock::with_clock(FixedClock::from_millis(1700000000123))),
+        CoincheckOption::Nonce(MonotonicNonce::new()),
+        CoincheckOption::HttpAuth(true),
+    ])
+}
+
+#[test]
+fn coincheck_get() {
+    let request = build::<CoincheckOption, ()>(coincheck_options(), reqwest::Method::GET, "https://coincheck.com/api/accounts/balance", None);
+    assert_eq!(header(&request, "ACCESS-KEY"), "cckey");
+    assert_eq!(header(&request, "ACCESS-NONCE"), "1700000000123");
+    assert_eq!(header(&request, "ACCESS-SIGNATURE"), hmac_sha256("ccsecret", "1700000000123https://coincheck.com/api/accounts/balance"));
+}
+
+#[test]
+fn coincheck_post() {
+    let request = build::<CoincheckOption, _>(
+        coincheck_options(),
+        reqwest::Method::POST,
+        "https://coincheck.com/api/exchange/orders",
+        Some([("pair", "btc_jpy"), ("order_type", "buy"), ("rate", "3000000"), ("amount", "0.01")]),
+    );
+    assert_eq!(header(&request, "ACCESS-SIGNATURE"), hmac_sha256(
+        "ccsecret",
+        "1700000000123https://coincheck.com/api/exchange/orderspair=btc_jpy&order_type=buy&rate=3000000&amount=0.01",
+    ));
+    assert_eq!(body(&request), "pair=btc_jpy&order_type=buy&rate=3000000&amount=0.01");
+}
@@ -0,0 +1,65 @@
+use crypto_botters::{
+    binance::BinanceOption,
+    bitflyer::BitFlyerOption,
+    bybit::BybitOption,
+    coincheck::CoincheckOption,
+    generic_api_client::websocket::{DedupKey, WebSocketHandler, WebSocketMessage},
+    traits::*,
+};
+
+fn dedup_key<O, M>(message: &str) -> Option<DedupKey>
+where
+    O: WebSocketOption<fn(M)>,
+{
+    let message_handler: fn(M) = |_| ();
+    let handler = O::websocket_handler(message_handler, O::Options::default());
+    handler.dedup_key(&WebSocketMessage::Text(message.to_owned()))
+}
+
+#[test]
+fn binance() {
+    let key = |message| dedup_key::<BinanceOption, serde_json::Value>(message);
+    let depth = key(r#"{"stream":"bnbbtc@depth","data":{"e":"depthUpdate","E":1672515782136,"s":"BNBBTC","U":157,"u":160,"b":[["0.0024","10"]],"a":[]}}"#);
+    assert!(depth.is_some());
+    // only the identifying fields are compared
+    assert_eq!(depth, key(r#"{"stream": "bnbbtc@depth", "data": {"e": "depthUpdate", "E": 1672515782136, "s": "BNBBTC", "U": 157, "u": 160, "b": [], "a": []}}"#));
+    assert_ne!(depth, key(r#"{"stream":"bnbbtc@depth","data":{"e":"depthUpdate","E":1672515782136,"s":"BNBBTC","U":161,"u":162,"b":[],"a":[]}}"#));
+    assert_ne!(depth, key(r#"{"stream":"bnbbtc@depth@100ms","data":{"e":"depthUpdate","E":1672515782136,"s":"BNBBTC","U":157,"u":160,"b":[],"a":[]}}"#));
+    // raw streams
+    assert!(key(r#"{"u":400900217,"s":"BNBUSDT","b":"25.35190000","B":"31.21000000","a":"25.36520000","A":"40.66000000"}"#).is_some());
+    // trades don't have an update id
+    assert_eq!(key(r#"{"e":"trade","E":1672515782136,"s":"BNBBTC","t":12345,"p":"0.001","q":"100","T":1672515782136,"m":true,"M":true}"#), None);
+}
+
+#[test]
+fn bybit() {
+    let key = |message| dedup_key::<BybitOption, serde_json::Value>(message);
+    let orderbook = key(r#"{"topic":"orderbook.50.BTCUSDT","type":"delta","ts":1687940967466,"data":{"s":"BTCUSDT","b":[],"a":[["30247.20","30.028"]],"u":177400507,"seq":66544703342},"cts":1687940967464}"#);
+    assert!(orderbook.is_some());
+    assert_eq!(orderbook, key(r#"{"topic":"orderbook.50.BTCUSDT","type":"delta","ts":1687940967467,"data":{"s":"BTCUSDT","b":[],"a":[],"u":177400507,"seq":66544703342},"cts":1687940967464}"#));
+    assert_ne!(orderbook, key(r#"{"topic":"orderbook.50.BTCUSDT","type":"delta","ts":1687940967466,"data":{"s":"BTCUSDT","b":[],"a":[],"u":177400508,"seq":66544703343},"cts":1687940967464}"#));
+    assert_ne!(orderbook, key(r#"{"topic":"orderbook.50.ETHUSDT","type":"delta","ts":1687940967466,"data":{"s":"ETHUSDT","b":[],"a":[],"u":177400507,"seq":66544703342},"cts":1687940967464}"#));
+    assert_eq!(key(r#"{"topic":"publicTrade.BTCUSDT","type":"snapshot","ts":1672304486868,"data":[{"T":1672304486865,"s":"BTCUSDT","S":"Buy","v":"0.001","p":"16578.50","i":"20f43950-d8dd-5b31-9112-a178eb6023af","BT":false}]}"#), None);
+    assert_eq!(key(r#"{"success":true,"ret_msg":"","conn_id":"a","op":"subscribe"}"#), None);
+}
+
+#[test]
+fn bitflyer() {
+    let key = |message| dedup_key::<BitFlyerOption, crypto_botters::bitflyer::BitFlyerChannelMessage>(message);
+    let executions = key(r#"{"jsonrpc":"2.0","method":"channelMessage","params":{"channel":"lightning_executions_BTC_JPY","message":[{"id":39361,"side":"SELL","price":35100,"size":0.01,"exec_date":"2015-07-07T10:44:33.547Z","buy_child_order_acceptance_id":"JRF20150707-014356-184990","sell_child_order_acceptance_id":"JRF20150707-104433-186048"}]}}"#);
+    assert!(executions.is_some());
+    assert_eq!(executions, key(r#"{"jsonrpc":"2.0","method":"channelMessage","params":{"channel":"lightning_executions_BTC_JPY","message":[{"id":39361}]}}"#));
+    assert_ne!(executions, key(r#"{"jsonrpc":"2.0","method":"channelMessage","params":{"channel":"lightning_executions_BTC_JPY","message":[{"id":39361},{"id":39362}]}}"#));
+    assert_ne!(executions, key(r#"{"jsonrpc":"2.0","method":"channelMessage","params":{"channel":"lightning_executions_FX_BTC_JPY","message":[{"id":39361}]}}"#));
+    assert_eq!(key(r#"{"jsonrpc":"2.0","method":"channelMessage","params":{"channel":"lightning_ticker_BTC_JPY","message":{"product_code":"BTC_JPY","tick_id":3579}}}"#), None);
+}
+
+#[test]
+fn coincheck() {
+    let key = |message| dedup_key::<CoincheckOption, serde_json::Value>(message);
+    let trades = key(r#"[["1663318663","2357062","btc_jpy","2820896.0","5.0","sell","1193401","2078767"]]"#);
+    assert!(trades.is_some());
+    assert_eq!(trades, key(r#"[["1663318664","2357062","btc_jpy","2820896.0","5.0","sell","1193401","2078767"]]"#));
+    assert_ne!(trades, key(r#"[["1663318663","2357063","btc_jpy","2820896.0","5.0","sell","1193401","2078767"]]"#));
+    assert_eq!(key(r#"["btc_jpy",{"bids":[["148634.0","0"]],"asks":[],"last_update_at":"1659321701"}]"#), None);
+}
@@ -0,0 +1,125 @@
+use std::{
+    sync::{Arc, atomic::{AtomicUsize, Ordering}},
+    time::Duration,
+};
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::Message;
+use crypto_botters::{
+    binance::{BinanceOption, BinanceWebSocketUrl},
+    stream::{MessageStream, OverflowPolicy, StreamConfig},
+    Client,
+};
+
+/// Starts a WebSocket server which sends `{"i":0}` to `{"i":<count - 1>}` on each connection,
+/// and returns its url and the number of connections accepted so far.
+async fn start_server(count: usize) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let connections = Arc::new(AtomicUsize::new(0));
+    let accepted = Arc::clone(&connections);
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            accepted.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(async move {
+                let mut websocket = tokio_tungstenite::accept_async(stream).await.unwrap();
+                for i in 0..count {
+                    websocket.send(Message::Text(format!(r#"{{"i":{i}}}"#))).await.unwrap();
+                }
+                // keep the connection open until the client closes it
+                while let Some(Ok(_)) = websocket.next().await {}
+            });
+        }
+    });
+    (url, connections)
+}
+
+async fn wait_until(mut condition: impl FnMut() -> bool) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !condition() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }).await.expect("timed out");
+}
+
+async fn collect(stream: &mut MessageStream<serde_json::Value>, count: usize) -> Vec<u64> {
+    let mut received = vec![];
+    for _ in 0..count {
+        let message = tokio::time::timeout(Duration::from_secs(5), stream.next()).await.unwrap().unwrap();
+        received.push(message["i"].as_u64().unwrap());
+    }
+    received
+}
+
+// the connection waits asynchronously, so this doesn't need the multi-threaded runtime
+#[tokio::test]
+async fn block() {
+    let (url, _) = start_server(10).await;
+    let client = Client::new();
+    let (connection, mut stream) = client.websocket_stream(
+        &url,
+        StreamConfig::new(2, OverflowPolicy::Block),
+        [BinanceOption::WebSocketUrl(BinanceWebSocketUrl::None)],
+    ).await.unwrap();
+
+    wait_until(|| stream.len() == 2).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(stream.len(), 2);
+    assert_eq!(collect(&mut stream, 10).await, (0..10).collect::<Vec<_>>());
+    assert_eq!(stream.dropped(), 0);
+
+    // the stream ends when the connection is dropped
+    drop(connection);
+    let end = tokio::time::timeout(Duration::from_secs(5), stream.next()).await.unwrap();
+    assert!(end.is_none());
+}
+
+#[tokio::test]
+async fn drop_newest() {
+    let (url, _) = start_server(5).await;
+    let client = Client::new();
+    let (_connection, mut stream) = client.websocket_stream(
+        &url,
+        StreamConfig::new(2, OverflowPolicy::DropNewest),
+        [BinanceOption::WebSocketUrl(BinanceWebSocketUrl::None)],
+    ).await.unwrap();
+
+    wait_until(|| stream.dropped() == 3).await;
+    assert_eq!(collect(&mut stream, 2).await, [0, 1]);
+}
+
+#[tokio::test]
+async fn drop_oldest() {
+    let (url, _) = start_server(5).await;
+    let client = Client::new();
+    let (_connection, mut stream) = client.websocket_stream(
+        &url,
+        StreamConfig::new(2, OverflowPolicy::DropOldest),
+        [BinanceOption::WebSocketUrl(BinanceWebSocketUrl::None)],
+    ).await.unwrap();
+
+    wait_until(|| stream.dropped() == 3).await;
+    assert_eq!(collect(&mut stream, 2).await, [3, 4]);
+}
+
+#[tokio::test]
+async fn reconnect() {
+    let (url, connections) = start_server(3).await;
+    let client = Client::new();
+    let (_connection, stream) = client.websocket_stream(
+        &url,
+        StreamConfig::new(2, OverflowPolicy::Reconnect),
+        [BinanceOption::WebSocketUrl(BinanceWebSocketUrl::None)],
+    ).await.unwrap();
+
+    // the third message overflows, which discards all three and reconnects
+    wait_until(|| connections.load(Ordering::SeqCst) == 2).await;
+    assert!(stream.dropped() >= 3);
+}
+
+#[test]
+fn default_config() {
+    let config = StreamConfig::default();
+    assert_eq!(config.buffer, 1024);
+    assert_eq!(config.overflow, OverflowPolicy::DropOldest);
+}